@@ -0,0 +1,164 @@
+use std::path::PathBuf;
+use crate::{logging::LogLevel, config::{LoggingConfig, LogColor, format::ConfigFormat}};
+
+pub static USAGE: &str = "Usage: quickserving [command] [options]
+
+Commands:
+  serve                  serve the routes from the config file (default)
+  schema                 print the JSON Schema of the config file
+  record                 forward every request to the upstream and record
+                         the traffic as a config serving it
+  import <file>          turn an OpenAPI 3 document or a HAR file into a
+                         config serving its responses
+
+Options:
+  -c, --config <path>    path of the config file, defaults to the first of
+                         quickserving.json, .yaml, .yml or .toml found
+  -f, --format <format>  format of the config file (json, yaml or toml),
+                         defaults to the one matching the file's extension
+  --log-level <level>    minimum level of logged messages (debug, info, warn
+                         or error), overrides the config
+  --log-output <output>  where to log to (stdout, stderr or a file path),
+                         overrides the config
+  --log-format <format>  template of log lines, overrides the config
+  --log-color <when>     color log levels (auto, always or never),
+                         overrides the config
+  -h, --help             print this message
+
+Record options:
+  --upstream <url>       base URL of the server to forward requests to
+  -p, --port <port>      port to listen on, 8080 by default
+  -o, --output <path>    path of the recorded config, recording.json by
+                         default
+  --bodies <dir>         save response bodies as files in the directory and
+                         record them as file responses
+  --har <path>           also write the traffic to the file as HAR
+
+Import options:
+  -p, --port <port>      port of the imported config, 8080 by default
+  -o, --output <path>    path of the imported config, printed when not set";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Serve,
+    Schema,
+    Record,
+    Import
+}
+
+impl TryFrom<String> for Command {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        return match s.as_str() {
+            "serve" => Ok(Self::Serve),
+            "schema" => Ok(Self::Schema),
+            "record" => Ok(Self::Record),
+            "import" => Ok(Self::Import),
+            _ => Err(format!("Unknown command \"{}\".", s))
+        };
+    }
+}
+
+pub struct Args {
+    pub command: Command,
+    pub config_path: Option<PathBuf>,
+    pub format: Option<ConfigFormat>,
+    // logging settings given as flags, they take precedence over the config
+    pub logging: LoggingConfig,
+    pub help: bool,
+    // settings of the record command
+    pub upstream: Option<String>,
+    pub port: Option<u16>,
+    pub output: Option<PathBuf>,
+    pub bodies: Option<PathBuf>,
+    pub har: Option<PathBuf>,
+    // the file of the import command
+    pub input: Option<PathBuf>
+}
+
+impl Args {
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self {
+            command: Command::Serve,
+            config_path: None,
+            format: None,
+            logging: LoggingConfig::default(),
+            help: false,
+            upstream: None,
+            port: None,
+            output: None,
+            bodies: None,
+            har: None,
+            input: None
+        };
+        let mut args = args.peekable();
+
+        // the command is the first argument, when it's not an option
+        if let Some(command) = args.next_if(|arg| !arg.starts_with('-')) {
+            parsed.command = Command::try_from(command)?;
+        }
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" | "--config" => {
+                    parsed.config_path = Some(PathBuf::from(expect_value(&arg, args.next())?));
+                },
+                "-f" | "--format" => {
+                    parsed.format = Some(ConfigFormat::try_from(expect_value(&arg, args.next())?)?);
+                },
+                "--log-level" => {
+                    parsed.logging.level = Some(LogLevel::try_from(expect_value(&arg, args.next())?)?);
+                },
+                "--log-output" => {
+                    parsed.logging.output = Some(expect_value(&arg, args.next())?);
+                },
+                "--log-format" => {
+                    parsed.logging.format = Some(expect_value(&arg, args.next())?);
+                },
+                "--log-color" => {
+                    parsed.logging.color = Some(match expect_value(&arg, args.next())?.as_str() {
+                        "auto" => LogColor::Auto,
+                        "always" => LogColor::Always,
+                        "never" => LogColor::Never,
+                        other => return Err(format!("Unknown color setting \"{}\", expected one of auto, always or never.", other))
+                    });
+                },
+                "--upstream" => {
+                    parsed.upstream = Some(expect_value(&arg, args.next())?);
+                },
+                "-p" | "--port" => {
+                    let port = expect_value(&arg, args.next())?;
+
+                    parsed.port = Some(port.parse::<u16>().map_err(|_| format!("Invalid port \"{}\".", port))?);
+                },
+                "-o" | "--output" => {
+                    parsed.output = Some(PathBuf::from(expect_value(&arg, args.next())?));
+                },
+                "--bodies" => {
+                    parsed.bodies = Some(PathBuf::from(expect_value(&arg, args.next())?));
+                },
+                "--har" => {
+                    parsed.har = Some(PathBuf::from(expect_value(&arg, args.next())?));
+                },
+                "-h" | "--help" => {
+                    parsed.help = true;
+                },
+                // the import command takes the file to import as its only positional argument
+                _ if parsed.command == Command::Import && parsed.input.is_none() && !arg.starts_with('-') => {
+                    parsed.input = Some(PathBuf::from(arg));
+                },
+                _ => return Err(format!("Unknown argument \"{}\".", arg))
+            };
+        }
+
+        return Ok(parsed);
+    }
+}
+
+fn expect_value(arg: &str, value: Option<String>) -> Result<String, String> {
+    return match value {
+        Some(value) => Ok(value),
+        None => Err(format!("Missing value for argument \"{}\".", arg))
+    };
+}
@@ -0,0 +1,317 @@
+use std::{env, fs, path::{Path, PathBuf}};
+use serde_json::{Map, Value};
+use crate::config::{format::ConfigFormat, validation::{Diagnostic, field_path, index_path}};
+
+// the config after resolving all of its includes
+pub struct Composed {
+    pub value: Value,
+    // every file the config was composed of, except for the one it was loaded from
+    pub included_files: Vec<PathBuf>,
+    // the patterns of the includes with wildcards, which files created later can match as well
+    pub include_patterns: Vec<PathBuf>
+}
+
+// substitutes environment variables in the config and merges all the files it includes into it,
+// the paths of included files are relative to the directory of the file including them
+pub fn compose(value: Value, dir: &Path) -> Result<Composed, Vec<Diagnostic>> {
+    let mut composer = Composer {
+        included_files: Vec::new(),
+        include_patterns: Vec::new(),
+        stack: Vec::new(),
+        diagnostics: Vec::new()
+    };
+    let value = composer.resolve(value, dir, "");
+
+    if !composer.diagnostics.is_empty() {
+        return Err(composer.diagnostics);
+    }
+
+    return Ok(Composed {
+        value,
+        included_files: composer.included_files,
+        include_patterns: composer.include_patterns
+    });
+}
+
+struct Composer {
+    included_files: Vec<PathBuf>,
+    include_patterns: Vec<PathBuf>,
+    // the files we are currently including, used to detect include cycles
+    stack: Vec<PathBuf>,
+    diagnostics: Vec<Diagnostic>
+}
+
+impl Composer {
+    fn report(self: &mut Self, path: String, message: String) {
+        self.diagnostics.push(Diagnostic {
+            path,
+            position: None,
+            message,
+            suggestion: None
+        });
+    }
+
+    fn resolve(self: &mut Self, value: Value, dir: &Path, file: &str) -> Value {
+        let mut value = value;
+
+        self.substitute(&mut value, String::new(), file);
+
+        let object = match value.as_object_mut() {
+            Some(object) => object,
+            None => return value
+        };
+        let include = match object.remove("include") {
+            Some(include) => include,
+            None => return value
+        };
+        let patterns = match include {
+            Value::String(pattern) => vec![pattern],
+            Value::Array(patterns) => patterns
+                .into_iter()
+                .filter_map(|pattern| pattern.as_str().map(|pattern| pattern.to_string()))
+                .collect(),
+            _ => {
+                self.report(located("include", file), "include must be a path or a list of paths".to_string());
+                return value;
+            }
+        };
+        let mut included = Value::Object(Map::new());
+
+        for pattern in patterns {
+            for path in self.expand(&pattern, dir, file) {
+                let value = self.include(&path);
+
+                merge(&mut included, value);
+            }
+        }
+
+        // the including file's own values take precedence over the included ones
+        merge(&mut included, value);
+
+        return included;
+    }
+
+    fn expand(self: &mut Self, pattern: &str, dir: &Path, file: &str) -> Vec<PathBuf> {
+        let full_pattern = dir.join(pattern);
+        let is_glob = pattern.contains(['*', '?', '[']);
+
+        if !is_glob {
+            if !full_pattern.is_file() {
+                self.report(located("include", file), format!("included file `{}` does not exist", full_pattern.display()));
+                return vec![];
+            }
+
+            return vec![full_pattern];
+        }
+
+        let paths = match glob::glob(&full_pattern.to_string_lossy()) {
+            Ok(paths) => paths,
+            Err(err) => {
+                self.report(located("include", file), format!("invalid include pattern `{}`, {}", pattern, err));
+                return vec![];
+            }
+        };
+
+        self.include_patterns.push(full_pattern);
+
+        return expand_glob(paths);
+    }
+
+    fn include(self: &mut Self, path: &Path) -> Value {
+        let file = path.display().to_string();
+        let canonical = fs::canonicalize(path).unwrap_or(path.to_path_buf());
+
+        if self.stack.contains(&canonical) {
+            self.report(located("include", &file), format!("file `{}` includes itself", file));
+            return Value::Object(Map::new());
+        }
+
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                self.report(located("include", &file), format!("cannot read included file, {}", err));
+                return Value::Object(Map::new());
+            }
+        };
+        let value = match ConfigFormat::from_path(path).parse(&source) {
+            Ok((value, _)) => value,
+            Err(diagnostic) => {
+                self.report(file.clone(), diagnostic.to_string());
+                return Value::Object(Map::new());
+            }
+        };
+        let dir = path.parent().unwrap_or(Path::new("."));
+
+        self.included_files.push(path.to_path_buf());
+        self.stack.push(canonical);
+
+        let value = self.resolve(value, dir, &file);
+
+        self.stack.pop();
+
+        return value;
+    }
+
+    // replaces all the ${VAR} and ${VAR:-default} expressions in string values with the environment
+    fn substitute(self: &mut Self, value: &mut Value, path: String, file: &str) {
+        match value {
+            Value::String(string) => {
+                match substitute_env(string) {
+                    Ok(substituted) => *string = substituted,
+                    Err(message) => self.report(located(&path, file), message)
+                };
+            },
+            Value::Array(values) => {
+                for (idx, value) in values.iter_mut().enumerate() {
+                    self.substitute(value, index_path(&path, idx), file);
+                }
+            },
+            Value::Object(object) => {
+                for (key, value) in object.iter_mut() {
+                    self.substitute(value, field_path(&path, key), file);
+                }
+            },
+            _ => ()
+        };
+    }
+}
+
+// the files matching the pattern, in the order the includes are merged in
+pub fn expand_pattern(pattern: &Path) -> Vec<PathBuf> {
+    return match glob::glob(&pattern.to_string_lossy()) {
+        Ok(paths) => expand_glob(paths),
+        Err(_) => vec![]
+    };
+}
+
+fn expand_glob(paths: glob::Paths) -> Vec<PathBuf> {
+    return paths
+        .filter_map(|path| path.ok())
+        .filter(|path| path.is_file())
+        .collect();
+}
+
+pub fn substitute_env(string: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut rest = string;
+
+    while let Some(start) = rest.find("${") {
+        // $${ escapes the expression, the dollar before it is taken from the config
+        // and not from what a variable before it was substituted with
+        let escaped = rest[..start].ends_with('$');
+
+        result.push_str(&rest[0..start]);
+
+        if escaped {
+            result.push('{');
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => return Err(format!("unclosed `${{` in `{}`", string))
+        };
+        let expression = &rest[start + 2..end];
+        let (name, default) = match expression.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (expression, None)
+        };
+
+        match (env::var(name), default) {
+            (Ok(value), _) if !value.is_empty() => result.push_str(&value),
+            (_, Some(default)) => result.push_str(default),
+            (Ok(value), None) => result.push_str(&value),
+            (Err(_), None) => return Err(format!("environment variable `{}` is not set and has no default", name))
+        };
+
+        rest = &rest[end + 1..];
+    }
+
+    result.push_str(rest);
+
+    return Ok(result);
+}
+
+// merges the overriding value into the base one, objects are merged key by key,
+// lists are concatenated and every other value is replaced
+fn merge(base: &mut Value, overriding: Value) {
+    match (base, overriding) {
+        (Value::Object(base), Value::Object(overriding)) => {
+            for (key, value) in overriding {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => { base.insert(key, value); }
+                };
+            }
+        },
+        (Value::Array(base), Value::Array(overriding)) => {
+            base.extend(overriding);
+        },
+        (base, overriding) => {
+            *base = overriding;
+        }
+    };
+}
+
+// prefixes the path with the file it was found in, when it wasn't found in the main config file
+fn located(path: &str, file: &str) -> String {
+    if file.is_empty() {
+        return path.to_string();
+    }
+
+    return format!("{}: {}", file, path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every test sets variables of its own, as the tests run in parallel
+    #[test]
+    fn substitutes_variables() {
+        env::set_var("QS_COMPOSE_NAME", "api");
+
+        assert_eq!(substitute_env("${QS_COMPOSE_NAME}.example.com/${QS_COMPOSE_NAME}"), Ok("api.example.com/api".to_string()));
+    }
+
+    #[test]
+    fn substitutes_defaults() {
+        env::set_var("QS_COMPOSE_EMPTY", "");
+
+        assert_eq!(substitute_env("${QS_COMPOSE_UNSET:-8080}"), Ok("8080".to_string()));
+        assert_eq!(substitute_env("${QS_COMPOSE_EMPTY:-8080}"), Ok("8080".to_string()));
+        assert_eq!(substitute_env("${QS_COMPOSE_EMPTY}"), Ok(String::new()));
+    }
+
+    #[test]
+    fn keeps_escaped_expressions() {
+        assert_eq!(substitute_env("$${QS_COMPOSE_UNSET} costs $5"), Ok("${QS_COMPOSE_UNSET} costs $5".to_string()));
+    }
+
+    #[test]
+    fn substitutes_after_values_ending_with_a_dollar() {
+        env::set_var("QS_COMPOSE_PRICE", "5$");
+        env::set_var("QS_COMPOSE_UNIT", "/kg");
+
+        assert_eq!(substitute_env("${QS_COMPOSE_PRICE}${QS_COMPOSE_UNIT}"), Ok("5$/kg".to_string()));
+    }
+
+    #[test]
+    fn rejects_unset_variables_and_unclosed_expressions() {
+        assert_eq!(
+            substitute_env("${QS_COMPOSE_UNSET}"),
+            Err("environment variable `QS_COMPOSE_UNSET` is not set and has no default".to_string())
+        );
+        assert_eq!(substitute_env("${QS_COMPOSE_UNSET"), Err("unclosed `${` in `${QS_COMPOSE_UNSET`".to_string()));
+    }
+
+    #[test]
+    fn merges_objects_and_concatenates_lists() {
+        let mut base = serde_json::json!({ "port": 1, "routes": { "/a": [1] }, "list": [1] });
+
+        merge(&mut base, serde_json::json!({ "port": 2, "routes": { "/b": [2] }, "list": [2] }));
+
+        assert_eq!(base, serde_json::json!({ "port": 2, "routes": { "/a": [1], "/b": [2] }, "list": [1, 2] }));
+    }
+}
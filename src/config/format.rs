@@ -0,0 +1,401 @@
+use std::{collections::HashMap, path::Path};
+use serde_json::Value;
+use crate::config::validation::{self, Diagnostic, Position, field_path, index_path};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Yaml,
+    Toml
+}
+
+impl ConfigFormat {
+    // picks the format from the file's extension, files without a known extension are read as JSON
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+
+        return match extension.as_deref() {
+            Some("yaml") | Some("yml") => Self::Yaml,
+            Some("toml") => Self::Toml,
+            _ => Self::Json
+        };
+    }
+
+    // parses the config source into a generic value, together with the positions
+    // of every value in the source when the format lets us know them
+    pub fn parse(self: &Self, source: &str) -> Result<(Value, HashMap<String, Position>), Diagnostic> {
+        return match self {
+            Self::Json => match serde_json::from_str::<Value>(source) {
+                Ok(value) => Ok((value, validation::positions(source))),
+                Err(err) => {
+                    let message = err.to_string();
+                    let location = format!(" at line {} column {}", err.line(), err.column());
+
+                    Err(syntax_error(
+                        "JSON",
+                        message.trim_end_matches(location.as_str()),
+                        Some(Position { line: err.line(), column: err.column() })
+                    ))
+                }
+            },
+            Self::Yaml => match serde_yaml::from_str::<Value>(source) {
+                Ok(value) => Ok((value, yaml_positions(source))),
+                Err(err) => {
+                    let position = err
+                        .location()
+                        .map(|location| Position { line: location.line(), column: location.column() });
+
+                    Err(syntax_error("YAML", &err.to_string(), position))
+                }
+            },
+            Self::Toml => match toml::from_str::<Value>(source) {
+                Ok(value) => Ok((value, toml_positions(source))),
+                Err(err) => {
+                    let position = err
+                        .span()
+                        .map(|span| position_at(source, span.start));
+
+                    Err(syntax_error("TOML", err.message(), position))
+                }
+            }
+        };
+    }
+}
+
+impl TryFrom<String> for ConfigFormat {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        return match s.to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "yaml" | "yml" => Ok(Self::Yaml),
+            "toml" => Ok(Self::Toml),
+            _ => Err(format!("Unknown config format \"{}\", expected one of json, yaml or toml.", s))
+        };
+    }
+}
+
+impl Into<String> for ConfigFormat {
+    fn into(self) -> String {
+        return match self {
+            Self::Json => "json",
+            Self::Yaml => "yaml",
+            Self::Toml => "toml"
+        }
+        .to_string();
+    }
+}
+
+fn syntax_error(format: &str, message: &str, position: Option<Position>) -> Diagnostic {
+    return Diagnostic {
+        path: String::new(),
+        position,
+        message: format!("Invalid {}. {}", format, message.trim()),
+        suggestion: None
+    };
+}
+
+// finds where the keys and values of the TOML source are, keyed by their paths like the JSON ones
+fn toml_positions(source: &str) -> HashMap<String, Position> {
+    let mut positions = HashMap::new();
+
+    if let Ok(document) = toml_edit::ImDocument::parse(source) {
+        scan_toml_table(source, document.as_table(), "", &mut positions);
+    }
+
+    return positions;
+}
+
+fn scan_toml_table(source: &str, table: &toml_edit::Table, path: &str, positions: &mut HashMap<String, Position>) {
+    for (key, item) in table.iter() {
+        let member_path = field_path(path, key);
+
+        if let Some(span) = table.key(key).and_then(|key| key.span()) {
+            positions.insert(member_path.clone(), position_at(source, span.start));
+        }
+
+        match item {
+            toml_edit::Item::Table(table) => scan_toml_table(source, table, &member_path, positions),
+            toml_edit::Item::ArrayOfTables(tables) => {
+                for (idx, table) in tables.iter().enumerate() {
+                    let item_path = index_path(&member_path, idx);
+
+                    if let Some(span) = table.span() {
+                        positions.entry(item_path.clone()).or_insert(position_at(source, span.start));
+                    }
+
+                    scan_toml_table(source, table, &item_path, positions);
+                }
+            },
+            toml_edit::Item::Value(value) => scan_toml_value(source, value, &member_path, positions),
+            toml_edit::Item::None => ()
+        };
+    }
+}
+
+fn scan_toml_value(source: &str, value: &toml_edit::Value, path: &str, positions: &mut HashMap<String, Position>) {
+    if let Some(span) = value.span() {
+        positions.entry(path.to_string()).or_insert(position_at(source, span.start));
+    }
+
+    match value {
+        toml_edit::Value::Array(array) => {
+            for (idx, value) in array.iter().enumerate() {
+                scan_toml_value(source, value, &index_path(path, idx), positions);
+            }
+        },
+        toml_edit::Value::InlineTable(table) => {
+            for (key, value) in table.iter() {
+                let member_path = field_path(path, key);
+
+                if let Some(span) = table.key(key).and_then(|key| key.span()) {
+                    positions.insert(member_path.clone(), position_at(source, span.start));
+                }
+
+                scan_toml_value(source, value, &member_path, positions);
+            }
+        },
+        _ => ()
+    };
+}
+
+// finds where the keys and list items of the YAML source are, keyed by their paths like the JSON ones,
+// serde_yaml doesn't tell, so the lines are scanned, which covers the block style configs are written in,
+// while the values of flow style collections, like [1, 2], only get the position of their key
+fn yaml_positions(source: &str) -> HashMap<String, Position> {
+    let mut positions = HashMap::new();
+    // the collections the lines are in, with the indentation of their entries, innermost last
+    let mut collections = vec![YamlCollection { indent: 0, path: String::new(), is_list: false, items: 0 }];
+    // the path of the last key or list item that has no value on its line, so its value is on the next lines
+    let mut open: Option<String> = None;
+    // the indentation of the key a block scalar (| or >) belongs to, whose lines are skipped
+    let mut block_scalar: Option<usize> = None;
+
+    for (idx, line) in source.lines().enumerate() {
+        let content = line.trim_start_matches(' ');
+        let indent = line.len() - content.len();
+
+        if content.is_empty() || content.starts_with('#') {
+            continue;
+        }
+
+        if let Some(block_indent) = block_scalar {
+            if indent > block_indent {
+                continue;
+            }
+
+            block_scalar = None;
+        }
+
+        if content.starts_with("---") || content.starts_with("...") {
+            continue;
+        }
+
+        let is_item = content == "-" || content.starts_with("- ");
+
+        // a list may be indented as much as the key it belongs to
+        while collections.len() > 1 {
+            let innermost = &collections[collections.len() - 1];
+
+            if innermost.indent > indent || (innermost.indent == indent && innermost.is_list && !is_item) {
+                collections.pop();
+            } else {
+                break;
+            }
+        }
+
+        let innermost = &collections[collections.len() - 1];
+        let opens_collection = indent > innermost.indent || (indent == innermost.indent && is_item && !innermost.is_list);
+
+        match open.take() {
+            Some(path) if opens_collection => collections.push(YamlCollection { indent, path, is_list: is_item, items: 0 }),
+            // the rest of a multi-line value
+            _ if indent > innermost.indent => continue,
+            _ => ()
+        };
+
+        let line_number = idx + 1;
+        let mut column = indent;
+        let mut content = content;
+        let collection = collections.len() - 1;
+
+        if is_item {
+            let item_path = index_path(&collections[collection].path, collections[collection].items);
+
+            collections[collection].items += 1;
+            positions.entry(item_path.clone()).or_insert(Position { line: line_number, column: column + 1 });
+
+            let rest = content[1..].trim_start_matches(' ');
+
+            column += content.len() - rest.len();
+            content = rest;
+
+            if is_empty_value(content) {
+                open = Some(item_path);
+                continue;
+            }
+
+            match yaml_key(content) {
+                // the item is a map, its first key is on the item's line and the others are indented as much as it is
+                Some(_) => collections.push(YamlCollection { indent: column, path: item_path, is_list: false, items: 0 }),
+                None => continue
+            };
+        }
+
+        let (key, value) = match yaml_key(content) {
+            Some(entry) => entry,
+            None => continue
+        };
+        let member_path = field_path(&collections[collections.len() - 1].path, &key);
+
+        positions.insert(member_path.clone(), Position { line: line_number, column: column + 1 });
+
+        if is_empty_value(value) {
+            open = Some(member_path);
+        } else if value.starts_with('|') || value.starts_with('>') {
+            block_scalar = Some(column);
+        }
+    }
+
+    return positions;
+}
+
+struct YamlCollection {
+    indent: usize,
+    path: String,
+    is_list: bool,
+    items: usize
+}
+
+// splits a `key: value` line into its key, unquoted, and the rest of the line
+fn yaml_key(content: &str) -> Option<(String, &str)> {
+    let (key, rest) = match content.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let end = content[1..].find(quote)? + 1;
+            let key = content[1..end].replace("\\\"", "\"").replace("''", "'");
+
+            (key, content[end + 1..].trim_start_matches(' '))
+        },
+        _ => {
+            let end = content.find(": ").or(content.strip_suffix(':').map(|key| key.len()))?;
+
+            (content[..end].trim_end().to_string(), &content[end..])
+        }
+    };
+    let value = rest.strip_prefix(':')?;
+
+    if !value.is_empty() && !value.starts_with(' ') {
+        return None;
+    }
+
+    return Some((key, value.trim()));
+}
+
+fn is_empty_value(value: &str) -> bool {
+    return value.is_empty() || value.starts_with('#');
+}
+
+fn position_at(source: &str, offset: usize) -> Position {
+    let before = &source[0..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = match before.rfind('\n') {
+        Some(idx) => before[idx + 1..].chars().count() + 1,
+        None => before.chars().count() + 1
+    };
+
+    return Position { line, column };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_format_from_the_extension() {
+        assert_eq!(ConfigFormat::from_path(Path::new("quickserving.yml")), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::from_path(Path::new("quickserving.TOML")), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::from_path(Path::new("quickserving")), ConfigFormat::Json);
+    }
+
+    #[test]
+    fn parses_every_format_into_the_same_value() {
+        let json = ConfigFormat::Json.parse("{\"port\": 8080, \"routes\": {}}").unwrap().0;
+        let yaml = ConfigFormat::Yaml.parse("port: 8080\nroutes: {}\n").unwrap().0;
+        let toml = ConfigFormat::Toml.parse("port = 8080\n[routes]\n").unwrap().0;
+
+        assert_eq!(json, yaml);
+        assert_eq!(json, toml);
+    }
+
+    #[test]
+    fn points_at_syntax_errors() {
+        let json = ConfigFormat::Json.parse("{\n  \"port\": 8080,\n}").unwrap_err();
+        let yaml = ConfigFormat::Yaml.parse("port: 8080\nroutes: [\n").unwrap_err();
+        let toml = ConfigFormat::Toml.parse("port = 8080\nroutes = \n").unwrap_err();
+
+        assert!(json.message.starts_with("Invalid JSON."));
+        assert_eq!(json.position, Some(Position { line: 3, column: 1 }));
+        assert!(yaml.message.starts_with("Invalid YAML."));
+        assert_eq!(yaml.position.map(|position| position.line), Some(3));
+        assert!(toml.message.starts_with("Invalid TOML."));
+        assert_eq!(toml.position.map(|position| position.line), Some(2));
+    }
+
+    fn position(positions: &HashMap<String, Position>, path: &str) -> Option<(usize, usize)> {
+        return positions.get(path).map(|position| (position.line, position.column));
+    }
+
+    #[test]
+    fn finds_yaml_positions() {
+        let source = [
+            "# comment",
+            "port: 8080",
+            "routes:",
+            "  \"/greet\":",
+            "    - method: GET",
+            "      response:",
+            "        text: |",
+            "          hello",
+            "          world: not a key",
+            "        http:",
+            "          status: 200",
+            "  /other:",
+            "  - method: GET",
+            "    response: { type: text }"
+        ].join("\n");
+        let positions = ConfigFormat::Yaml.parse(&source).unwrap().1;
+
+        assert_eq!(position(&positions, "port"), Some((2, 1)));
+        assert_eq!(position(&positions, "routes[\"/greet\"]"), Some((4, 3)));
+        assert_eq!(position(&positions, "routes[\"/greet\"][0]"), Some((5, 5)));
+        assert_eq!(position(&positions, "routes[\"/greet\"][0].method"), Some((5, 7)));
+        assert_eq!(position(&positions, "routes[\"/greet\"][0].response.http.status"), Some((11, 11)));
+        assert_eq!(position(&positions, "routes[\"/greet\"][0].response.world"), None);
+        assert_eq!(position(&positions, "routes[\"/other\"][0].response"), Some((14, 5)));
+        // the values of flow collections are not scanned
+        assert_eq!(position(&positions, "routes[\"/other\"][0].response.type"), None);
+    }
+
+    #[test]
+    fn finds_toml_positions() {
+        let source = [
+            "port = 8080",
+            "",
+            "[[routes.\"/greet\"]]",
+            "method = \"GET\"",
+            "response = { type = \"text\", http = { status = 200 } }",
+            "",
+            "[admin]",
+            "port = 9000"
+        ].join("\n");
+        let positions = ConfigFormat::Toml.parse(&source).unwrap().1;
+
+        assert_eq!(position(&positions, "port"), Some((1, 1)));
+        assert_eq!(position(&positions, "routes[\"/greet\"][0].method"), Some((4, 1)));
+        assert_eq!(position(&positions, "routes[\"/greet\"][0].response.http.status"), Some((5, 38)));
+        assert_eq!(position(&positions, "admin.port"), Some((8, 1)));
+    }
+}
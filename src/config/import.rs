@@ -0,0 +1,558 @@
+use std::{collections::{BTreeMap, HashMap}, path::Path, fs};
+use serde_json::{Map, Value, json};
+use crate::{
+    error::Error,
+    http::{method::Method, headers::{Headers, HeaderName}, template::CompiledTemplate},
+    config::{RouteConfig, ResponseConfig, ResponseHTTPConfig, format::ConfigFormat, path}
+};
+
+// the routes of an imported document, sorted by path so that the written config is stable
+pub type Routes = BTreeMap<String, Vec<RouteConfig>>;
+
+static OPERATIONS: [&str; 8] = ["get", "put", "post", "delete", "options", "head", "patch", "trace"];
+
+// response headers that describe a single transfer of the response rather than its content
+static SKIPPED_HEADERS: [&str; 7] = ["content-length", "content-encoding", "transfer-encoding", "connection", "keep-alive", "date", "x-request-id"];
+
+// reads an OpenAPI 3 document or a HAR file and turns it into routes, HAR files are read as JSON
+pub fn import_file(path: &Path, format: ConfigFormat) -> Result<Routes, Error> {
+    let source = fs::read_to_string(path)?;
+    let value = match format.parse(&source) {
+        Ok((value, _)) => value,
+        Err(diagnostic) => return Err(Error::Config(format!("Cannot parse \"{}\". {}", path.display(), diagnostic)))
+    };
+
+    if value.get("openapi").is_some() {
+        return from_openapi(&value);
+    }
+
+    if value.pointer("/log/entries").is_some() {
+        return from_har(&value);
+    }
+
+    return Err(Error::Config(format!("\"{}\" is neither an OpenAPI 3 document nor a HAR file.", path.display())));
+}
+
+// turns every operation of the OpenAPI 3 document into a route response, answering with the example
+// of its successful response, the paths are prefixed with the path of the document's first server
+pub fn from_openapi(document: &Value) -> Result<Routes, Error> {
+    let version = document.get("openapi").and_then(|version| version.as_str()).unwrap_or("");
+
+    if !version.starts_with('3') {
+        return Err(Error::Config(format!("Unsupported OpenAPI version \"{}\", only OpenAPI 3 is supported.", version)));
+    }
+
+    let paths = match document.get("paths").and_then(|paths| paths.as_object()) {
+        Some(paths) => paths,
+        None => return Err(Error::Config("The OpenAPI document has no paths.".to_string()))
+    };
+    let base_path = document
+        .pointer("/servers/0/url")
+        .and_then(|url| url.as_str())
+        .map(server_path)
+        .unwrap_or_default();
+    let mut routes = Routes::new();
+
+    for (route, item) in paths {
+        let item = resolve(document, item);
+
+        for operation_name in OPERATIONS {
+            let operation = match item.get(operation_name) {
+                Some(operation) => resolve(document, operation),
+                None => continue
+            };
+            let method = match Method::try_from(operation_name.to_uppercase()) {
+                Ok(method) => method,
+                Err(_) => continue
+            };
+            let response_config = openapi_response(document, operation);
+
+            routes
+                .entry(format!("{}{}", base_path, route))
+                .or_default()
+                .push(RouteConfig::new(method, response_config));
+        }
+    }
+
+    return Ok(routes);
+}
+
+// the path part of a server URL, e.g. "/v1" for "https://api.example.com/v1/"
+fn server_path(url: &str) -> String {
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|idx| &rest[idx..]).unwrap_or(""),
+        None => url
+    };
+
+    return path.trim_end_matches('/').to_string();
+}
+
+// follows a local $ref like "#/components/schemas/User"
+fn resolve<'a>(document: &'a Value, value: &'a Value) -> &'a Value {
+    let mut value = value;
+
+    // refs can point to refs, the limit protects us from cycles
+    for _ in 0..32 {
+        let pointer = match value.get("$ref").and_then(|reference| reference.as_str()) {
+            Some(reference) => reference.trim_start_matches('#'),
+            None => return value
+        };
+
+        value = match document.pointer(pointer) {
+            Some(resolved) => resolved,
+            None => return value
+        };
+    }
+
+    return value;
+}
+
+fn openapi_response(document: &Value, operation: &Value) -> ResponseConfig {
+    let empty = Map::new();
+    let responses = operation
+        .get("responses")
+        .and_then(|responses| responses.as_object())
+        .unwrap_or(&empty);
+    // the first successful response, falling back to the default and then to any other one
+    let mut codes = responses.keys().collect::<Vec<&String>>();
+
+    codes.sort_by_key(|code| match code.as_str() {
+        code if code.starts_with('2') => (0, code.to_string()),
+        "default" => (1, String::new()),
+        code => (2, code.to_string())
+    });
+
+    let (code, response) = match codes.first() {
+        Some(code) => (code.as_str(), resolve(document, &responses[*code])),
+        None => ("200", &Value::Null)
+    };
+    let status = match code.parse::<u16>() {
+        Ok(200) | Err(_) => None,
+        Ok(status) => Some(status)
+    };
+    let content = response.get("content").and_then(|content| content.as_object());
+    // JSON is preferred when the response has several content types
+    let media = content.and_then(|content| {
+        content
+            .iter()
+            .find(|(content_type, _)| content_type.contains("json"))
+            .or(content.iter().next())
+    });
+    let mut headers = Headers::new();
+    let text = match media {
+        Some((content_type, media)) => {
+            let _ = headers.insert(HeaderName::ContentType, content_type.clone());
+            let example = media_example(document, media);
+
+            match example {
+                Value::String(text) if !content_type.contains("json") => text,
+                Value::Null => String::new(),
+                example => serde_json::to_string_pretty(&example).unwrap_or_default()
+            }
+        },
+        None => String::new()
+    };
+
+    return ResponseConfig::Text {
+        text,
+        templated: false,
+        http: ResponseHTTPConfig { status, headers, raw_headers: Vec::new() },
+        compiled: CompiledTemplate::default()
+    };
+}
+
+// the example of the media type, from its "example", its first "examples" entry, or its schema
+fn media_example(document: &Value, media: &Value) -> Value {
+    if let Some(example) = media.get("example") {
+        return example.clone();
+    }
+
+    let first_example = media
+        .get("examples")
+        .and_then(|examples| examples.as_object())
+        .and_then(|examples| examples.values().next())
+        .map(|example| resolve(document, example))
+        .and_then(|example| example.get("value"));
+
+    if let Some(example) = first_example {
+        return example.clone();
+    }
+
+    return match media.get("schema") {
+        Some(schema) => schema_example(document, schema, 0),
+        None => Value::Null
+    };
+}
+
+// builds an example from the schema, using its examples and defaults where it has them
+fn schema_example(document: &Value, schema: &Value, depth: usize) -> Value {
+    let schema = resolve(document, schema);
+
+    // recursive schemas would never end
+    if depth > 8 {
+        return Value::Null;
+    }
+
+    if let Some(example) = schema.get("example").or(schema.get("default")) {
+        return example.clone();
+    }
+
+    if let Some(first) = schema.get("enum").and_then(|values| values.get(0)) {
+        return first.clone();
+    }
+
+    for combinator in ["allOf", "oneOf", "anyOf"] {
+        let schemas = match schema.get(combinator).and_then(|schemas| schemas.as_array()) {
+            Some(schemas) => schemas,
+            None => continue
+        };
+
+        // all of the schemas are merged, otherwise any of them is fine so we take the first
+        if combinator != "allOf" {
+            return schemas.first().map(|schema| schema_example(document, schema, depth + 1)).unwrap_or(Value::Null);
+        }
+
+        let mut merged = Map::new();
+
+        for schema in schemas {
+            if let Value::Object(object) = schema_example(document, schema, depth + 1) {
+                merged.extend(object);
+            }
+        }
+
+        return Value::Object(merged);
+    }
+
+    let schema_type = schema.get("type").and_then(|schema_type| schema_type.as_str());
+    let has_properties = schema.get("properties").is_some();
+
+    return match schema_type {
+        Some("object") | None if has_properties => {
+            let properties = schema
+                .get("properties")
+                .and_then(|properties| properties.as_object())
+                .map(|properties| properties
+                    .iter()
+                    .map(|(name, property)| (name.clone(), schema_example(document, property, depth + 1)))
+                    .collect::<Map<String, Value>>())
+                .unwrap_or_default();
+
+            Value::Object(properties)
+        },
+        Some("object") => json!({}),
+        Some("array") => match schema.get("items") {
+            Some(items) => json!([schema_example(document, items, depth + 1)]),
+            None => json!([])
+        },
+        Some("string") => match schema.get("format").and_then(|format| format.as_str()) {
+            Some("date-time") => json!("2024-01-01T00:00:00Z"),
+            Some("date") => json!("2024-01-01"),
+            Some("uuid") => json!("00000000-0000-0000-0000-000000000000"),
+            Some("email") => json!("user@example.com"),
+            _ => json!("string")
+        },
+        Some("integer") => json!(0),
+        Some("number") => json!(0.0),
+        Some("boolean") => json!(false),
+        _ => Value::Null
+    };
+}
+
+// turns the entries of the HAR file into route responses, one per method of every path, the paths
+// are turned into templates where their segments look like ids, e.g. /users/42 into /users/{id}
+pub fn from_har(har: &Value) -> Result<Routes, Error> {
+    let entries = match har.pointer("/log/entries").and_then(|entries| entries.as_array()) {
+        Some(entries) => entries,
+        None => return Err(Error::Config("The HAR file has no entries.".to_string()))
+    };
+    let mut routes = Routes::new();
+
+    for entry in entries {
+        let (method, url) = match (entry.pointer("/request/method"), entry.pointer("/request/url")) {
+            (Some(Value::String(method)), Some(Value::String(url))) => (method, url),
+            _ => continue
+        };
+        let method = match Method::try_from(method.to_uppercase()) {
+            Ok(method) => method,
+            Err(_) => continue
+        };
+        let response = match har_response(entry) {
+            Some(response) => response,
+            None => continue
+        };
+        let route_configs = routes.entry(infer_template(&url_path(url))).or_default();
+
+        // the first response of every method is kept, the others are most likely the same request repeated
+        if route_configs.iter().all(|route_config| !route_config.same_requests(&RouteConfig::new(method.clone(), response.clone()))) {
+            route_configs.push(RouteConfig::new(method, response));
+        }
+    }
+
+    return Ok(routes);
+}
+
+fn har_response(entry: &Value) -> Option<ResponseConfig> {
+    let response = entry.get("response")?;
+    let content = response.get("content");
+
+    // binary bodies are base64 encoded, and our responses can only hold text
+    if content.and_then(|content| content.get("encoding")).and_then(|encoding| encoding.as_str()) == Some("base64") {
+        return None;
+    }
+
+    let text = content
+        .and_then(|content| content.get("text"))
+        .and_then(|text| text.as_str())
+        .unwrap_or("")
+        .to_string();
+    let status = match response.get("status").and_then(|status| status.as_u64()) {
+        Some(200) | None => None,
+        Some(status) => Some(status as u16)
+    };
+    let mut headers = Headers::new();
+    let har_headers = response
+        .get("headers")
+        .and_then(|headers| headers.as_array())
+        .cloned()
+        .unwrap_or_default();
+
+    for header in har_headers {
+        let (name, value) = match (header.get("name").and_then(|name| name.as_str()), header.get("value").and_then(|value| value.as_str())) {
+            (Some(name), Some(value)) => (name, value),
+            _ => continue
+        };
+
+        if SKIPPED_HEADERS.contains(&name.to_lowercase().as_str()) {
+            continue;
+        }
+
+        if let Some(name) = HeaderName::find(name) {
+            let _ = headers.insert(name, value.to_string());
+        }
+    }
+
+    return Some(ResponseConfig::Text {
+        text,
+        templated: false,
+        http: ResponseHTTPConfig { status, headers, raw_headers: Vec::new() },
+        compiled: CompiledTemplate::default()
+    });
+}
+
+// the path of the URL, without its query and fragment
+fn url_path(url: &str) -> String {
+    let rest = match url.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|idx| &rest[idx..]).unwrap_or("/"),
+        None => url
+    };
+
+    return rest
+        .split(['?', '#'])
+        .next()
+        .unwrap_or("/")
+        .to_string();
+}
+
+// replaces the segments of the path that look like ids with parameters, named after the segment before them
+fn infer_template(url_path: &str) -> String {
+    let segments = url_path.split('/').collect::<Vec<&str>>();
+    let mut used_names: HashMap<String, usize> = HashMap::new();
+    let mut template = Vec::new();
+
+    for (idx, segment) in segments.iter().enumerate() {
+        if !is_id(segment) {
+            template.push(segment.to_string());
+            continue;
+        }
+
+        let name = match idx.checked_sub(1).map(|previous| segments[previous]) {
+            Some(previous) if !previous.is_empty() && !is_id(previous) => format!("{}_id", previous.trim_end_matches('s')),
+            _ => "id".to_string()
+        };
+        let count = used_names.entry(name.clone()).or_insert(0);
+
+        *count += 1;
+
+        match *count {
+            1 => template.push(format!("{{{}}}", name)),
+            count => template.push(format!("{{{}_{}}}", name, count))
+        };
+    }
+
+    let template = template.join("/");
+
+    // a template that cannot be matched again is not a template
+    if path::match_path(&template, url_path).is_none() {
+        return url_path.to_string();
+    }
+
+    return template;
+}
+
+// numbers, UUIDs and long hex strings are ids
+fn is_id(segment: &str) -> bool {
+    if segment.is_empty() {
+        return false;
+    }
+
+    let is_number = segment.chars().all(|c| c.is_ascii_digit());
+    let is_uuid = segment.len() == 36
+        && segment.chars().enumerate().all(|(idx, c)| match idx {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit()
+        });
+    let is_hex = segment.len() >= 16 && segment.chars().all(|c| c.is_ascii_hexdigit());
+
+    return is_number || is_uuid || is_hex;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn as_json(routes: &Routes) -> Value {
+        return serde_json::to_value(routes).unwrap();
+    }
+
+    #[test]
+    fn imports_openapi_operations_with_their_examples() {
+        let document = json!({
+            "openapi": "3.0.3",
+            "servers": [{ "url": "https://api.example.com/v1/" }],
+            "paths": {
+                "/users/{id}": {
+                    "get": {
+                        "responses": {
+                            "404": { "description": "missing" },
+                            "200": { "content": { "application/json": { "example": { "id": 1 } } } }
+                        }
+                    },
+                    "delete": { "responses": { "204": { "description": "deleted" } } }
+                }
+            }
+        });
+        let routes = as_json(&from_openapi(&document).unwrap());
+
+        assert_eq!(routes, json!({
+            "/v1/users/{id}": [
+                {
+                    "method": "GET",
+                    "response": { "type": "text", "text": "{\n  \"id\": 1\n}", "http": { "headers": { "Content-Type": "application/json" } } }
+                },
+                {
+                    "method": "DELETE",
+                    "response": { "type": "text", "text": "", "http": { "status": 204, "headers": {} } }
+                }
+            ]
+        }));
+    }
+
+    #[test]
+    fn builds_openapi_examples_from_referenced_schemas() {
+        let document = json!({
+            "openapi": "3.1.0",
+            "paths": {
+                "/users": {
+                    "post": {
+                        "responses": {
+                            "201": { "$ref": "#/components/responses/Created" }
+                        }
+                    }
+                }
+            },
+            "components": {
+                "responses": {
+                    "Created": { "content": { "application/json": { "schema": { "$ref": "#/components/schemas/User" } } } }
+                },
+                "schemas": {
+                    "User": {
+                        "type": "object",
+                        "properties": {
+                            "id": { "type": "string", "format": "uuid" },
+                            "role": { "enum": ["admin", "user"] },
+                            "tags": { "type": "array", "items": { "type": "string" } },
+                            "manager": { "$ref": "#/components/schemas/User" }
+                        }
+                    }
+                }
+            }
+        });
+        let routes = from_openapi(&document).unwrap();
+        let text = match &routes["/users"][0].response {
+            ResponseConfig::Text { text, http, .. } => {
+                assert_eq!(http.status, Some(201));
+                text.clone()
+            },
+            _ => panic!("expected a text response")
+        };
+        let example = serde_json::from_str::<Value>(&text).unwrap();
+
+        assert_eq!(example["id"], json!("00000000-0000-0000-0000-000000000000"));
+        assert_eq!(example["role"], json!("admin"));
+        assert_eq!(example["tags"], json!(["string"]));
+        assert_eq!(example["manager"]["manager"]["role"], json!("admin"));
+    }
+
+    #[test]
+    fn rejects_other_openapi_versions() {
+        assert!(from_openapi(&json!({ "openapi": "2.0", "paths": {} })).is_err());
+        assert!(from_openapi(&json!({ "openapi": "3.0.0" })).is_err());
+    }
+
+    #[test]
+    fn imports_har_entries_with_templated_paths() {
+        let har = json!({
+            "log": {
+                "entries": [
+                    {
+                        "request": { "method": "GET", "url": "https://api.example.com/users/42/orders/0b7f5c2e-8a1d-4e3f-9c6b-2d4a1e5f7a90?expand=1" },
+                        "response": {
+                            "status": 200,
+                            "headers": [
+                                { "name": "Content-Type", "value": "application/json" },
+                                { "name": "Content-Length", "value": "11" },
+                                { "name": "Date", "value": "Mon, 01 Jan 2024 00:00:00 GMT" }
+                            ],
+                            "content": { "text": "{\"id\":\"o\"}" }
+                        }
+                    },
+                    {
+                        "request": { "method": "GET", "url": "https://api.example.com/users/43/orders/1b7f5c2e-8a1d-4e3f-9c6b-2d4a1e5f7a90" },
+                        "response": { "status": 200, "content": { "text": "repeated" } }
+                    },
+                    {
+                        "request": { "method": "POST", "url": "https://api.example.com/users" },
+                        "response": { "status": 201, "content": { "text": "" } }
+                    },
+                    {
+                        "request": { "method": "GET", "url": "https://api.example.com/logo.png" },
+                        "response": { "status": 200, "content": { "text": "iVBORw0KGgo=", "encoding": "base64" } }
+                    }
+                ]
+            }
+        });
+        let routes = as_json(&from_har(&har).unwrap());
+
+        assert_eq!(routes, json!({
+            "/users": [
+                { "method": "POST", "response": { "type": "text", "text": "", "http": { "status": 201, "headers": {} } } }
+            ],
+            "/users/{user_id}/orders/{order_id}": [
+                {
+                    "method": "GET",
+                    "response": { "type": "text", "text": "{\"id\":\"o\"}", "http": { "headers": { "Content-Type": "application/json" } } }
+                }
+            ]
+        }));
+    }
+
+    #[test]
+    fn infers_path_templates() {
+        assert_eq!(infer_template("/users/42"), "/users/{user_id}");
+        assert_eq!(infer_template("/42/43"), "/{id}/{id_2}");
+        assert_eq!(infer_template("/files/deadbeefdeadbeef"), "/files/{file_id}");
+        assert_eq!(infer_template("/users/me"), "/users/me");
+        assert_eq!(url_path("https://example.com"), "/");
+        assert_eq!(url_path("/a/b?c#d"), "/a/b");
+    }
+}
@@ -1,65 +1,910 @@
-use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
-use crate::http::{method::Method, headers::Headers};
+pub mod compose;
+pub mod format;
+pub mod import;
+pub mod path;
+pub mod schema;
+pub mod shared;
+pub mod validation;
+pub mod watcher;
 
-#[derive(Serialize, Deserialize, Clone)]
+use serde::{Serialize, Deserialize, Deserializer, de};
+use schemars::JsonSchema;
+use serde_json::Value;
+use std::{collections::HashMap, fs::File, io::Read, path::{Path, PathBuf}};
+use crate::{error::Error, http::{method::Method, headers::Headers, request::Request, scenarios::{Scenarios, LockedScenarios}, script::CompiledScript, template::CompiledTemplate}, logging::LogLevel, config::format::ConfigFormat};
+
+// the config file names looked up when no config file is given, in order
+pub static CONFIG_FILE_NAMES: [&str; 4] = [
+    "quickserving.json",
+    "quickserving.yaml",
+    "quickserving.yml",
+    "quickserving.toml"
+];
+
+/// HTTP metadata of a response.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default)]
+#[serde(deny_unknown_fields)]
 pub struct ResponseHTTPConfig {
-    pub headers: Headers
+    /// The status code of the response, 200 when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Headers sent with the response.
+    #[serde(default)]
+    pub headers: Headers,
+    /// Headers sent as they are after the known ones, in order, so they can be any header and repeated, like Set-Cookie.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub raw_headers: Vec<RawHeader>
+}
+
+/// A header sent as it is.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RawHeader {
+    pub name: String,
+    pub value: String
+}
+
+impl ResponseHTTPConfig {
+    // fills in everything this config doesn't set itself from the defaults
+    pub fn inherit(self: &Self, defaults: &ResponseHTTPConfig) -> ResponseHTTPConfig {
+        let mut headers = defaults.headers.clone();
+
+        for (name, value) in self.headers.iter() {
+            let _ = headers.insert(name.clone(), value.clone());
+        }
+
+        return ResponseHTTPConfig {
+            status: self.status.or(defaults.status),
+            headers,
+            raw_headers: defaults.raw_headers.iter().chain(self.raw_headers.iter()).cloned().collect()
+        };
+    }
+
+    // the raw headers as name and value pairs, the way responses take them
+    pub fn raw_header_pairs(self: &Self) -> Vec<(String, String)> {
+        return self.raw_headers
+            .iter()
+            .map(|header| (header.name.clone(), header.value.clone()))
+            .collect();
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-#[serde(tag = "type", rename_all = "lowercase")]
+/// What the route responds with, picked by the "type" attribute.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
 pub enum ResponseConfig {
+    /// Responds with the text from the "text" attribute.
     Text { 
+        /// The response body.
         text: String,
-        http: ResponseHTTPConfig
+        /// Renders the text as a template with the request's data, see the templates docs.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        templated: bool,
+        #[serde(default)]
+        http: ResponseHTTPConfig,
+        #[serde(skip)]
+        #[schemars(skip)]
+        compiled: CompiledTemplate
+    },
+    /// Responds with the JSON value from the "body" attribute, with the application/json content type.
+    Json {
+        /// The response body, any JSON value.
+        body: Value,
+        /// Indents the body over multiple lines.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        pretty: bool,
+        /// Renders every string in the body as a template with the request's data, see the templates docs.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        templated: bool,
+        #[serde(default)]
+        http: ResponseHTTPConfig,
+        #[serde(skip)]
+        #[schemars(skip)]
+        compiled: CompiledTemplate
     },
+    /// Responds with the file at the "source" path, or with 404 when it cannot be found.
     File { 
+        /// Path of the file to serve, relative to the directory the server runs in.
         source: String,
+        /// Renders the file as a template with the request's data, see the templates docs.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        templated: bool,
+        #[serde(default)]
+        http: ResponseHTTPConfig
+    },
+    /// Forwards the request to the "upstream" URL, with the request's path and query appended to it.
+    Proxy {
+        /// Base URL of the server to forward to, e.g. "https://api.example.com".
+        upstream: String,
+        /// Headers added to the upstream's response, and the status replacing its own.
+        #[serde(default)]
         http: ResponseHTTPConfig
+    },
+    /// Sends the request to a FastCGI "upstream", like PHP-FPM, and responds with what the application writes.
+    FastCgi {
+        /// Address of the upstream, "host:port" for TCP or "unix:<path>" for a Unix socket.
+        upstream: String,
+        /// The script the upstream runs, sent as the SCRIPT_FILENAME param, e.g. "/var/www/index.php".
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        script_filename: Option<String>,
+        /// Milliseconds the upstream has to answer before the route answers with 504, 30000 by default.
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+        /// Params added to the request's ones, replacing the CGI ones with the same name.
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        params: HashMap<String, String>,
+        #[serde(default)]
+        http: ResponseHTTPConfig
+    },
+    /// Runs the Rhai "script", or the one in the "source" file, which builds the response from the request.
+    /// Needs the "script" feature.
+    Script {
+        /// The code of the script.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        script: Option<String>,
+        /// Path of a file with the code of the script, relative to the directory the server runs in.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source: Option<String>,
+        /// Milliseconds the script can run for before it's stopped and the route answers with 500, 1000 by default.
+        #[serde(default = "default_script_timeout_ms")]
+        timeout_ms: u64,
+        #[serde(default)]
+        http: ResponseHTTPConfig,
+        #[serde(skip)]
+        #[schemars(skip)]
+        compiled: CompiledScript
+    },
+    /// Responds with the received request, like httpbin's /anything.
+    Echo {
+        /// "json" for the request as a JSON object, or "raw" for the request as HTTP text, "json" by default.
+        #[serde(default)]
+        format: EchoFormat,
+        #[serde(default)]
+        http: ResponseHTTPConfig
+    },
+    /// Runs the "command" as a CGI script, with the request in its environment and the body on its input,
+    /// and responds with the headers and the body it writes.
+    Cgi {
+        /// The executable, a path relative to the directory the server runs in, or a name looked up in PATH.
+        command: String,
+        /// Arguments the command is run with.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        args: Vec<String>,
+        /// Directory the command runs in, the server's one by default.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        working_dir: Option<String>,
+        /// Milliseconds the command can run for before it's killed and answered with 504, 30000 by default.
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+        /// Variables added to the command's environment, replacing the CGI ones with the same name.
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        env: HashMap<String, String>,
+        /// Passes the server's whole environment to the command, which otherwise only gets PATH and the CGI variables.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        inherit_env: bool,
+        #[serde(default)]
+        http: ResponseHTTPConfig
+    },
+    /// Responds with the "responses" one after another, on every request the next one.
+    Sequence {
+        /// The responses in the order they are used in.
+        responses: Vec<ResponseConfig>,
+        /// What to respond with once every response was used, "last" by default.
+        #[serde(default)]
+        then: SequenceEnd
+    },
+    /// Responds with one of the "responses" picked at random, by their weights.
+    Random {
+        responses: Vec<WeightedResponseConfig>,
+        /// Makes the picks the same on every run when set.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seed: Option<u64>
+    }
+}
+
+/// How an echo response writes the request.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum EchoFormat {
+    /// A JSON object with the method, path, query, version, headers and body of the request.
+    #[default]
+    Json,
+    /// The request as it was sent, in HTTP text.
+    Raw
+}
+
+/// What a sequence responds with once every response was used.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SequenceEnd {
+    /// Keeps responding with the last response.
+    #[default]
+    Last,
+    /// Starts over from the first response.
+    Cycle
+}
+
+/// A response of a random response, with the weight of picking it.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WeightedResponseConfig {
+    /// How likely the response is to be picked compared to the others, 1 by default.
+    #[serde(default = "default_weight")]
+    pub weight: u64,
+    pub response: ResponseConfig
+}
+
+fn default_timeout_ms() -> u64 {
+    return 30_000;
+}
+
+fn default_script_timeout_ms() -> u64 {
+    return 1000;
+}
+
+fn default_weight() -> u64 {
+    return 1;
+}
+
+impl ResponseConfig {
+    // the responses the sequence or random response picks from, none for the others
+    pub fn nested(self: &Self) -> Vec<&ResponseConfig> {
+        return match self {
+            Self::Sequence { responses, .. } => responses.iter().collect(),
+            Self::Random { responses, .. } => responses.iter().map(|weighted| &weighted.response).collect(),
+            _ => Vec::new()
+        };
+    }
+
+    // compiles the script of every script response and the templates of every templated text and json
+    // response in this one, so that they are ready before the first request
+    fn compile(self: &mut Self) -> Result<(), String> {
+        match self {
+            Self::Text { text, templated: true, compiled, .. } => {
+                *compiled = CompiledTemplate::compile(text)?;
+            },
+            Self::Json { body, templated: true, compiled, .. } => {
+                *compiled = CompiledTemplate::compile_json(body)?;
+            },
+            Self::Script { script, source, compiled, .. } => {
+                let code = match (script, source) {
+                    (Some(script), _) => script.clone(),
+                    (None, Some(source)) => match std::fs::read_to_string(&source) {
+                        Ok(code) => code,
+                        Err(err) => return Err(format!("Cannot read script \"{}\". {}", source, err))
+                    },
+                    (None, None) => return Err("The script response has neither a script nor a source.".to_string())
+                };
+
+                *compiled = CompiledScript::compile(&code)?;
+            },
+            Self::Sequence { responses, .. } => for response in responses {
+                response.compile()?;
+            },
+            Self::Random { responses, .. } => for weighted in responses {
+                weighted.response.compile()?;
+            },
+            _ => {}
+        };
+
+        return Ok(());
+    }
+}
+
+/// Settings every route inherits unless it overrides them.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct DefaultsConfig {
+    /// HTTP metadata inherited by every response, header by header.
+    #[serde(default)]
+    pub http: ResponseHTTPConfig,
+    /// Faults injected into the responses of every route, fault by fault.
+    #[serde(default)]
+    pub faults: FaultsConfig
+}
+
+/// Faults injected into a response, to test how clients cope with slow and broken servers.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FaultsConfig {
+    /// Waits before sending the response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delay: Option<DelayFault>,
+    /// Sends the response at most this fast.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bandwidth: Option<BandwidthFault>,
+    /// Closes the connection without sending a response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drop: Option<Fault>,
+    /// Sends the head and half of the body, then closes the connection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncate: Option<Fault>,
+    /// Sends random bytes instead of the response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub garbage: Option<Fault>,
+    /// Resets the TCP connection without sending a response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reset: Option<Fault>
+}
+
+impl FaultsConfig {
+    // fills in every fault this config doesn't set itself from the defaults
+    pub fn inherit(self: &Self, defaults: &FaultsConfig) -> FaultsConfig {
+        return FaultsConfig {
+            delay: self.delay.clone().or(defaults.delay.clone()),
+            bandwidth: self.bandwidth.clone().or(defaults.bandwidth.clone()),
+            drop: self.drop.clone().or(defaults.drop.clone()),
+            truncate: self.truncate.clone().or(defaults.truncate.clone()),
+            garbage: self.garbage.clone().or(defaults.garbage.clone()),
+            reset: self.reset.clone().or(defaults.reset.clone())
+        };
     }
+
+    pub fn is_empty(self: &Self) -> bool {
+        return self.delay.is_none()
+            && self.bandwidth.is_none()
+            && self.drop.is_none()
+            && self.truncate.is_none()
+            && self.garbage.is_none()
+            && self.reset.is_none();
+    }
+}
+
+/// A fault that only needs its probability.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Fault {
+    /// How likely the fault is to happen, from 0 to 1, 1 by default.
+    #[serde(default = "default_probability")]
+    pub probability: f64
+}
+
+/// Waits "ms" milliseconds before sending the response, plus up to "jitter_ms" more.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DelayFault {
+    pub ms: u64,
+    /// The most milliseconds added to the delay at random, 0 by default.
+    #[serde(default)]
+    pub jitter_ms: u64,
+    /// How likely the delay is to happen, from 0 to 1, 1 by default.
+    #[serde(default = "default_probability")]
+    pub probability: f64
+}
+
+/// Sends the response at "bytes_per_second".
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BandwidthFault {
+    pub bytes_per_second: u64,
+    /// How likely the throttling is to happen, from 0 to 1, 1 by default.
+    #[serde(default = "default_probability")]
+    pub probability: f64
+}
+
+fn default_probability() -> f64 {
+    return 1.0;
+}
+
+/// Where and how every served request is logged.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AccessLogConfig {
+    /// "common", "combined", "json" or a custom template with %placeholders%.
+    #[serde(default = "default_access_log_format")]
+    pub format: String,
+    /// "stdout" or the path of the file to append the log to.
+    #[serde(default = "default_access_log_output")]
+    pub output: String
+}
+
+fn default_access_log_format() -> String {
+    return "common".to_string();
+}
+
+fn default_access_log_output() -> String {
+    return "stdout".to_string();
+}
+
+/// The endpoint exposing the server's metrics in the Prometheus text format.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// The path the metrics are served at, "/metrics" by default.
+    #[serde(default = "default_metrics_path")]
+    pub path: String
+}
+
+pub fn default_metrics_path() -> String {
+    return "/metrics".to_string();
+}
+
+/// The built-in liveness and readiness endpoints.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HealthConfig {
+    /// The path answering whether the server is running, "/healthz" by default.
+    #[serde(default = "default_liveness_path")]
+    pub liveness_path: String,
+    /// The path answering whether the server can serve its routes, "/readyz" by default.
+    #[serde(default = "default_readiness_path")]
+    pub readiness_path: String,
+    /// How many seconds the server keeps serving as not ready after it's asked to shut down, 5 by default.
+    #[serde(default = "default_drain_seconds")]
+    pub drain_seconds: u64
 }
 
-#[derive(Serialize, Deserialize)]
+pub fn default_liveness_path() -> String {
+    return "/healthz".to_string();
+}
+
+pub fn default_readiness_path() -> String {
+    return "/readyz".to_string();
+}
+
+fn default_drain_seconds() -> u64 {
+    return 5;
+}
+
+/// The admin API changing the routes at runtime.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AdminConfig {
+    /// The port the admin API listens on, it must differ from the server's port.
+    #[serde(deserialize_with = "deserialize_port")]
+    #[schemars(schema_with = "schema::port_schema")]
+    pub port: u16,
+    /// The address the admin API listens on, "127.0.0.1" by default so that only the local machine can reach it.
+    #[serde(default = "default_admin_host")]
+    pub host: String,
+    /// Lets the admin API add cgi, fastcgi and script responses, which run code on the machine, false by default.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_exec_responses: bool
+}
+
+fn default_admin_host() -> String {
+    return "127.0.0.1".to_string();
+}
+
+/// The journal of received requests, kept in memory for verifying them.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct JournalConfig {
+    /// How many of the most recent requests are kept, 1000 by default.
+    #[serde(default = "default_journal_capacity")]
+    pub capacity: usize
+}
+
+fn default_journal_capacity() -> usize {
+    return 1000;
+}
+
+/// A named state machine, routes of the scenario respond only in some of its states and can move it to another one.
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioConfig {
+    /// The state the scenario starts in, and returns to when it's reset, "started" by default.
+    #[serde(default = "default_scenario_state")]
+    pub initial: String
+}
+
+fn default_scenario_state() -> String {
+    return "started".to_string();
+}
+
+/// Settings of the server's own log.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// The minimum level of logged messages, "info" when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<LogLevel>,
+    /// Minimum levels of single modules, keyed by module path like "http::server".
+    #[serde(default)]
+    pub modules: HashMap<String, LogLevel>,
+    /// "stdout", "stderr" or the path of the file to append the log to, "stdout" when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// "plain" lines formatted with the format template, or "syslog" formatted lines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<LogStyle>,
+    /// Template of plain lines, with %loglevel%, %module%, %date%, %time% and %message% placeholders.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    /// Whether the log level is colored, "auto" colors it only when writing to a terminal.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<LogColor>,
+    /// Rotation of the log file, only used when logging to a file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<LogRotationConfig>
+}
+
+impl LoggingConfig {
+    // the config with every setting the overrides set replaced with theirs
+    pub fn overridden_by(self: &Self, overrides: &LoggingConfig) -> LoggingConfig {
+        let mut modules = self.modules.clone();
+
+        modules.extend(overrides.modules.clone());
+
+        return LoggingConfig {
+            level: overrides.level.or(self.level),
+            modules,
+            output: overrides.output.clone().or(self.output.clone()),
+            style: overrides.style.or(self.style),
+            format: overrides.format.clone().or(self.format.clone()),
+            color: overrides.color.or(self.color),
+            rotation: overrides.rotation.clone().or(self.rotation.clone())
+        };
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStyle {
+    Plain,
+    Syslog
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LogColor {
+    Auto,
+    Always,
+    Never
+}
+
+/// When the log file is rotated, it's renamed with a ".1" suffix, shifting older files to ".2", ".3" and so on.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LogRotationConfig {
+    /// Rotates the file once it grows over this many bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<u64>,
+    /// Rotates the file every hour or every day.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<RotationInterval>,
+    /// How many rotated files are kept, older ones are deleted.
+    #[serde(default = "default_rotation_keep")]
+    pub keep: usize
+}
+
+fn default_rotation_keep() -> usize {
+    return 5;
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RotationInterval {
+    Hourly,
+    Daily
+}
+
+/// A response to requests matching the route.
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct RouteConfig {
+    /// The HTTP method of requests to respond to.
     method: Method,
+    /// Query parameters the request must have with these values.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    query: Option<HashMap<String, String>>,
+    /// The exact body the request must have.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    /// The scenario the states and the next state refer to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scenario: Option<String>,
+    /// The states of the scenario the route responds in, any state when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    states: Option<Vec<String>>,
+    /// The state the scenario moves to once the route has responded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    next_state: Option<String>,
+    /// Faults injected into the response, overriding the default ones fault by fault.
+    #[serde(default, skip_serializing_if = "FaultsConfig::is_empty")]
+    faults: FaultsConfig,
     response: ResponseConfig
 }
 
-#[derive(Deserialize)]
+impl RouteConfig {
+    pub fn new(method: Method, response: ResponseConfig) -> Self {
+        return Self {
+            method,
+            query: None,
+            body: None,
+            scenario: None,
+            states: None,
+            next_state: None,
+            faults: FaultsConfig::default(),
+            response
+        };
+    }
+
+    pub fn with_query(self: Self, query: HashMap<String, String>) -> Self {
+        return Self { query: Some(query), ..self };
+    }
+
+    pub fn with_body(self: Self, body: String) -> Self {
+        return Self { body: Some(body), ..self };
+    }
+
+    pub fn response(self: &Self) -> &ResponseConfig {
+        return &self.response;
+    }
+
+    pub fn faults(self: &Self) -> &FaultsConfig {
+        return &self.faults;
+    }
+
+    // the scenario and the state it moves to once the route has responded
+    pub fn transition(self: &Self) -> Option<(&String, &String)> {
+        return self.scenario.as_ref().zip(self.next_state.as_ref());
+    }
+
+    // whether both configs respond to the same requests
+    pub fn same_requests(self: &Self, other: &RouteConfig) -> bool {
+        return self.method == other.method
+            && self.query == other.query
+            && self.body == other.body
+            && self.scenario == other.scenario
+            && self.states == other.states;
+    }
+
+    pub fn matches(self: &Self, request: &Request) -> bool {
+        if self.method != request.method {
+            return false;
+        }
+
+        if let Some(query) = &self.query {
+            let params = request.query_params();
+            let has_all = query
+                .iter()
+                .all(|(key, value)| params.iter().any(|(param, param_value)| param == key && param_value == value));
+
+            if !has_all {
+                return false;
+            }
+        }
+
+        return match &self.body {
+            Some(body) => body.as_bytes() == request.body,
+            None => true
+        };
+    }
+}
+
+/// The quickserving configuration file.
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct ServerConfig {
+    /// The port the server listens on, can be given as a string to set it from an environment variable.
+    #[serde(deserialize_with = "deserialize_port")]
+    #[schemars(schema_with = "schema::port_schema")]
     pub port: u16,
-    pub routes: HashMap<String, Vec<RouteConfig>>
+    #[serde(default)]
+    pub defaults: DefaultsConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    /// Logs every served request when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_log: Option<AccessLogConfig>,
+    /// Serves the metrics of the server when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<MetricsConfig>,
+    /// Serves the liveness and readiness endpoints when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<HealthConfig>,
+    /// Serves the admin API on its own port when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin: Option<AdminConfig>,
+    /// Records the received requests when set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub journal: Option<JournalConfig>,
+    /// Scenarios keyed by their name, see the routes' "scenario" attribute.
+    #[serde(default)]
+    pub scenarios: HashMap<String, ScenarioConfig>,
+    /// Routes keyed by their path, each with the responses for different request methods.
+    pub routes: HashMap<String, Vec<RouteConfig>>,
+    // the files included by the config file, they are watched for changes along with it
+    #[serde(skip)]
+    pub included_files: Vec<PathBuf>,
+    // the patterns of the includes with wildcards, watched for files starting or stopping to match them
+    #[serde(skip)]
+    pub include_patterns: Vec<PathBuf>
 }
 
 impl TryFrom<String> for ServerConfig {
-    type Error = String;
+    type Error = Error;
 
     fn try_from(s: String) -> Result<Self, Self::Error> {
-        let config = match serde_json::from_str::<Self>(&s) {
+        return Self::parse(&s, ConfigFormat::Json);
+    }
+}
+
+impl ServerConfig {
+    // finds the config file in the current directory
+    pub fn find_file() -> Option<PathBuf> {
+        return CONFIG_FILE_NAMES
+            .iter()
+            .map(PathBuf::from)
+            .find(|path| path.is_file());
+    }
+
+    // parses the config, resolving its includes relative to the current directory
+    pub fn parse(source: &str, format: ConfigFormat) -> Result<Self, Error> {
+        return Self::parse_in(source, format, Path::new("."));
+    }
+
+    fn parse_in(source: &str, format: ConfigFormat, dir: &Path) -> Result<Self, Error> {
+        let (value, positions) = match format.parse(source) {
+            Ok(parsed) => parsed,
+            Err(diagnostic) => return Err(Error::Validation(vec![diagnostic]))
+        };
+        let composed = match compose::compose(value, dir) {
+            Ok(composed) => composed,
+            Err(diagnostics) => return Err(Error::Validation(diagnostics))
+        };
+        let diagnostics = validation::validate(&composed.value, &positions);
+
+        if !diagnostics.is_empty() {
+            return Err(Error::Validation(diagnostics));
+        }
+
+        let mut config = match serde_json::from_value::<Self>(composed.value) {
             Ok(config) => config,
-            Err(err) => return Err(err.to_string())
+            Err(err) => return Err(Error::Config(err.to_string()))
         };
 
+        config.included_files = composed.included_files;
+        config.include_patterns = composed.include_patterns;
+        config.compile()?;
+
         return Ok(config);
     }
-}
 
-impl ServerConfig {
-    pub fn find_response_config(self: &Self, path: &String, method: &Method) -> Option<ResponseConfig> {
-        let result = match self.routes.get(path) {
-            Some(result) => result,
-            None => return None
+    fn compile(self: &mut Self) -> Result<(), Error> {
+        for route_configs in self.routes.values_mut() {
+            for route_config in route_configs {
+                route_config.response.compile().map_err(Error::Config)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    // replaces the route table with the given one, validating the resulting config the same way as a config file
+    pub fn with_routes(self: &Self, routes: Value) -> Result<Self, Error> {
+        let mut value = match serde_json::to_value(self) {
+            Ok(value) => value,
+            Err(err) => return Err(Error::Config(err.to_string()))
         };
 
-        for route_config in result {
-            if &route_config.method != method {
-                continue;
-            }
+        value["routes"] = routes;
+
+        let diagnostics = validation::validate(&value, &HashMap::new());
+
+        if !diagnostics.is_empty() {
+            return Err(Error::Validation(diagnostics));
+        }
+
+        let mut config = match serde_json::from_value::<Self>(value) {
+            Ok(config) => config,
+            Err(err) => return Err(Error::Config(err.to_string()))
+        };
+
+        config.included_files = self.included_files.clone();
+        config.include_patterns = self.include_patterns.clone();
+        config.compile()?;
+
+        return Ok(config);
+    }
+
+    pub fn read_from_file(path: &Path, format: ConfigFormat) -> Result<Self, Error> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => return Err(Error::Config(format!("Cannot read config file \"{}\", file not found.", path.display())))
+        };
+        let mut config_str = String::new();
+
+        if file.read_to_string(&mut config_str).is_err() {
+            return Err(Error::Config(format!("Cannot read config file \"{}\", insufficient permissions.", path.display())));
+        }
+
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new(".")
+        };
+
+        return Self::parse_in(&config_str, format, dir);
+    }
+
+    pub fn find_response_config(self: &Self, request: &Request, scenarios: &Scenarios) -> Option<ResponseConfig> {
+        return self
+            .find_route(request, &scenarios.lock())
+            .map(|(_, route_config)| route_config.response.clone());
+    }
+
+    // the current state of the scenario, None when there is no such scenario
+    pub fn scenario_state(self: &Self, name: &str, scenarios: &Scenarios) -> Option<String> {
+        let scenario = self.scenarios.get(name)?;
+
+        return Some(scenarios.get(name).unwrap_or(scenario.initial.clone()));
+    }
+
+    // every response of every route, including the ones of sequences and random responses,
+    // together with the key of the route it's in
+    pub fn responses(self: &Self) -> Vec<(&String, &ResponseConfig)> {
+        let mut responses = self.routes
+            .iter()
+            .flat_map(|(path, route_configs)| route_configs
+                .iter()
+                .map(move |route_config| (path, &route_config.response)))
+            .collect::<Vec<(&String, &ResponseConfig)>>();
+        let mut idx = 0;
 
-            return Some(route_config.response.clone());
+        while idx < responses.len() {
+            let (path, response_config) = responses[idx];
+
+            responses.extend(response_config.nested().into_iter().map(|nested| (path, nested)));
+            idx += 1;
+        }
+
+        return responses;
+    }
+
+    // finds the response for the request, together with the key of the route it was found in, the first
+    // response of the route matching the request's method, query and body in the current state of its scenario is used
+    pub fn find_route(self: &Self, request: &Request, scenarios: &LockedScenarios) -> Option<(String, &RouteConfig)> {
+        // the route at the exact path takes precedence over the path templates matching it,
+        // templates with fewer parameters being more specific than the others
+        let mut templates = self.routes
+            .iter()
+            .filter(|(route, _)| path::is_template(route) && path::match_path(route, &request.path).is_some())
+            .collect::<Vec<(&String, &Vec<RouteConfig>)>>();
+
+        templates.sort_by_key(|(route, _)| (route.matches('{').count(), route.as_str()));
+
+        let candidates = self.routes
+            .get_key_value(&request.path)
+            .into_iter()
+            .chain(templates);
+
+        for (route, route_configs) in candidates {
+            for route_config in route_configs {
+                if route_config.matches(request) && self.in_state(route_config, scenarios) {
+                    return Some((route.clone(), route_config));
+                }
+            }
         }
 
         return None;
     }
+
+    fn in_state(self: &Self, route_config: &RouteConfig, scenarios: &LockedScenarios) -> bool {
+        let (scenario, states) = match (&route_config.scenario, &route_config.states) {
+            (Some(scenario), Some(states)) => (scenario, states),
+            _ => return true
+        };
+        let state = match self.scenarios.get(scenario) {
+            Some(scenario_config) => scenarios.get(scenario).unwrap_or(scenario_config.initial.clone()),
+            None => return false
+        };
+
+        return states.contains(&state);
+    }
+}
+
+// the port can also be given as a string, so that it can be set from an environment variable
+fn deserialize_port<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Port {
+        Number(u16),
+        String(String)
+    }
+
+    return match Port::deserialize(deserializer)? {
+        Port::Number(port) => Ok(port),
+        Port::String(port) => port.trim().parse::<u16>().map_err(de::Error::custom)
+    };
 }
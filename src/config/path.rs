@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+// whether the route is a path template, with {name} segments matching any single path segment
+pub fn is_template(route: &str) -> bool {
+    return route.contains('{');
+}
+
+// matches the path against the route's template, returning the values of its parameters by name
+pub fn match_path(route: &str, path: &str) -> Option<HashMap<String, String>> {
+    let route_segments = route.split('/').collect::<Vec<&str>>();
+    let path_segments = path.split('/').collect::<Vec<&str>>();
+
+    if route_segments.len() != path_segments.len() {
+        return None;
+    }
+
+    let mut params = HashMap::new();
+
+    for (route_segment, path_segment) in route_segments.into_iter().zip(path_segments) {
+        match param_name(route_segment) {
+            Some(name) if !path_segment.is_empty() => {
+                params.insert(name.to_string(), path_segment.to_string());
+            },
+            Some(_) => return None,
+            None if route_segment == path_segment => (),
+            None => return None
+        };
+    }
+
+    return Some(params);
+}
+
+// the name of the parameter when the segment is a whole {name} one
+pub fn param_name(segment: &str) -> Option<&str> {
+    return segment
+        .strip_prefix('{')
+        .and_then(|segment| segment.strip_suffix('}'));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        return pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+    }
+
+    #[test]
+    fn matches_exact_paths() {
+        assert_eq!(match_path("/users", "/users"), Some(HashMap::new()));
+        assert_eq!(match_path("/users", "/users/"), None);
+        assert_eq!(match_path("/users", "/orders"), None);
+    }
+
+    #[test]
+    fn matches_parameters() {
+        assert_eq!(match_path("/users/{id}", "/users/42"), Some(params(&[("id", "42")])));
+        assert_eq!(
+            match_path("/users/{user}/orders/{order}", "/users/ada/orders/7"),
+            Some(params(&[("user", "ada"), ("order", "7")]))
+        );
+    }
+
+    #[test]
+    fn matches_parameters_to_single_segments() {
+        assert_eq!(match_path("/users/{id}", "/users/"), None);
+        assert_eq!(match_path("/users/{id}", "/users/42/orders"), None);
+        assert_eq!(match_path("/users/{id}", "/users"), None);
+    }
+
+    #[test]
+    fn finds_parameter_names() {
+        assert!(is_template("/users/{id}"));
+        assert!(!is_template("/users"));
+        assert_eq!(param_name("{id}"), Some("id"));
+        assert_eq!(param_name("{}"), Some(""));
+        assert_eq!(param_name("id"), None);
+        assert_eq!(param_name("{id"), None);
+    }
+}
@@ -0,0 +1,31 @@
+use schemars::{r#gen::{SchemaGenerator, SchemaSettings}, schema::{InstanceType, Schema, SchemaObject, SingleOrVec}};
+use serde_json::{Value, json};
+use crate::config::ServerConfig;
+
+// the JSON Schema of the config file, derived from the config types
+pub fn schema() -> Value {
+    let generator = SchemaSettings::draft07().into_generator();
+    let mut schema = serde_json::to_value(generator.into_root_schema_for::<ServerConfig>())
+        .unwrap();
+
+    // includes are resolved before the config is deserialized, so they are not part of the config types
+    if let Some(properties) = schema["properties"].as_object_mut() {
+        properties.insert("include".to_string(), json!({
+            "description": "Paths of config files to merge into this one, relative to its directory, globs are allowed.",
+            "anyOf": [
+                { "type": "string" },
+                { "type": "array", "items": { "type": "string" } }
+            ]
+        }));
+    }
+
+    return schema;
+}
+
+pub fn port_schema(_: &mut SchemaGenerator) -> Schema {
+    return SchemaObject {
+        instance_type: Some(SingleOrVec::Vec(vec![InstanceType::Integer, InstanceType::String])),
+        ..Default::default()
+    }
+    .into();
+}
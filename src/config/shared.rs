@@ -0,0 +1,34 @@
+use std::sync::{Arc, RwLock};
+use crate::config::ServerConfig;
+
+// handle to the config currently being served, it can be cloned and
+// shared between threads and swapped as a whole, so every request sees
+// either the old or the new route table but never a mix of both.
+#[derive(Clone)]
+pub struct SharedConfig(
+    Arc<RwLock<Arc<ServerConfig>>>
+);
+
+impl SharedConfig {
+    pub fn new(config: ServerConfig) -> Self {
+        return Self(Arc::new(RwLock::new(Arc::new(config))));
+    }
+
+    pub fn load(self: &Self) -> Arc<ServerConfig> {
+        let config = match self.0.read() {
+            Ok(config) => config,
+            Err(poisoned) => poisoned.into_inner()
+        };
+
+        return config.clone();
+    }
+
+    pub fn store(self: &Self, config: ServerConfig) {
+        let mut current = match self.0.write() {
+            Ok(current) => current,
+            Err(poisoned) => poisoned.into_inner()
+        };
+
+        *current = Arc::new(config);
+    }
+}
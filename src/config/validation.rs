@@ -0,0 +1,1376 @@
+use std::{collections::HashMap, fmt, fs, net::IpAddr, path::Path};
+use serde_json::{Map, Value};
+use crate::{http::{method::Method, headers::HeaderName, template::Template, script::CompiledScript}, config::{path, default_metrics_path, default_liveness_path, default_readiness_path}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize
+}
+
+// a single problem found in the config, pointing at the place it was found at
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub path: String,
+    pub position: Option<Position>,
+    pub message: String,
+    pub suggestion: Option<String>
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)?;
+        } else {
+            write!(f, "{}: {}", self.path, self.message)?;
+        }
+
+        if let Some(position) = self.position {
+            write!(f, " (line {}, column {})", position.line, position.column)?;
+        }
+
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, ", did you mean `{}`?", suggestion)?;
+        }
+
+        return Ok(());
+    }
+}
+
+// finds where every value of the JSON source starts, keyed by the value's path
+pub fn positions(source: &str) -> HashMap<String, Position> {
+    return Scanner::new(source).scan();
+}
+
+// validates already parsed config, the positions are used to point at the problems when known
+pub fn validate(value: &Value, positions: &HashMap<String, Position>) -> Vec<Diagnostic> {
+    let mut validator = Validator {
+        positions,
+        diagnostics: Vec::new()
+    };
+
+    validator.check_server(value);
+
+    return validator.diagnostics;
+}
+
+struct Validator<'a> {
+    positions: &'a HashMap<String, Position>,
+    diagnostics: Vec<Diagnostic>
+}
+
+impl<'a> Validator<'a> {
+    fn report(self: &mut Self, path: &str, message: String, suggestion: Option<String>) {
+        self.diagnostics.push(Diagnostic {
+            path: path.to_string(),
+            position: self.positions.get(path).copied(),
+            message,
+            suggestion
+        });
+    }
+
+    fn check_server(self: &mut Self, value: &Value) {
+        let object = match self.expect_object(value, "") {
+            Some(object) => object,
+            None => return
+        };
+
+        self.check_fields(object, "", &["port", "defaults", "logging", "access_log", "metrics", "health", "admin", "journal", "scenarios", "routes"], &["port", "routes"]);
+
+        let mut ports = Vec::new();
+
+        if let Some(port) = object.get("port") {
+            if let Some(port) = self.check_port(port, "port") {
+                ports.push(("port".to_string(), port));
+            }
+        }
+
+        if let Some(admin) = object.get("admin") {
+            if let Some(port) = self.check_admin(admin, "admin") {
+                ports.push(("admin.port".to_string(), port));
+            }
+        }
+
+        self.check_port_conflicts(&ports);
+
+        if let Some(defaults) = object.get("defaults") {
+            self.check_defaults(defaults, "defaults");
+        }
+
+        if let Some(logging) = object.get("logging") {
+            self.check_logging(logging, "logging");
+        }
+
+        if let Some(access_log) = object.get("access_log") {
+            self.check_access_log(access_log, "access_log");
+        }
+
+        if let Some(metrics) = object.get("metrics") {
+            self.check_metrics(metrics, "metrics", object.get("routes"));
+        }
+
+        if let Some(health) = object.get("health") {
+            self.check_health(health, "health", object.get("routes"));
+        }
+
+        if let Some(journal) = object.get("journal") {
+            self.check_journal(journal, "journal");
+        }
+
+        let scenarios = match object.get("scenarios") {
+            Some(scenarios) => self.check_scenarios(scenarios, "scenarios"),
+            None => Vec::new()
+        };
+
+        if let Some(routes) = object.get("routes") {
+            self.check_routes(routes, "routes", &scenarios);
+        }
+    }
+
+    // checks the scenarios, returning the names of all of them
+    fn check_scenarios(self: &mut Self, value: &Value, path: &str) -> Vec<String> {
+        let scenarios = match self.expect_object(value, path) {
+            Some(scenarios) => scenarios,
+            None => return Vec::new()
+        };
+
+        for (name, scenario) in scenarios {
+            let scenario_path = field_path(path, name);
+
+            if let Some(scenario) = self.expect_object(scenario, &scenario_path) {
+                self.check_fields(scenario, &scenario_path, &["initial"], &[]);
+                self.expect_string(scenario.get("initial"), &field_path(&scenario_path, "initial"));
+            }
+        }
+
+        return scenarios.keys().cloned().collect();
+    }
+
+    fn check_logging(self: &mut Self, value: &Value, path: &str) {
+        let object = match self.expect_object(value, path) {
+            Some(object) => object,
+            None => return
+        };
+        let levels = ["debug", "info", "warn", "error"];
+
+        self.check_fields(object, path, &["level", "modules", "output", "style", "format", "color", "rotation"], &[]);
+
+        if let Some(level) = object.get("level") {
+            self.check_enum(level, &field_path(path, "level"), "log level", &levels);
+        }
+
+        if let Some(modules) = object.get("modules") {
+            let modules_path = field_path(path, "modules");
+
+            if let Some(modules) = self.expect_object(modules, &modules_path) {
+                for (module, level) in modules {
+                    self.check_enum(level, &field_path(&modules_path, module), "log level", &levels);
+                }
+            }
+        }
+
+        if let Some(style) = object.get("style") {
+            self.check_enum(style, &field_path(path, "style"), "log style", &["plain", "syslog"]);
+        }
+
+        if let Some(color) = object.get("color") {
+            self.check_enum(color, &field_path(path, "color"), "color setting", &["auto", "always", "never"]);
+        }
+
+        self.expect_string(object.get("output"), &field_path(path, "output"));
+        self.expect_string(object.get("format"), &field_path(path, "format"));
+
+        if let Some(rotation) = object.get("rotation") {
+            self.check_rotation(rotation, &field_path(path, "rotation"));
+        }
+    }
+
+    fn check_rotation(self: &mut Self, value: &Value, path: &str) {
+        let object = match self.expect_object(value, path) {
+            Some(object) => object,
+            None => return
+        };
+
+        self.check_fields(object, path, &["max_size", "interval", "keep"], &[]);
+        self.expect_number(object.get("max_size"), &field_path(path, "max_size"));
+        self.expect_number(object.get("keep"), &field_path(path, "keep"));
+
+        if let Some(interval) = object.get("interval") {
+            self.check_enum(interval, &field_path(path, "interval"), "rotation interval", &["hourly", "daily"]);
+        }
+    }
+
+    fn check_access_log(self: &mut Self, value: &Value, path: &str) {
+        let object = match self.expect_object(value, path) {
+            Some(object) => object,
+            None => return
+        };
+
+        self.check_fields(object, path, &["format", "output"], &[]);
+        self.expect_string(object.get("format"), &field_path(path, "format"));
+        self.expect_string(object.get("output"), &field_path(path, "output"));
+    }
+
+    fn check_metrics(self: &mut Self, value: &Value, path: &str, routes: Option<&Value>) {
+        let object = match self.expect_object(value, path) {
+            Some(object) => object,
+            None => return
+        };
+
+        self.check_fields(object, path, &["path"], &[]);
+        self.check_endpoint(object.get("path"), &field_path(path, "path"), default_metrics_path(), "metrics", routes);
+    }
+
+    fn check_health(self: &mut Self, value: &Value, path: &str, routes: Option<&Value>) {
+        let object = match self.expect_object(value, path) {
+            Some(object) => object,
+            None => return
+        };
+
+        self.check_fields(object, path, &["liveness_path", "readiness_path", "drain_seconds"], &[]);
+        self.check_endpoint(object.get("liveness_path"), &field_path(path, "liveness_path"), default_liveness_path(), "liveness", routes);
+        self.check_endpoint(object.get("readiness_path"), &field_path(path, "readiness_path"), default_readiness_path(), "readiness", routes);
+        self.expect_number(object.get("drain_seconds"), &field_path(path, "drain_seconds"));
+    }
+
+    // checks the path of a built-in endpoint, they are matched before the routes,
+    // so a route at the same path would never be served
+    fn check_endpoint(self: &mut Self, value: Option<&Value>, path: &str, default: String, name: &str, routes: Option<&Value>) {
+        let endpoint_path = match value {
+            Some(value) => match self.expect_string(Some(value), path) {
+                Some(endpoint_path) => endpoint_path.to_string(),
+                None => return
+            },
+            None => default
+        };
+
+        if !endpoint_path.starts_with('/') {
+            self.report(path, format!("path `{}` must start with `/`", endpoint_path), None);
+        }
+
+        if routes.and_then(|routes| routes.get(&endpoint_path)).is_some() {
+            self.report(
+                &field_path("routes", &endpoint_path),
+                format!("route `{}` conflicts with the {} endpoint", endpoint_path, name),
+                None
+            );
+        }
+    }
+
+    // returns the admin port, so that it can be checked for conflicts with the other ports
+    fn check_admin(self: &mut Self, value: &Value, path: &str) -> Option<u16> {
+        let object = self.expect_object(value, path)?;
+
+        self.check_fields(object, path, &["port", "host", "allow_exec_responses"], &["port"]);
+        self.expect_bool(object.get("allow_exec_responses"), &field_path(path, "allow_exec_responses"));
+
+        let host_path = field_path(path, "host");
+
+        if let Some(host) = self.expect_string(object.get("host"), &host_path) {
+            if host.parse::<IpAddr>().is_err() {
+                self.report(&host_path, format!("host `{}` must be an IP address, like 127.0.0.1", host), None);
+            }
+        }
+
+        return match object.get("port") {
+            Some(port) => self.check_port(port, &field_path(path, "port")),
+            None => None
+        };
+    }
+
+    fn check_journal(self: &mut Self, value: &Value, path: &str) {
+        let object = match self.expect_object(value, path) {
+            Some(object) => object,
+            None => return
+        };
+
+        self.check_fields(object, path, &["capacity"], &[]);
+        self.expect_number(object.get("capacity"), &field_path(path, "capacity"));
+    }
+
+    fn check_defaults(self: &mut Self, value: &Value, path: &str) {
+        let object = match self.expect_object(value, path) {
+            Some(object) => object,
+            None => return
+        };
+
+        self.check_fields(object, path, &["http", "faults"], &[]);
+
+        if let Some(http) = object.get("http") {
+            self.check_http(http, &field_path(path, "http"));
+        }
+
+        if let Some(faults) = object.get("faults") {
+            self.check_faults(faults, &field_path(path, "faults"));
+        }
+    }
+
+    fn check_faults(self: &mut Self, value: &Value, path: &str) {
+        let object = match self.expect_object(value, path) {
+            Some(object) => object,
+            None => return
+        };
+
+        self.check_fields(object, path, &["delay", "bandwidth", "drop", "truncate", "garbage", "reset"], &[]);
+
+        for (name, fault) in object {
+            let fault_path = field_path(path, name);
+            let fault = match self.expect_object(fault, &fault_path) {
+                Some(fault) => fault,
+                None => continue
+            };
+
+            match name.as_str() {
+                "delay" => {
+                    self.check_fields(fault, &fault_path, &["ms", "jitter_ms", "probability"], &["ms"]);
+                    self.expect_number(fault.get("ms"), &field_path(&fault_path, "ms"));
+                    self.expect_number(fault.get("jitter_ms"), &field_path(&fault_path, "jitter_ms"));
+                },
+                "bandwidth" => {
+                    let bandwidth_path = field_path(&fault_path, "bytes_per_second");
+
+                    self.check_fields(fault, &fault_path, &["bytes_per_second", "probability"], &["bytes_per_second"]);
+
+                    match fault.get("bytes_per_second").map(|bandwidth| bandwidth.as_u64()) {
+                        Some(Some(0)) => self.report(&bandwidth_path, "bandwidth must be above 0".to_string(), None),
+                        Some(None) => self.report(&bandwidth_path, "expected a positive number".to_string(), None),
+                        _ => ()
+                    };
+                },
+                "drop" | "truncate" | "garbage" | "reset" => self.check_fields(fault, &fault_path, &["probability"], &[]),
+                // unknown faults are reported by check_fields
+                _ => continue
+            };
+
+            if let Some(probability) = fault.get("probability") {
+                if !probability.as_f64().is_some_and(|probability| (0.0..=1.0).contains(&probability)) {
+                    self.report(&field_path(&fault_path, "probability"), "probability must be a number from 0 to 1".to_string(), None);
+                }
+            }
+        }
+    }
+
+    fn check_port(self: &mut Self, value: &Value, path: &str) -> Option<u16> {
+        // ports given as strings come from environment variables
+        let port = match value {
+            Value::String(port) => port.trim().parse::<u64>().ok(),
+            value => value.as_u64()
+        };
+        let port = match port {
+            Some(port) => port,
+            None => {
+                self.report(path, "port must be a number".to_string(), None);
+                return None;
+            }
+        };
+
+        if port == 0 {
+            self.report(path, "port 0 is not allowed".to_string(), None);
+            return None;
+        }
+
+        if port > u16::MAX as u64 {
+            self.report(path, format!("port {} is out of range, the largest port is {}", port, u16::MAX), None);
+            return None;
+        }
+
+        return Some(port as u16);
+    }
+
+    fn check_port_conflicts(self: &mut Self, ports: &[(String, u16)]) {
+        for (idx, (path, port)) in ports.iter().enumerate() {
+            let conflict = ports[0..idx]
+                .iter()
+                .find(|(_, other)| other == port);
+
+            if let Some((other_path, _)) = conflict {
+                self.report(path, format!("port {} is already used by `{}`", port, other_path), None);
+            }
+        }
+    }
+
+    fn check_routes(self: &mut Self, value: &Value, path: &str, scenarios: &[String]) {
+        let routes = match self.expect_object(value, path) {
+            Some(routes) => routes,
+            None => return
+        };
+
+        for (key, route_configs) in routes {
+            let route_path = field_path(path, key);
+
+            if !key.starts_with('/') {
+                self.report(&route_path, format!("route `{}` must start with `/`", key), Some(format!("/{}", key)));
+            }
+
+            // path template parameters must be whole segments, like /users/{id}
+            let has_partial_param = key
+                .split('/')
+                .any(|segment| segment.contains(['{', '}']) && path::param_name(segment).is_none_or(|name| name.is_empty() || name.contains(['{', '}'])));
+
+            if has_partial_param {
+                self.report(&route_path, format!("route `{}` has a parameter that is not a whole `{{name}}` segment", key), None);
+            }
+
+            let route_configs = match route_configs.as_array() {
+                Some(route_configs) => route_configs,
+                None => {
+                    self.report(&route_path, "route must be a list of method and response pairs".to_string(), None);
+                    continue;
+                }
+            };
+            // responses differing only in their method, query, body or scenario states are told apart by them
+            let mut seen_requests: HashMap<String, String> = HashMap::new();
+
+            for (idx, route_config) in route_configs.iter().enumerate() {
+                let config_path = index_path(&route_path, idx);
+                let method = self.check_route_config(route_config, &config_path, scenarios);
+
+                if let Some(method) = method {
+                    let method_path = field_path(&config_path, "method");
+                    let request_key = ["query", "body", "scenario", "states"]
+                        .iter()
+                        .map(|field| route_config.get(field).map(|value| value.to_string()).unwrap_or_default())
+                        .fold(method.clone(), |key, value| format!("{} {}", key, value));
+
+                    match seen_requests.get(&request_key) {
+                        Some(first_path) => {
+                            let message = format!("method {} is already handled by `{}` on this path", method, first_path);
+                            self.report(&method_path, message, None);
+                        },
+                        None => { seen_requests.insert(request_key, config_path); }
+                    };
+                }
+            }
+        }
+    }
+
+    // checks a single route config, returning its method when it is valid
+    fn check_route_config(self: &mut Self, value: &Value, path: &str, scenarios: &[String]) -> Option<String> {
+        let object = match self.expect_object(value, path) {
+            Some(object) => object,
+            None => return None
+        };
+
+        self.check_fields(object, path, &["method", "query", "body", "scenario", "states", "next_state", "faults", "response"], &["method", "response"]);
+        self.expect_string(object.get("body"), &field_path(path, "body"));
+        self.check_route_scenario(object, path, scenarios);
+
+        if let Some(faults) = object.get("faults") {
+            self.check_faults(faults, &field_path(path, "faults"));
+        }
+
+        if let Some(query) = object.get("query") {
+            let query_path = field_path(path, "query");
+
+            if let Some(query) = self.expect_object(query, &query_path) {
+                for (key, value) in query {
+                    self.expect_string(Some(value), &field_path(&query_path, key));
+                }
+            }
+        }
+
+        if let Some(response) = object.get("response") {
+            self.check_response(response, &field_path(path, "response"));
+        }
+
+        let method = match object.get("method") {
+            Some(method) => method,
+            None => return None
+        };
+
+        return self.check_method(method, &field_path(path, "method"));
+    }
+
+    fn check_route_scenario(self: &mut Self, object: &Map<String, Value>, path: &str, scenarios: &[String]) {
+        let scenario_path = field_path(path, "scenario");
+        let scenario = self.expect_string(object.get("scenario"), &scenario_path);
+
+        if let Some(scenario) = scenario {
+            if !scenarios.iter().any(|name| name == scenario) {
+                self.report(&scenario_path, format!("scenario `{}` is not defined in `scenarios`", scenario), suggest(scenario, scenarios));
+            }
+        }
+
+        if let Some(states) = object.get("states") {
+            let states_path = field_path(path, "states");
+
+            match states.as_array() {
+                Some(states) => states
+                    .iter()
+                    .enumerate()
+                    .for_each(|(idx, state)| { self.expect_string(Some(state), &index_path(&states_path, idx)); }),
+                None => self.report(&states_path, "expected a list of states".to_string(), None)
+            };
+        }
+
+        let next_state_path = field_path(path, "next_state");
+
+        self.expect_string(object.get("next_state"), &next_state_path);
+
+        if !object.contains_key("scenario") {
+            for field in ["states", "next_state"] {
+                if object.contains_key(field) {
+                    self.report(&field_path(path, field), format!("`{}` needs the `scenario` it refers to", field), None);
+                }
+            }
+        }
+    }
+
+    fn check_method(self: &mut Self, value: &Value, path: &str) -> Option<String> {
+        let method = match value.as_str() {
+            Some(method) => method,
+            None => {
+                self.report(path, "method must be a string".to_string(), None);
+                return None;
+            }
+        };
+
+        if Method::try_from(method.to_string()).is_ok() {
+            return Some(method.to_string());
+        }
+
+        let known = Method::all()
+            .into_iter()
+            .map(|method| method.into())
+            .collect::<Vec<String>>();
+
+        self.report(path, format!("unknown method `{}`", method), suggest(method, &known));
+
+        return None;
+    }
+
+    fn check_response(self: &mut Self, value: &Value, path: &str) {
+        let object = match self.expect_object(value, path) {
+            Some(object) => object,
+            None => return
+        };
+        let response_type = match object.get("type") {
+            Some(Value::String(response_type)) => response_type.as_str(),
+            Some(_) => {
+                self.report(&field_path(path, "type"), "response type must be a string".to_string(), None);
+                return;
+            },
+            None => {
+                self.report(path, "missing field `type`".to_string(), None);
+                return;
+            }
+        };
+
+        match response_type {
+            "text" => {
+                self.check_fields(object, path, &["type", "text", "templated", "http"], &["type", "text"]);
+
+                let text_path = field_path(path, "text");
+                let text = self.expect_string(object.get("text"), &text_path);
+
+                if let (Some(text), true) = (text, self.expect_bool(object.get("templated"), &field_path(path, "templated"))) {
+                    self.check_template(text, &text_path);
+                }
+            },
+            "json" => {
+                self.check_fields(object, path, &["type", "body", "pretty", "templated", "http"], &["type", "body"]);
+                self.expect_bool(object.get("pretty"), &field_path(path, "pretty"));
+
+                if let (Some(body), true) = (object.get("body"), self.expect_bool(object.get("templated"), &field_path(path, "templated"))) {
+                    self.check_json_templates(body, &field_path(path, "body"));
+                }
+            },
+            "file" => {
+                self.check_fields(object, path, &["type", "source", "templated", "http"], &["type", "source"]);
+
+                let source_path = field_path(path, "source");
+                let templated = self.expect_bool(object.get("templated"), &field_path(path, "templated"));
+
+                if let Some(source) = self.expect_string(object.get("source"), &source_path) {
+                    if !Path::new(source).is_file() {
+                        self.report(&source_path, format!("file `{}` does not exist", source), None);
+                    } else if let (true, Ok(template)) = (templated, fs::read_to_string(source)) {
+                        self.check_template(&template, &source_path);
+                    }
+                }
+            },
+            "proxy" => {
+                self.check_fields(object, path, &["type", "upstream", "http"], &["type", "upstream"]);
+
+                let upstream_path = field_path(path, "upstream");
+
+                if let Some(upstream) = self.expect_string(object.get("upstream"), &upstream_path) {
+                    if !upstream.starts_with("http://") && !upstream.starts_with("https://") {
+                        self.report(&upstream_path, format!("upstream `{}` must be an http:// or https:// URL", upstream), None);
+                    }
+                }
+            },
+            "cgi" => {
+                self.check_fields(object, path, &["type", "command", "args", "working_dir", "timeout_ms", "env", "inherit_env", "http"], &["type", "command"]);
+
+                let command_path = field_path(path, "command");
+
+                // plain names are looked up in PATH when the script runs, paths must exist already
+                if let Some(command) = self.expect_string(object.get("command"), &command_path) {
+                    if command.contains('/') && !Path::new(command).is_file() {
+                        self.report(&command_path, format!("file `{}` does not exist", command), None);
+                    }
+                }
+
+                if let Some(args) = object.get("args") {
+                    let args_path = field_path(path, "args");
+
+                    match args.as_array() {
+                        Some(args) => args
+                            .iter()
+                            .enumerate()
+                            .for_each(|(idx, arg)| { self.expect_string(Some(arg), &index_path(&args_path, idx)); }),
+                        None => self.report(&args_path, "expected a list of arguments".to_string(), None)
+                    };
+                }
+
+                let working_dir_path = field_path(path, "working_dir");
+
+                if let Some(working_dir) = self.expect_string(object.get("working_dir"), &working_dir_path) {
+                    if !Path::new(working_dir).is_dir() {
+                        self.report(&working_dir_path, format!("directory `{}` does not exist", working_dir), None);
+                    }
+                }
+
+                self.check_timeout(object.get("timeout_ms"), &field_path(path, "timeout_ms"));
+
+                if let Some(env) = object.get("env") {
+                    self.check_string_map(env, &field_path(path, "env"));
+                }
+
+                self.expect_bool(object.get("inherit_env"), &field_path(path, "inherit_env"));
+            },
+            "fastcgi" => {
+                self.check_fields(object, path, &["type", "upstream", "script_filename", "timeout_ms", "params", "http"], &["type", "upstream"]);
+
+                let upstream_path = field_path(path, "upstream");
+
+                if let Some(upstream) = self.expect_string(object.get("upstream"), &upstream_path) {
+                    let is_valid = match upstream.strip_prefix("unix:") {
+                        Some(socket) => !socket.is_empty(),
+                        None => !upstream.contains("://") && upstream
+                            .rsplit_once(':')
+                            .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+                    };
+
+                    if !is_valid {
+                        self.report(&upstream_path, format!("upstream `{}` must be a host:port address or a unix:<path> socket", upstream), None);
+                    }
+                }
+
+                self.expect_string(object.get("script_filename"), &field_path(path, "script_filename"));
+                self.check_timeout(object.get("timeout_ms"), &field_path(path, "timeout_ms"));
+
+                if let Some(params) = object.get("params") {
+                    self.check_string_map(params, &field_path(path, "params"));
+                }
+            },
+            "script" => {
+                self.check_fields(object, path, &["type", "script", "source", "timeout_ms", "http"], &["type"]);
+                self.check_timeout(object.get("timeout_ms"), &field_path(path, "timeout_ms"));
+
+                let script_path = field_path(path, "script");
+                let source_path = field_path(path, "source");
+                let script = self.expect_string(object.get("script"), &script_path);
+                let source = self.expect_string(object.get("source"), &source_path);
+                let code = match (script, source) {
+                    (Some(_), Some(_)) => {
+                        self.report(path, "expected either `script` or `source`, not both".to_string(), None);
+                        None
+                    },
+                    (Some(script), None) => Some((script.to_string(), script_path)),
+                    (None, Some(source)) => match fs::read_to_string(source) {
+                        Ok(code) => Some((code, source_path)),
+                        Err(_) => {
+                            self.report(&source_path, format!("file `{}` does not exist", source), None);
+                            None
+                        }
+                    },
+                    (None, None) => {
+                        if !object.contains_key("script") && !object.contains_key("source") {
+                            self.report(path, "expected the `script` or the `source` of the script".to_string(), None);
+                        }
+
+                        None
+                    }
+                };
+
+                if !cfg!(feature = "script") {
+                    self.report(&field_path(path, "type"), "script responses need quickserving built with the `script` feature".to_string(), None);
+                } else if let Some((code, code_path)) = code {
+                    if let Err(err) = CompiledScript::compile(&code) {
+                        self.report(&code_path, format!("invalid script, {}", err), None);
+                    }
+                }
+            },
+            "echo" => {
+                self.check_fields(object, path, &["type", "format", "http"], &["type"]);
+
+                if let Some(format) = object.get("format") {
+                    self.check_enum(format, &field_path(path, "format"), "echo format", &["json", "raw"]);
+                }
+            },
+            "sequence" => {
+                self.check_fields(object, path, &["type", "responses", "then"], &["type", "responses"]);
+
+                if let Some(then) = object.get("then") {
+                    self.check_enum(then, &field_path(path, "then"), "sequence end", &["last", "cycle"]);
+                }
+
+                for (response_path, response) in self.expect_responses(object.get("responses"), &field_path(path, "responses")) {
+                    self.check_response(response, &response_path);
+                }
+
+                return;
+            },
+            "random" => {
+                self.check_fields(object, path, &["type", "responses", "seed"], &["type", "responses"]);
+                self.expect_number(object.get("seed"), &field_path(path, "seed"));
+
+                let responses = self.expect_responses(object.get("responses"), &field_path(path, "responses"));
+                let mut total_weight = Some(0u64);
+
+                for (weighted_path, weighted) in &responses {
+                    let weighted = match self.expect_object(weighted, weighted_path) {
+                        Some(weighted) => weighted,
+                        None => continue
+                    };
+
+                    self.check_fields(weighted, weighted_path, &["weight", "response"], &["response"]);
+                    self.expect_number(weighted.get("weight"), &field_path(weighted_path, "weight"));
+                    let weight = weighted.get("weight").map(|weight| weight.as_u64().unwrap_or(0)).unwrap_or(1);
+                    total_weight = total_weight.and_then(|total_weight| total_weight.checked_add(weight));
+
+                    if let Some(response) = weighted.get("response") {
+                        self.check_response(response, &field_path(weighted_path, "response"));
+                    }
+                }
+
+                match total_weight {
+                    None => self.report(&field_path(path, "responses"), format!("the weights must add up to at most {}", u64::MAX), None),
+                    Some(0) if !responses.is_empty() => self.report(&field_path(path, "responses"), "at least one response must have a weight above 0".to_string(), None),
+                    _ => {}
+                };
+
+                return;
+            },
+            other => {
+                let known = ["text".to_string(), "json".to_string(), "file".to_string(), "proxy".to_string(), "cgi".to_string(), "fastcgi".to_string(), "script".to_string(), "echo".to_string(), "sequence".to_string(), "random".to_string()];
+
+                self.report(&field_path(path, "type"), format!("unknown response type `{}`", other), suggest(other, &known));
+                return;
+            }
+        };
+
+        if let Some(http) = object.get("http") {
+            self.check_http(http, &field_path(path, "http"));
+        }
+    }
+
+    fn check_timeout(self: &mut Self, value: Option<&Value>, path: &str) {
+        self.expect_number(value, path);
+
+        if value.and_then(Value::as_u64) == Some(0) {
+            self.report(path, "timeout must be above 0".to_string(), None);
+        }
+    }
+
+    // an object of strings, like the variables of a cgi response
+    fn check_string_map(self: &mut Self, value: &Value, path: &str) {
+        if let Some(object) = self.expect_object(value, path) {
+            for (name, value) in object {
+                self.expect_string(Some(value), &field_path(path, name));
+            }
+        }
+    }
+
+    fn check_template(self: &mut Self, template: &str, path: &str) {
+        if let Err(err) = Template::parse(template) {
+            self.report(path, format!("invalid template, {}", err), None);
+        }
+    }
+
+    // every string in a templated JSON body is a template of its own
+    fn check_json_templates(self: &mut Self, value: &Value, path: &str) {
+        match value {
+            Value::String(template) => self.check_template(template, path),
+            Value::Array(items) => for (index, item) in items.iter().enumerate() {
+                self.check_json_templates(item, &index_path(path, index));
+            },
+            Value::Object(fields) => for (key, field) in fields {
+                self.check_json_templates(field, &field_path(path, key));
+            },
+            _ => {}
+        };
+    }
+
+    fn check_http(self: &mut Self, value: &Value, path: &str) {
+        let object = match self.expect_object(value, path) {
+            Some(object) => object,
+            None => return
+        };
+
+        self.check_fields(object, path, &["status", "headers", "raw_headers"], &[]);
+
+        if let Some(status) = object.get("status") {
+            let status_path = field_path(path, "status");
+
+            match status.as_u64() {
+                Some(100..=599) => (),
+                Some(status) => self.report(&status_path, format!("status {} is not between 100 and 599", status), None),
+                None => self.report(&status_path, "status must be a number".to_string(), None)
+            };
+        }
+
+        if let Some(headers) = object.get("headers") {
+            self.check_headers(headers, &field_path(path, "headers"));
+        }
+
+        if let Some(raw_headers) = object.get("raw_headers") {
+            self.check_raw_headers(raw_headers, &field_path(path, "raw_headers"));
+        }
+    }
+
+    fn check_raw_headers(self: &mut Self, value: &Value, path: &str) {
+        let raw_headers = match value.as_array() {
+            Some(raw_headers) => raw_headers,
+            None => {
+                self.report(path, "raw headers must be a list of name and value pairs".to_string(), None);
+                return;
+            }
+        };
+
+        for (idx, raw_header) in raw_headers.iter().enumerate() {
+            let header_path = index_path(path, idx);
+            let object = match self.expect_object(raw_header, &header_path) {
+                Some(object) => object,
+                None => continue
+            };
+
+            self.check_fields(object, &header_path, &["name", "value"], &["name", "value"]);
+            self.expect_string(object.get("value"), &field_path(&header_path, "value"));
+
+            if let Some(name) = self.expect_string(object.get("name"), &field_path(&header_path, "name")) {
+                if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == ':') {
+                    self.report(&field_path(&header_path, "name"), format!("invalid header name `{}`", name), None);
+                }
+            }
+        }
+    }
+
+    fn check_headers(self: &mut Self, value: &Value, path: &str) {
+        let headers = match self.expect_object(value, path) {
+            Some(headers) => headers,
+            None => return
+        };
+        let known = HeaderName::all()
+            .into_iter()
+            .map(|name| name.into())
+            .collect::<Vec<String>>();
+
+        for (name, header_value) in headers {
+            let header_path = field_path(path, name);
+
+            if HeaderName::try_from(name.clone()).is_err() {
+                self.report(&header_path, format!("unknown header name `{}`", name), suggest(name, &known));
+            }
+
+            if !header_value.is_string() {
+                self.report(&header_path, "header value must be a string".to_string(), None);
+            }
+        }
+    }
+
+    // reports unknown and missing fields of an object
+    fn check_fields(self: &mut Self, object: &Map<String, Value>, path: &str, known: &[&str], required: &[&str]) {
+        let known_strings = known
+            .iter()
+            .map(|field| field.to_string())
+            .collect::<Vec<String>>();
+
+        for key in object.keys() {
+            if known.contains(&key.as_str()) {
+                continue;
+            }
+
+            self.report(&field_path(path, key), format!("unknown field `{}`", key), suggest(key, &known_strings));
+        }
+
+        for field in required {
+            if object.contains_key(*field) {
+                continue;
+            }
+
+            self.report(path, format!("missing field `{}`", field), None);
+        }
+    }
+
+    // checks that the value is one of the known strings
+    fn check_enum(self: &mut Self, value: &Value, path: &str, what: &str, known: &[&str]) {
+        let string = match value.as_str() {
+            Some(string) => string,
+            None => {
+                self.report(path, format!("{} must be a string", what), None);
+                return;
+            }
+        };
+
+        if known.contains(&string) {
+            return;
+        }
+
+        let known = known
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<String>>();
+
+        self.report(path, format!("unknown {} `{}`", what, string), suggest(string, &known));
+    }
+
+    fn expect_number(self: &mut Self, value: Option<&Value>, path: &str) {
+        if let Some(value) = value {
+            if !value.is_u64() {
+                self.report(path, "expected a positive number".to_string(), None);
+            }
+        }
+    }
+
+    // the responses of a sequence or random response with their paths, there must be at least one
+    fn expect_responses<'v>(self: &mut Self, value: Option<&'v Value>, path: &str) -> Vec<(String, &'v Value)> {
+        let responses = match value {
+            Some(Value::Array(responses)) => responses,
+            Some(_) => {
+                self.report(path, "expected a list of responses".to_string(), None);
+                return Vec::new();
+            },
+            // missing fields are reported by check_fields
+            None => return Vec::new()
+        };
+
+        if responses.is_empty() {
+            self.report(path, "expected at least one response".to_string(), None);
+        }
+
+        return responses
+            .iter()
+            .enumerate()
+            .map(|(idx, response)| (index_path(path, idx), response))
+            .collect();
+    }
+
+    // returns whether the flag is set, missing flags are not
+    fn expect_bool(self: &mut Self, value: Option<&Value>, path: &str) -> bool {
+        return match value {
+            Some(Value::Bool(flag)) => *flag,
+            Some(_) => {
+                self.report(path, "expected true or false".to_string(), None);
+                false
+            },
+            None => false
+        };
+    }
+
+    fn expect_object<'v>(self: &mut Self, value: &'v Value, path: &str) -> Option<&'v Map<String, Value>> {
+        let object = value.as_object();
+
+        if object.is_none() {
+            self.report(path, "expected an object".to_string(), None);
+        }
+
+        return object;
+    }
+
+    fn expect_string<'v>(self: &mut Self, value: Option<&'v Value>, path: &str) -> Option<&'v str> {
+        let value = match value {
+            Some(value) => value,
+            // missing fields are reported by check_fields
+            None => return None
+        };
+        let string = value.as_str();
+
+        if string.is_none() {
+            self.report(path, "expected a string".to_string(), None);
+        }
+
+        return string;
+    }
+}
+
+pub fn field_path(parent: &str, key: &str) -> String {
+    let is_identifier = !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    return match (parent.is_empty(), is_identifier) {
+        (true, true) => key.to_string(),
+        (false, true) => format!("{}.{}", parent, key),
+        (_, false) => format!("{}[{:?}]", parent, key)
+    };
+}
+
+pub fn index_path(parent: &str, idx: usize) -> String {
+    return format!("{}[{}]", parent, idx);
+}
+
+// finds the known value closest to the given one, if it is close enough to be a typo
+pub fn suggest(value: &str, known: &[String]) -> Option<String> {
+    let lowercase = value.to_lowercase();
+    let max_distance = (value.chars().count() / 3).max(2);
+
+    return known
+        .iter()
+        .map(|candidate| (edit_distance(&lowercase, &candidate.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.clone());
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<char>>();
+    let b = b.chars().collect::<Vec<char>>();
+    let mut previous = (0..=b.len()).collect::<Vec<usize>>();
+
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+        }
+
+        previous = current;
+    }
+
+    return previous[b.len()];
+}
+
+// walks the JSON source remembering where every value starts, so that problems
+// found in the parsed config can be pointed at in the file.
+// object members point at their key, as that's where the typos usually are.
+struct Scanner {
+    chars: Vec<char>,
+    idx: usize,
+    line: usize,
+    column: usize,
+    positions: HashMap<String, Position>
+}
+
+impl Scanner {
+    fn new(source: &str) -> Self {
+        return Self {
+            chars: source.chars().collect(),
+            idx: 0,
+            line: 1,
+            column: 1,
+            positions: HashMap::new()
+        };
+    }
+
+    fn scan(self: Self) -> HashMap<String, Position> {
+        let mut scanner = self;
+
+        scanner.skip_whitespace();
+        scanner.scan_value(String::new());
+
+        return scanner.positions;
+    }
+
+    fn position(self: &Self) -> Position {
+        return Position { line: self.line, column: self.column };
+    }
+
+    fn peek(self: &Self) -> Option<char> {
+        return self.chars.get(self.idx).copied();
+    }
+
+    fn advance(self: &mut Self) -> Option<char> {
+        let c = self.peek()?;
+
+        self.idx += 1;
+
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        return Some(c);
+    }
+
+    fn skip_whitespace(self: &mut Self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+
+            self.advance();
+        }
+    }
+
+    fn scan_value(self: &mut Self, path: String) {
+        let position = self.position();
+
+        self.positions.entry(path.clone()).or_insert(position);
+
+        match self.peek() {
+            Some('{') => self.scan_object(path),
+            Some('[') => self.scan_array(path),
+            Some('"') => { self.scan_string(); },
+            _ => {
+                while let Some(c) = self.peek() {
+                    if c == ',' || c == '}' || c == ']' || c.is_whitespace() {
+                        break;
+                    }
+
+                    self.advance();
+                }
+            }
+        };
+    }
+
+    fn scan_object(self: &mut Self, path: String) {
+        self.advance();
+        self.skip_whitespace();
+
+        while let Some(c) = self.peek() {
+            if c == '}' {
+                self.advance();
+                return;
+            }
+
+            if c == ',' {
+                self.advance();
+                self.skip_whitespace();
+                continue;
+            }
+
+            let key_position = self.position();
+            let key = self.scan_string();
+            let member_path = field_path(&path, &key);
+
+            self.positions.insert(member_path.clone(), key_position);
+            self.skip_whitespace();
+            // the colon
+            self.advance();
+            self.skip_whitespace();
+            self.scan_value(member_path);
+            self.skip_whitespace();
+        }
+    }
+
+    fn scan_array(self: &mut Self, path: String) {
+        self.advance();
+        self.skip_whitespace();
+
+        let mut idx = 0;
+
+        while let Some(c) = self.peek() {
+            if c == ']' {
+                self.advance();
+                return;
+            }
+
+            if c == ',' {
+                self.advance();
+                self.skip_whitespace();
+                continue;
+            }
+
+            self.scan_value(index_path(&path, idx));
+            self.skip_whitespace();
+            idx += 1;
+        }
+    }
+
+    fn scan_string(self: &mut Self) -> String {
+        let mut string = String::new();
+
+        // the opening quote
+        self.advance();
+
+        while let Some(c) = self.advance() {
+            match c {
+                '"' => break,
+                '\\' => {
+                    let escaped = match self.advance() {
+                        Some(escaped) => escaped,
+                        None => break
+                    };
+
+                    match escaped {
+                        'n' => string.push('\n'),
+                        't' => string.push('\t'),
+                        'r' => string.push('\r'),
+                        'b' => string.push('\u{8}'),
+                        'f' => string.push('\u{c}'),
+                        'u' => {
+                            let code = (0..4)
+                                .filter_map(|_| self.advance())
+                                .collect::<String>();
+                            let decoded = u32::from_str_radix(&code, 16)
+                                .ok()
+                                .and_then(char::from_u32);
+
+                            if let Some(decoded) = decoded {
+                                string.push(decoded);
+                            }
+                        },
+                        other => string.push(other)
+                    };
+                },
+                c => string.push(c)
+            };
+        }
+
+        return string;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn messages(config: Value) -> Vec<String> {
+        return validate(&config, &HashMap::new())
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+    }
+
+    fn routes(routes: Value) -> Value {
+        return json!({ "port": 8080, "routes": routes });
+    }
+
+    fn text() -> Value {
+        return json!({ "type": "text", "text": "", "http": { "headers": {} } });
+    }
+
+    fn random(weights: &[u64]) -> Value {
+        let responses = weights
+            .iter()
+            .map(|weight| json!({ "weight": weight, "response": text() }))
+            .collect::<Vec<Value>>();
+
+        return json!({
+            "port": 8080,
+            "routes": { "/": [{ "method": "GET", "response": { "type": "random", "responses": responses } }] }
+        });
+    }
+
+    #[test]
+    fn accepts_a_minimal_config() {
+        let config = routes(json!({ "/": [{ "method": "GET", "response": text() }] }));
+
+        assert_eq!(messages(config), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_missing_and_unknown_fields() {
+        assert_eq!(
+            messages(json!({ "routes": {}, "prot": 8080 })),
+            vec![
+                "prot: unknown field `prot`, did you mean `port`?".to_string(),
+                "missing field `port`".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_routes() {
+        let config = routes(json!({
+            "users": [{ "method": "GET", "response": text() }],
+            "/orders": [{ "method": "GET", "response": text() }, { "method": "GET", "response": text() }]
+        }));
+
+        assert_eq!(messages(config), vec![
+            "routes[\"/orders\"][1].method: method GET is already handled by `routes[\"/orders\"][0]` on this path".to_string(),
+            "routes.users: route `users` must start with `/`, did you mean `/users`?".to_string()
+        ]);
+    }
+
+    #[test]
+    fn reports_partial_parameters() {
+        let config = routes(json!({ "/users/id{id}": [{ "method": "GET", "response": text() }] }));
+
+        assert_eq!(messages(config), vec![
+            "routes[\"/users/id{id}\"]: route `/users/id{id}` has a parameter that is not a whole `{name}` segment".to_string()
+        ]);
+    }
+
+    #[test]
+    fn suggests_known_names() {
+        let response = json!({ "type": "txt", "text": "", "http": { "headers": {} } });
+        let config = routes(json!({ "/": [{ "method": "GTE", "response": response }] }));
+
+        assert_eq!(messages(config), vec![
+            "routes[\"/\"][0].response.type: unknown response type `txt`, did you mean `text`?".to_string(),
+            "routes[\"/\"][0].method: unknown method `GTE`, did you mean `GET`?".to_string()
+        ]);
+    }
+
+    #[test]
+    fn reports_invalid_ports() {
+        assert_eq!(messages(json!({ "port": 70000, "routes": {} })), vec![
+            "port: port 70000 is out of range, the largest port is 65535".to_string()
+        ]);
+        assert_eq!(messages(json!({ "port": 0, "routes": {} })), vec![
+            "port: port 0 is not allowed".to_string()
+        ]);
+    }
+
+    #[test]
+    fn reports_invalid_admin_hosts() {
+        let config = json!({ "port": 8080, "routes": {}, "admin": { "port": 9000, "host": "localhost" } });
+
+        assert_eq!(messages(config), vec!["admin.host: host `localhost` must be an IP address, like 127.0.0.1".to_string()]);
+    }
+
+    #[test]
+    fn reports_invalid_raw_headers() {
+        let raw_headers = json!([{ "name": "Set-Cookie", "value": "a=1" }, { "name": "Bad Name", "value": "1" }, { "name": "X-Count", "value": 1 }]);
+        let config = routes(json!({ "/": [{ "method": "GET", "response": { "type": "text", "text": "", "http": { "raw_headers": raw_headers } } }] }));
+
+        assert_eq!(messages(config), vec![
+            "routes[\"/\"][0].response.http.raw_headers[1].name: invalid header name `Bad Name`".to_string(),
+            "routes[\"/\"][0].response.http.raw_headers[2].value: expected a string".to_string()
+        ]);
+    }
+
+    #[test]
+    fn reports_positions() {
+        let source = "{\n  \"port\": \"eighty\",\n  \"routes\": {}\n}";
+        let config = serde_json::from_str::<Value>(source).unwrap();
+        let messages = validate(&config, &positions(source))
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect::<Vec<String>>();
+
+        assert_eq!(messages, vec!["port: port must be a number (line 2, column 3)".to_string()]);
+    }
+
+    #[test]
+    fn accepts_weights_up_to_the_max() {
+        assert_eq!(messages(random(&[u64::MAX - 1, 1])), Vec::<String>::new());
+    }
+
+    #[test]
+    fn rejects_weights_that_overflow() {
+        assert_eq!(
+            messages(random(&[u64::MAX, 1])),
+            vec![format!("routes[\"/\"][0].response.responses: the weights must add up to at most {}", u64::MAX)]
+        );
+    }
+
+    #[test]
+    fn rejects_weights_that_are_all_zero() {
+        assert_eq!(
+            messages(random(&[0, 0])),
+            vec!["routes[\"/\"][0].response.responses: at least one response must have a weight above 0".to_string()]
+        );
+    }
+}
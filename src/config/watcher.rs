@@ -0,0 +1,174 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    thread,
+    time::{Duration, SystemTime}
+};
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use crate::{logging::{self, LogLevel, log}, http::metrics::Metrics, config::{ServerConfig, compose, format::ConfigFormat, shared::SharedConfig}};
+
+static POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// the files making up the config with the time they were last modified at, None for the ones that are gone
+type WatchedFiles = BTreeMap<PathBuf, Option<SystemTime>>;
+
+pub struct ConfigWatcher {
+    path: PathBuf,
+    format: ConfigFormat,
+    config: SharedConfig,
+    port: u16,
+    last_seen: Option<WatchedFiles>,
+    metrics: Option<Arc<Metrics>>
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf, format: ConfigFormat, config: SharedConfig) -> Self {
+        let last_seen = watched_files(&path, &config.load());
+        // the listener cannot be rebound on reload, so we remember the port we started on
+        let port = config.load().port;
+
+        return Self { path, format, config, port, last_seen, metrics: None };
+    }
+
+    // makes the watcher count its reloads in the metrics
+    pub fn with_metrics(self: Self, metrics: Arc<Metrics>) -> Self {
+        return Self {
+            metrics: Some(metrics),
+            ..self
+        };
+    }
+
+    // spawns a background thread polling the config file for changes,
+    // on unix the config is also reloaded on SIGHUP, whether it changed or not
+    pub fn spawn(self: Self) -> thread::JoinHandle<()> {
+        let hangup = hangup_flag();
+
+        return thread::spawn(move || {
+            let mut watcher = self;
+
+            loop {
+                thread::sleep(POLL_INTERVAL);
+
+                if hangup.swap(false, Ordering::Relaxed) {
+                    log(LogLevel::INFO, "Received SIGHUP, reloading the config.".to_string());
+                    watcher.last_seen = watched_files(&watcher.path, &watcher.config.load());
+                    watcher.reload();
+                } else {
+                    watcher.poll();
+                }
+            }
+        });
+    }
+
+    // checks if any of the files was modified, added or removed since the last poll and reloads the config if so,
+    // the old config keeps being served when the new one cannot be read or parsed
+    pub fn poll(self: &mut Self) {
+        let seen = watched_files(&self.path, &self.config.load());
+
+        if seen.is_none() || seen == self.last_seen {
+            return;
+        }
+
+        self.reload();
+        // the files are looked at again, as a reload can include files the old config didn't
+        self.last_seen = watched_files(&self.path, &self.config.load());
+    }
+
+    pub fn reload(self: &Self) {
+        let path = self.path.display().to_string();
+        let config = match ServerConfig::read_from_file(&self.path, self.format) {
+            Ok(config) => config,
+            Err(err) => {
+                log(LogLevel::ERROR, format!("Cannot reload config file \"{}\", keeping the old config. {}", path, err));
+                self.record_reload(false);
+                return;
+            }
+        };
+        if config.port != self.port {
+            log(LogLevel::WARN, format!("Port change from {} to {} requires a restart, still serving on port {}.", self.port, config.port, self.port));
+        }
+
+        logging::configure(&config.logging);
+        self.config.store(config);
+        log(LogLevel::INFO, format!("Reloaded config file \"{}\".", path));
+        self.record_reload(true);
+    }
+
+    fn record_reload(self: &Self, succeeded: bool) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_reload(succeeded);
+        }
+    }
+}
+
+// a flag raised whenever the process receives SIGHUP
+#[cfg(unix)]
+fn hangup_flag() -> Arc<AtomicBool> {
+    let flag = Arc::new(AtomicBool::new(false));
+
+    if let Err(err) = signal_hook::flag::register(signal_hook::consts::SIGHUP, flag.clone()) {
+        log(LogLevel::WARN, format!("Cannot reload the config on SIGHUP. {}", err));
+    }
+
+    return flag;
+}
+
+#[cfg(not(unix))]
+fn hangup_flag() -> Arc<AtomicBool> {
+    return Arc::new(AtomicBool::new(false));
+}
+
+// the config file, the files it includes and the files matching its include patterns, whether they
+// were included yet or not, None while the config file itself is missing, like when an editor replaces it
+fn watched_files(path: &PathBuf, config: &ServerConfig) -> Option<WatchedFiles> {
+    let modified = fs::metadata(path).ok()?.modified().ok();
+    let mut files = config.included_files
+        .iter()
+        .cloned()
+        .chain(config.include_patterns.iter().flat_map(|pattern| compose::expand_pattern(pattern)))
+        .map(|path| {
+            let modified = fs::metadata(&path).ok().and_then(|metadata| metadata.modified().ok());
+
+            (path, modified)
+        })
+        .collect::<WatchedFiles>();
+
+    files.insert(path.clone(), modified);
+
+    return Some(files);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(path: &str) -> String {
+        return format!(r#"{{ "routes": {{ "{}": [{{ "method": "GET", "response": {{ "type": "text", "text": "" }} }}] }} }}"#, path);
+    }
+
+    #[test]
+    fn reloads_files_that_start_matching_an_include() {
+        let dir = std::env::temp_dir().join(format!("quickserving-watcher-{}", std::process::id()));
+        let path = dir.join("quickserving.json");
+
+        fs::create_dir_all(dir.join("routes")).unwrap();
+        fs::write(&path, r#"{ "port": 8080, "include": "routes/*.json", "routes": {} }"#).unwrap();
+        fs::write(dir.join("routes/a.json"), route("/a")).unwrap();
+
+        let config = SharedConfig::new(ServerConfig::read_from_file(&path, ConfigFormat::Json).unwrap());
+        let mut watcher = ConfigWatcher::new(path.clone(), ConfigFormat::Json, config.clone());
+
+        fs::write(dir.join("routes/b.json"), route("/b")).unwrap();
+        watcher.poll();
+
+        assert!(config.load().routes.contains_key("/b"));
+
+        fs::remove_file(dir.join("routes/a.json")).unwrap();
+        watcher.poll();
+
+        assert!(!config.load().routes.contains_key("/a"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
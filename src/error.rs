@@ -0,0 +1,82 @@
+use std::{fmt, io};
+use crate::{http::status::StatusCode, config::validation::Diagnostic};
+
+#[derive(Debug)]
+pub enum Error {
+    Parse(ParseError),
+    Io(io::Error),
+    Config(String),
+    Validation(Vec<Diagnostic>),
+    Upstream(String),
+    UpstreamTimeout(String),
+    Script(String)
+}
+
+impl fmt::Display for Error {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Self::Parse(err) => write!(f, "Invalid request. {}", err),
+            Self::Io(err) => write!(f, "I/O error. {}", err),
+            Self::Config(message) => write!(f, "Invalid config. {}", message),
+            Self::Upstream(message) => write!(f, "Upstream error. {}", message),
+            Self::UpstreamTimeout(message) => write!(f, "Upstream timed out. {}", message),
+            Self::Script(message) => write!(f, "Script error. {}", message),
+            Self::Validation(diagnostics) => {
+                write!(f, "Invalid config, found {} problem(s):", diagnostics.len())?;
+
+                for diagnostic in diagnostics {
+                    write!(f, "\n  {}", diagnostic)?;
+                }
+
+                Ok(())
+            }
+        };
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        return Self::Io(err);
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        return Self::Parse(err);
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    Malformed(String),
+    HeadersTooLarge,
+    BodyTooLarge,
+    UnsupportedMethod(String)
+}
+
+impl ParseError {
+    // the status code the client should be answered with when its request cannot be parsed
+    pub fn status_code(self: &Self) -> StatusCode {
+        return match self {
+            Self::Malformed(_) => StatusCode::BadRequest,
+            Self::HeadersTooLarge => StatusCode::RequestHeaderFieldsTooLarge,
+            Self::BodyTooLarge => StatusCode::RequestEntityTooLarge,
+            Self::UnsupportedMethod(_) => StatusCode::NotImplemented
+        };
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Self::Malformed(message) => write!(f, "{}", message),
+            Self::HeadersTooLarge => write!(f, "Request headers are too large."),
+            Self::BodyTooLarge => write!(f, "Request body is too large."),
+            Self::UnsupportedMethod(method) => write!(f, "Method \"{}\" is not supported.", method)
+        };
+    }
+}
+
+impl std::error::Error for ParseError {}
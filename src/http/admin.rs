@@ -0,0 +1,570 @@
+use std::{
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::Instant
+};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use crate::{
+    error::Error,
+    logging::{self, LogLevel, log},
+    http::{request::Request, response::Response, method::Method, metrics::Metrics, journal::{Journal, RequestFilter, Times}, scenarios::Scenarios, sequences::Sequences, script::Scripts, server::create_json_response, status::StatusCode},
+    config::{ServerConfig, format::ConfigFormat, shared::SharedConfig}
+};
+
+// the API changing the routes of a running server, served on its own port:
+// GET /routes, PUT /routes, GET|POST|PUT|DELETE /routes/<route>, POST /reset and GET /stats,
+// GET|DELETE /requests, GET /requests/unmatched and POST /requests/verify for the journal,
+// and GET /scenarios, PUT /scenarios/<name> and POST /scenarios/reset for the scenarios
+pub struct Admin {
+    config: SharedConfig,
+    config_file: Option<(PathBuf, ConfigFormat)>,
+    // restored on reset when the server isn't serving a config file
+    initial: Arc<ServerConfig>,
+    metrics: Arc<Metrics>,
+    journal: Arc<Journal>,
+    scenarios: Arc<Scenarios>,
+    sequences: Arc<Sequences>,
+    scripts: Arc<Scripts>,
+    started: Instant,
+    // changes read the route table and store it changed, so they are made one at a time
+    changes: Mutex<()>
+}
+
+impl Admin {
+    pub fn new(
+        config: SharedConfig,
+        config_file: Option<(PathBuf, ConfigFormat)>,
+        metrics: Arc<Metrics>,
+        journal: Arc<Journal>,
+        scenarios: Arc<Scenarios>,
+        sequences: Arc<Sequences>,
+        scripts: Arc<Scripts>
+    ) -> Self {
+        return Self {
+            initial: config.load(),
+            config,
+            config_file,
+            metrics,
+            journal,
+            scenarios,
+            sequences,
+            scripts,
+            started: Instant::now(),
+            changes: Mutex::new(())
+        };
+    }
+
+    // serves the API on the listener in a background thread
+    pub fn spawn(self: Self, listener: TcpListener) -> thread::JoinHandle<()> {
+        let admin = Arc::new(self);
+
+        return thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        log(LogLevel::WARN, format!("Cannot accept admin connection. {}", err));
+                        continue;
+                    }
+                };
+                let admin = admin.clone();
+
+                thread::spawn(move || admin.serve(stream));
+            }
+        });
+    }
+
+    fn serve(self: &Self, stream: TcpStream) {
+        let response = match Request::read_from_stream(&stream) {
+            Ok(request) => self.handle(&request),
+            Err(err) => error_response(StatusCode::BadRequest, err.to_string())
+        };
+
+        if let Err(err) = response.send(&stream) {
+            log(LogLevel::WARN, format!("Cannot send admin response. {}", err));
+        }
+    }
+
+    pub fn handle(self: &Self, request: &Request) -> Response {
+        let path = request.path.as_str();
+
+        if let Some(route) = path.strip_prefix("/routes/") {
+            return self.handle_route(request, format!("/{}", route));
+        }
+
+        if let Some(name) = path.strip_prefix("/scenarios/").filter(|name| *name != "reset") {
+            return match request.method {
+                Method::PUT => self.set_scenario(request, name),
+                _ => error_response(StatusCode::MethodNotAllowed, format!("method not allowed on `{}`", path))
+            };
+        }
+
+        return match (&request.method, path) {
+            (Method::GET, "/routes") => self.list_routes(),
+            (Method::PUT, "/routes") => match parse_body(request) {
+                Ok(routes) => self.change_routes(|all_routes| {
+                    *all_routes = match routes {
+                        Value::Object(routes) => routes,
+                        _ => return Err((StatusCode::BadRequest, "the routes must be an object".to_string()))
+                    };
+
+                    Ok(StatusCode::OK)
+                }),
+                Err(message) => error_response(StatusCode::BadRequest, message)
+            },
+            (Method::POST, "/reset") => self.reset(),
+            (Method::GET, "/stats") => self.stats(),
+            (Method::GET, "/requests") => match filter_from_query(request) {
+                Ok(filter) => create_json_response(StatusCode::OK, json!(self.journal.entries(&filter))),
+                Err(message) => error_response(StatusCode::BadRequest, message)
+            },
+            (Method::DELETE, "/requests") => {
+                self.journal.clear();
+                create_json_response(StatusCode::OK, json!([]))
+            },
+            (Method::GET, "/requests/unmatched") => create_json_response(StatusCode::OK, json!(self.journal.unmatched())),
+            (Method::POST, "/requests/verify") => self.verify(request),
+            (Method::GET, "/scenarios") => self.list_scenarios(),
+            (Method::POST, "/scenarios/reset") => {
+                self.scenarios.reset();
+                log(LogLevel::INFO, "Reset scenarios through the admin API.".to_string());
+                self.list_scenarios()
+            },
+            (_, "/routes" | "/reset" | "/stats" | "/requests" | "/requests/unmatched" | "/requests/verify" | "/scenarios" | "/scenarios/reset") => error_response(StatusCode::MethodNotAllowed, format!("method not allowed on `{}`", path)),
+            _ => error_response(StatusCode::NotFound, format!("no admin endpoint at `{}`", path))
+        };
+    }
+
+    fn handle_route(self: &Self, request: &Request, route: String) -> Response {
+        if request.method == Method::GET {
+            return match self.config.load().routes.get(&route) {
+                Some(route_configs) => create_json_response(StatusCode::OK, json!(route_configs)),
+                None => error_response(StatusCode::NotFound, format!("route `{}` does not exist", route))
+            };
+        }
+
+        if request.method == Method::DELETE {
+            return self.change_routes(|routes| match routes.remove(&route) {
+                Some(_) => Ok(StatusCode::OK),
+                None => Err((StatusCode::NotFound, format!("route `{}` does not exist", route)))
+            });
+        }
+
+        let route_configs = match parse_body(request) {
+            // a single response can be given without wrapping it in a list
+            Ok(Value::Array(route_configs)) => route_configs,
+            Ok(route_config) => vec![route_config],
+            Err(message) => return error_response(StatusCode::BadRequest, message)
+        };
+
+        return match request.method {
+            // adds the responses to the route, creating it if needed
+            Method::POST => self.change_routes(|routes| {
+                let status_code = match routes.get_mut(&route) {
+                    Some(Value::Array(existing)) => {
+                        existing.extend(route_configs);
+                        StatusCode::OK
+                    },
+                    _ => {
+                        routes.insert(route, Value::Array(route_configs));
+                        StatusCode::Created
+                    }
+                };
+
+                Ok(status_code)
+            }),
+            // replaces all the responses of the route
+            Method::PUT => self.change_routes(|routes| {
+                let status_code = match routes.insert(route, Value::Array(route_configs)) {
+                    Some(_) => StatusCode::OK,
+                    None => StatusCode::Created
+                };
+
+                Ok(status_code)
+            }),
+            _ => error_response(StatusCode::MethodNotAllowed, format!("method not allowed on `{}`", request.path))
+        };
+    }
+
+    fn list_routes(self: &Self) -> Response {
+        return create_json_response(StatusCode::OK, json!(self.config.load().routes));
+    }
+
+    // applies the change to the route table and swaps the config with the changed one when it's valid,
+    // answering with the new route table, or with the status and message of the error the change failed with
+    fn change_routes(
+        self: &Self,
+        change: impl FnOnce(&mut Map<String, Value>) -> Result<StatusCode, (StatusCode, String)>
+    ) -> Response {
+        let _guard = match self.changes.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        let config = self.config.load();
+        let mut routes = match serde_json::to_value(&config.routes) {
+            Ok(Value::Object(routes)) => routes,
+            _ => Map::new()
+        };
+        let existing = exec_responses(&routes);
+        let status_code = match change(&mut routes) {
+            Ok(status_code) => status_code,
+            Err((status_code, message)) => return error_response(status_code, message)
+        };
+        let allows_exec = config.admin.as_ref().is_some_and(|admin| admin.allow_exec_responses);
+
+        // responses running code can only be kept as they are, unless the config allows adding them
+        if !allows_exec {
+            if let Some(added) = exec_responses(&routes).into_iter().find(|response| !existing.contains(response)) {
+                let message = format!(
+                    "{} responses can only be added through the admin API with `admin.allow_exec_responses` set to true",
+                    added["type"].as_str().unwrap_or_default()
+                );
+
+                return error_response(StatusCode::Forbidden, message);
+            }
+        }
+
+        let changed = match config.with_routes(Value::Object(routes)) {
+            Ok(changed) => changed,
+            Err(err) => return invalid_config_response(err)
+        };
+
+        self.config.store(changed);
+        log(LogLevel::INFO, "Changed routes through the admin API.".to_string());
+
+        return create_json_response(status_code, json!(self.config.load().routes));
+    }
+
+    // the current state of every scenario, keyed by its name
+    fn list_scenarios(self: &Self) -> Response {
+        let config = self.config.load();
+        let states = config.scenarios
+            .keys()
+            .map(|name| (name.clone(), json!(config.scenario_state(name, &self.scenarios))))
+            .collect::<Map<String, Value>>();
+
+        return create_json_response(StatusCode::OK, Value::Object(states));
+    }
+
+    // moves the scenario to the state from the body, e.g. {"state": "paid"}
+    fn set_scenario(self: &Self, request: &Request, name: &str) -> Response {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct ScenarioState {
+            state: String
+        }
+
+        if !self.config.load().scenarios.contains_key(name) {
+            return error_response(StatusCode::NotFound, format!("scenario `{}` does not exist", name));
+        }
+
+        let body = match parse_body(request).and_then(|body| {
+            serde_json::from_value::<ScenarioState>(body).map_err(|err| format!("invalid scenario state, {}", err))
+        }) {
+            Ok(body) => body,
+            Err(message) => return error_response(StatusCode::BadRequest, message)
+        };
+
+        self.scenarios.set(name, &body.state);
+        log(LogLevel::INFO, format!("Moved scenario \"{}\" to state \"{}\" through the admin API.", name, body.state));
+
+        return self.list_scenarios();
+    }
+
+    // restores the config file, or the config the server was started with, the initial states of the scenarios
+    // and the start of the sequences, and forgets the state of the scripts
+    fn reset(self: &Self) -> Response {
+        let _guard = match self.changes.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        let config = match &self.config_file {
+            Some((path, format)) => ServerConfig::read_from_file(path, *format),
+            None => serde_json::to_value(&self.initial.routes)
+                .map_err(|err| Error::Config(err.to_string()))
+                .and_then(|routes| self.initial.with_routes(routes))
+        };
+        let config = match config {
+            Ok(config) => config,
+            Err(err) => return invalid_config_response(err)
+        };
+
+        logging::configure(&config.logging);
+        self.config.store(config);
+        self.scenarios.reset();
+        self.sequences.reset();
+        self.scripts.reset();
+        log(LogLevel::INFO, "Reset routes through the admin API.".to_string());
+
+        return self.list_routes();
+    }
+
+    // answers 200 with the number of matching requests when they were received the expected
+    // number of times, or 417 with the reason when they weren't
+    fn verify(self: &Self, request: &Request) -> Response {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Verification {
+            #[serde(default)]
+            request: RequestFilter,
+            times: Times
+        }
+
+        let verification = match parse_body(request).and_then(|body| {
+            serde_json::from_value::<Verification>(body).map_err(|err| format!("invalid verification, {}", err))
+        }) {
+            Ok(verification) => verification,
+            Err(message) => return error_response(StatusCode::BadRequest, message)
+        };
+
+        return match self.journal.verify(&verification.request, verification.times) {
+            Ok(count) => create_json_response(StatusCode::OK, json!({ "count": count })),
+            Err(message) => create_json_response(StatusCode::ExpectationFailed, json!({
+                "error": message,
+                "count": self.journal.count(&verification.request)
+            }))
+        };
+    }
+
+    fn stats(self: &Self) -> Response {
+        let mut stats = self.metrics.stats();
+
+        stats["uptime_seconds"] = json!(self.started.elapsed().as_secs());
+        stats["routes"] = json!(self.config.load().routes.len());
+
+        return create_json_response(StatusCode::OK, stats);
+    }
+}
+
+// builds the journal filter from the query, e.g. ?method=POST&path=/orders&header=X-Api-Key:abc&matched=true
+fn filter_from_query(request: &Request) -> Result<RequestFilter, String> {
+    let mut filter = RequestFilter::new();
+
+    for (key, value) in request.query_params() {
+        filter = match key.as_str() {
+            "method" => match Method::try_from(value.to_uppercase()) {
+                Ok(method) => filter.method(method),
+                Err(_) => return Err(format!("unknown method `{}`", value))
+            },
+            "path" => filter.path(&value),
+            "header" => match value.split_once(':') {
+                Some((name, value)) => filter.header(name.trim(), value.trim()),
+                None => return Err(format!("header filter `{}` must be `name:value`", value))
+            },
+            "body" => filter.body(&value),
+            "route" => filter.route(&value),
+            "matched" => match value.as_str() {
+                "true" => filter.matched(true),
+                "false" => filter.matched(false),
+                _ => return Err(format!("matched must be `true` or `false`, not `{}`", value))
+            },
+            _ => return Err(format!("unknown filter `{}`", key))
+        };
+    }
+
+    return Ok(filter);
+}
+
+fn parse_body(request: &Request) -> Result<Value, String> {
+    return serde_json::from_slice(&request.body)
+        .map_err(|err| format!("invalid JSON body, {}", err));
+}
+
+// the responses of the route table that run code on the machine, including the ones in sequences and random responses
+fn exec_responses(routes: &Map<String, Value>) -> Vec<Value> {
+    fn collect(response: &Value, found: &mut Vec<Value>) {
+        if let Some("cgi" | "fastcgi" | "script") = response["type"].as_str() {
+            found.push(response.clone());
+        }
+
+        for nested in response["responses"].as_array().into_iter().flatten() {
+            // random responses wrap theirs with a weight
+            collect(nested.get("response").unwrap_or(nested), found);
+        }
+    }
+
+    let mut found = Vec::new();
+
+    for route_config in routes.values().filter_map(Value::as_array).flatten() {
+        collect(&route_config["response"], &mut found);
+    }
+
+    return found;
+}
+
+fn error_response(status_code: StatusCode, message: String) -> Response {
+    return create_json_response(status_code, json!({ "error": message }));
+}
+
+fn invalid_config_response(err: Error) -> Response {
+    let problems = match &err {
+        Error::Validation(diagnostics) => diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect(),
+        _ => vec![]
+    };
+
+    return create_json_response(StatusCode::UnprocessableEntity, json!({
+        "error": err.to_string(),
+        "problems": problems
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::{headers::Headers, journal::JournalEntry, response::ResponseSummary, version::Version};
+    use super::*;
+
+    fn admin(config: &str) -> Admin {
+        return Admin::new(
+            SharedConfig::new(ServerConfig::try_from(config.to_string()).unwrap()),
+            None,
+            Arc::new(Metrics::new()),
+            Arc::new(Journal::new()),
+            Arc::new(Scenarios::new()),
+            Arc::new(Sequences::new()),
+            Arc::new(Scripts::new())
+        );
+    }
+
+    fn request(method: Method, path: &str, body: &str) -> Request {
+        return Request::new(
+            method,
+            path.to_string(),
+            None,
+            Version::new("HTTP".to_string(), "1.1".to_string()),
+            Headers::new(),
+            body.as_bytes().to_vec()
+        );
+    }
+
+    fn json_body(response: &Response) -> Value {
+        return serde_json::from_slice(response.body()).unwrap();
+    }
+
+    static CONFIG: &str = r#"{
+        "port": 8080,
+        "admin": { "port": 8081 },
+        "routes": { "/": [{ "method": "GET", "response": { "type": "text", "text": "home" } }] }
+    }"#;
+
+    static EXEC_RESPONSES: [&str; 3] = [
+        r#"{ "method": "GET", "response": { "type": "cgi", "command": "build" } }"#,
+        r#"{ "method": "GET", "response": { "type": "fastcgi", "upstream": "127.0.0.1:9000", "script_filename": "/var/www/index.php" } }"#,
+        r#"{ "method": "GET", "response": { "type": "script", "script": "\"hello\"" } }"#
+    ];
+
+    #[test]
+    fn adds_replaces_and_removes_routes() {
+        let admin = admin(CONFIG);
+        let added = admin.handle(&request(Method::POST, "/routes/users", r#"{ "method": "GET", "response": { "type": "text", "text": "[]" } }"#));
+
+        assert_eq!(added.status_code(), 201);
+        assert_eq!(json_body(&added)["/users"][0]["response"]["text"], "[]");
+
+        let replaced = admin.handle(&request(Method::PUT, "/routes/users", r#"[{ "method": "POST", "response": { "type": "text", "text": "created" } }]"#));
+
+        assert_eq!(replaced.status_code(), 200);
+        assert_eq!(json_body(&replaced)["/users"].as_array().unwrap().len(), 1);
+        assert_eq!(json_body(&replaced)["/users"][0]["method"], "POST");
+        assert_eq!(admin.handle(&request(Method::DELETE, "/routes/users", "")).status_code(), 200);
+        assert_eq!(admin.handle(&request(Method::GET, "/routes/users", "")).status_code(), 404);
+        assert_eq!(admin.handle(&request(Method::DELETE, "/routes/users", "")).status_code(), 404);
+    }
+
+    #[test]
+    fn refuses_invalid_routes() {
+        let admin = admin(CONFIG);
+        let response = admin.handle(&request(Method::POST, "/routes/users", r#"{ "method": "GET", "response": { "type": "txt" } }"#));
+
+        assert_eq!(response.status_code(), 422);
+        assert!(!json_body(&response)["problems"].as_array().unwrap().is_empty());
+        assert_eq!(admin.handle(&request(Method::POST, "/routes/users", "{")).status_code(), 400);
+        assert!(!admin.config.load().routes.contains_key("/users"));
+    }
+
+    #[test]
+    fn refuses_responses_running_code_unless_allowed() {
+        let admin = admin(CONFIG);
+
+        for route_config in EXEC_RESPONSES {
+            let response = admin.handle(&request(Method::POST, "/routes/run", route_config));
+
+            assert_eq!(response.status_code(), 403, "{}", route_config);
+            assert!(json_body(&response)["error"].as_str().unwrap().contains("admin.allow_exec_responses"));
+        }
+
+        let nested = r#"{ "method": "GET", "response": { "type": "sequence", "responses": [{ "type": "cgi", "command": "build" }] } }"#;
+
+        assert_eq!(admin.handle(&request(Method::POST, "/routes/run", nested)).status_code(), 403);
+        assert_eq!(admin.handle(&request(Method::PUT, "/routes", &format!(r#"{{ "/run": [{}] }}"#, EXEC_RESPONSES[0]))).status_code(), 403);
+        assert!(!admin.config.load().routes.contains_key("/run"));
+    }
+
+    #[test]
+    fn adds_responses_running_code_when_allowed() {
+        let admin = admin(r#"{
+            "port": 8080,
+            "admin": { "port": 8081, "allow_exec_responses": true },
+            "routes": {}
+        }"#);
+
+        for route_config in &EXEC_RESPONSES[..2] {
+            assert_eq!(admin.handle(&request(Method::PUT, "/routes/run", route_config)).status_code(), 201, "{}", route_config);
+            assert_eq!(admin.handle(&request(Method::DELETE, "/routes/run", "")).status_code(), 200);
+        }
+    }
+
+    #[test]
+    fn keeps_responses_running_code_from_the_config() {
+        let admin = admin(r#"{
+            "port": 8080,
+            "admin": { "port": 8081 },
+            "routes": { "/build": [{ "method": "POST", "response": { "type": "cgi", "command": "build" } }] }
+        }"#);
+        let response = admin.handle(&request(Method::POST, "/routes/users", r#"{ "method": "GET", "response": { "type": "text", "text": "[]" } }"#));
+
+        assert_eq!(response.status_code(), 201);
+        assert!(admin.config.load().routes.contains_key("/build"));
+    }
+
+    #[test]
+    fn resets_to_the_initial_routes() {
+        let admin = admin(CONFIG);
+
+        admin.handle(&request(Method::POST, "/routes/users", r#"{ "method": "GET", "response": { "type": "text", "text": "[]" } }"#));
+        admin.handle(&request(Method::DELETE, "/routes/", ""));
+
+        let response = admin.handle(&request(Method::POST, "/reset", ""));
+
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(json_body(&response).as_object().unwrap().keys().collect::<Vec<&String>>(), vec!["/"]);
+        assert_eq!(admin.config.load().routes.len(), 1);
+    }
+
+    #[test]
+    fn verifies_the_journal() {
+        let admin = admin(CONFIG);
+        let summary = ResponseSummary { status_code: 200, body_bytes: 4, route: Some("/".to_string()) };
+
+        for _ in 0..2 {
+            admin.journal.record(JournalEntry::new(&request(Method::GET, "/", ""), &summary), 10);
+        }
+
+        let verified = admin.handle(&request(Method::POST, "/requests/verify", r#"{ "request": { "method": "GET", "path": "/" }, "times": { "exactly": 2 } }"#));
+
+        assert_eq!(verified.status_code(), 200);
+        assert_eq!(json_body(&verified)["count"], 2);
+
+        let failed = admin.handle(&request(Method::POST, "/requests/verify", r#"{ "request": { "path": "/" }, "times": { "at_least": 3 } }"#));
+
+        assert_eq!(failed.status_code(), 417);
+        assert_eq!(json_body(&failed)["count"], 2);
+        assert!(json_body(&failed)["error"].as_str().unwrap().contains("at least 3 time(s)"));
+        assert_eq!(admin.handle(&request(Method::POST, "/requests/verify", r#"{ "times": { "sometimes": 1 } }"#)).status_code(), 400);
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    collections::HashMap,
+    env,
+    io::{Read, Write},
+    path::Path,
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant}
+};
+use crate::{error::Error, logging::{LogLevel, log}, http::{request::Request, response::Response, headers::{Headers, HeaderName}, status::{Status, StatusCode}, version::Version}};
+
+// how often a running script is checked for having exited
+static POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// the script a cgi response runs, as it's set in the config
+pub struct Script<'a> {
+    pub command: &'a str,
+    pub args: &'a [String],
+    pub working_dir: Option<&'a str>,
+    pub timeout: Duration,
+    pub env: &'a HashMap<String, String>,
+    pub inherit_env: bool
+}
+
+// runs the script with the request and turns what it writes into our response,
+// a script that cannot be started, fails or writes no headers is an upstream error
+pub fn run(request: &Request, port: u16, script: &Script) -> Result<Response, Error> {
+    let mut command = Command::new(program(script.command));
+
+    command
+        .args(script.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    if !script.inherit_env {
+        command.env_clear();
+
+        if let Some(path) = env::var_os("PATH") {
+            command.env("PATH", path);
+        }
+    }
+
+    if let Some(working_dir) = script.working_dir {
+        command.current_dir(working_dir);
+    }
+
+    command.envs(variables(request, port)).envs(script.env);
+
+    let mut child = command
+        .spawn()
+        .map_err(|err| Error::Upstream(format!("Cannot run \"{}\". {}", script.command, err)))?;
+
+    // the input and the outputs are handled on their own threads, so that a script
+    // filling one of the pipes doesn't block while we wait on another
+    let mut stdin = child.stdin.take();
+    let body = request.body.clone();
+    let writer = thread::spawn(move || {
+        if let Some(stdin) = stdin.as_mut() {
+            let _ = stdin.write_all(&body);
+        }
+    });
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let exit_status = match wait(&mut child, script.timeout)? {
+        Some(exit_status) => exit_status,
+        None => {
+            let _ = child.kill();
+            let _ = child.wait();
+
+            return Err(Error::UpstreamTimeout(format!("\"{}\" ran for more than {}ms.", script.command, script.timeout.as_millis())));
+        }
+    };
+
+    let _ = writer.join();
+    let output = stdout.join().unwrap_or_default();
+    let errors = stderr.join().unwrap_or_default();
+
+    for line in String::from_utf8_lossy(&errors).lines().filter(|line| !line.trim().is_empty()) {
+        log(LogLevel::WARN, format!("\"{}\": {}", script.command, line));
+    }
+
+    if !exit_status.success() {
+        return Err(Error::Upstream(format!("\"{}\" failed with {}.", script.command, exit_status)));
+    }
+
+    return parse_output(&output);
+}
+
+// paths are taken relative to the server's directory, whatever directory the script runs in,
+// while plain names are left for the lookup in PATH
+fn program(command: &str) -> std::path::PathBuf {
+    let path = Path::new(command);
+
+    if path.components().count() > 1 && path.is_relative() {
+        if let Ok(current_dir) = env::current_dir() {
+            return current_dir.join(path);
+        }
+    }
+
+    return path.to_path_buf();
+}
+
+// the CGI/1.1 meta-variables of the request, with every header as an HTTP_ variable
+pub fn variables(request: &Request, port: u16) -> Vec<(String, String)> {
+    let method: String = request.method.clone().into();
+    let server_name = request.headers
+        .get(&HeaderName::Host)
+        .map(|host| host.rsplit_once(':').map(|(name, _)| name).unwrap_or(host).to_string())
+        .unwrap_or("localhost".to_string());
+    let request_uri = match &request.query {
+        Some(query) => format!("{}?{}", request.path, query),
+        None => request.path.clone()
+    };
+    let mut variables = vec![
+        ("GATEWAY_INTERFACE".to_string(), "CGI/1.1".to_string()),
+        ("SERVER_SOFTWARE".to_string(), format!("quickserving/{}", env!("CARGO_PKG_VERSION"))),
+        ("SERVER_PROTOCOL".to_string(), request.version.to_string()),
+        ("SERVER_NAME".to_string(), server_name),
+        ("SERVER_PORT".to_string(), port.to_string()),
+        ("REQUEST_METHOD".to_string(), method),
+        ("REQUEST_URI".to_string(), request_uri),
+        ("SCRIPT_NAME".to_string(), String::new()),
+        ("PATH_INFO".to_string(), request.path.clone()),
+        ("QUERY_STRING".to_string(), request.query.clone().unwrap_or_default())
+    ];
+
+    if !request.body.is_empty() {
+        variables.push(("CONTENT_LENGTH".to_string(), request.body.len().to_string()));
+    }
+
+    for (name, value) in &request.raw_headers {
+        let name = name.to_uppercase().replace('-', "_");
+
+        // the body is described by the variables above, and the request id is set below, while a Proxy
+        // header would become HTTP_PROXY, which scripts' HTTP clients take as their proxy (httpoxy)
+        match name.as_str() {
+            "CONTENT_TYPE" => variables.push((name, value.clone())),
+            "CONTENT_LENGTH" | "X_REQUEST_ID" | "PROXY" => {},
+            _ => variables.push((format!("HTTP_{}", name), value.clone()))
+        };
+    }
+
+    // the script gets the same request id as the proxied upstreams, so that its logs can be correlated with ours
+    variables.push(("HTTP_X_REQUEST_ID".to_string(), request.id.clone()));
+
+    return variables;
+}
+
+fn read_in_background<R: Read + Send + 'static>(source: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    return thread::spawn(move || {
+        let mut buffer = Vec::new();
+
+        if let Some(mut source) = source {
+            let _ = source.read_to_end(&mut buffer);
+        }
+
+        buffer
+    });
+}
+
+// waits for the script to exit, None when it's still running after the timeout
+fn wait(child: &mut Child, timeout: Duration) -> Result<Option<std::process::ExitStatus>, Error> {
+    let started = Instant::now();
+
+    loop {
+        if let Some(exit_status) = child.try_wait()? {
+            return Ok(Some(exit_status));
+        }
+
+        if started.elapsed() >= timeout {
+            return Ok(None);
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// the output is the response headers, a blank line and the body, the status being
+// taken from the Status header, or 302 with a Location header and 200 otherwise
+pub fn parse_output(output: &[u8]) -> Result<Response, Error> {
+    let (head, body) = match split_head(output) {
+        Some(parts) => parts,
+        None => return Err(Error::Upstream("The output has no headers.".to_string()))
+    };
+    let head = String::from_utf8_lossy(head);
+    // the body is kept as it was written, scripts may write images or other binary output
+    let body = body.to_vec();
+    let mut status_code = None;
+    let mut headers = Vec::new();
+
+    for line in head.lines() {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => return Err(Error::Upstream(format!("The output has a malformed header \"{}\".", line)))
+        };
+
+        if name.eq_ignore_ascii_case("status") {
+            let code = value.split_whitespace().next().and_then(|code| code.parse::<u16>().ok());
+
+            match code {
+                Some(code) => status_code = Some(code),
+                None => return Err(Error::Upstream(format!("The output has an invalid status \"{}\".", value)))
+            };
+
+            continue;
+        }
+
+        headers.push((name.to_string(), value.to_string()));
+    }
+
+    let has_location = headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("location"));
+    let status_code = status_code.unwrap_or(match has_location {
+        true => StatusCode::Found.into(),
+        false => StatusCode::OK.into()
+    });
+    let content_length = body.len().to_string();
+
+    return Ok(Response::new(
+        Status { status_code },
+        Version::new("HTTP".to_string(), "1.1".to_string()),
+        Headers::new(),
+        body
+    ).with_headers(headers).with_header(HeaderName::ContentLength, content_length));
+}
+
+// splits the output at the first blank line, which scripts may end with either \r\n or \n
+fn split_head(output: &[u8]) -> Option<(&[u8], &[u8])> {
+    for idx in 0..output.len() {
+        if output[idx..].starts_with(b"\r\n\r\n") {
+            return Some((&output[..idx], &output[idx + 4..]));
+        }
+
+        if output[idx..].starts_with(b"\n\n") {
+            return Some((&output[..idx], &output[idx + 2..]));
+        }
+
+        if output[idx..].starts_with(b"\n\r\n") {
+            return Some((&output[..idx], &output[idx + 3..]));
+        }
+    }
+
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_the_status_from_the_output() {
+        let response = parse_output(b"Status: 201 Created\nContent-Type: text/plain\n\nhi").unwrap();
+
+        assert_eq!(response.status_code(), 201);
+        assert_eq!(response.headers().get(&HeaderName::ContentType), Some(&"text/plain".to_string()));
+        assert_eq!(response.body_text(), "hi");
+    }
+
+    #[test]
+    fn defaults_to_200() {
+        let response = parse_output(b"Content-Type: application/json\r\n\r\n{}").unwrap();
+
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.body_text(), "{}");
+        assert_eq!(response.headers().get(&HeaderName::ContentLength), Some(&"2".to_string()));
+    }
+
+    #[test]
+    fn redirects_with_a_location() {
+        let response = parse_output(b"Location: /login\n\n").unwrap();
+
+        assert_eq!(response.status_code(), 302);
+        assert_eq!(response.headers().get(&HeaderName::Location), Some(&"/login".to_string()));
+    }
+
+    #[test]
+    fn keeps_the_body_after_the_first_blank_line() {
+        let response = parse_output(b"Status: 404 Not Found\n\nmissing\n\nreally").unwrap();
+
+        assert_eq!(response.status_code(), 404);
+        assert_eq!(response.body_text(), "missing\n\nreally");
+    }
+
+    #[test]
+    fn rejects_malformed_output() {
+        assert!(matches!(parse_output(b"no blank line"), Err(Error::Upstream(message)) if message.contains("no headers")));
+        assert!(matches!(parse_output(b"Not a header\n\n"), Err(Error::Upstream(message)) if message.contains("malformed header")));
+        assert!(matches!(parse_output(b"Status: ok\n\n"), Err(Error::Upstream(message)) if message.contains("invalid status")));
+    }
+
+    #[test]
+    fn keeps_binary_bodies() {
+        let mut output = b"Content-Type: image/png\r\n\r\n".to_vec();
+        let body = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0xff, 0xfe];
+        output.extend_from_slice(&body);
+
+        let response = parse_output(&output).unwrap();
+
+        assert_eq!(response.body(), &body);
+        assert_eq!(response.headers().get(&HeaderName::ContentLength), Some(&body.len().to_string()));
+    }
+
+    #[test]
+    fn splits_the_head_at_any_blank_line() {
+        assert_eq!(split_head(b"A: 1\r\n\r\nbody"), Some((&b"A: 1"[..], &b"body"[..])));
+        assert_eq!(split_head(b"A: 1\n\nbody"), Some((&b"A: 1"[..], &b"body"[..])));
+        assert_eq!(split_head(b"A: 1\n\r\nbody"), Some((&b"A: 1"[..], &b"body"[..])));
+        assert_eq!(split_head(b"\n\nbody"), Some((&b""[..], &b"body"[..])));
+        assert_eq!(split_head(b"A: 1\r\nbody"), None);
+    }
+
+    #[test]
+    fn never_sets_http_proxy() {
+        let request = Request::try_from("GET /?a=1 HTTP/1.1\r\nHost: example.com:8080\r\nProxy: http://attacker:1234\r\nX-Api-Key: abc\r\n\r\n".to_string())
+            .unwrap();
+        let variables = variables(&request, 8080);
+        let variable = |name: &str| variables.iter().find(|(variable, _)| variable == name).map(|(_, value)| value.as_str());
+
+        assert_eq!(variable("HTTP_PROXY"), None);
+        assert_eq!(variable("HTTP_X_API_KEY"), Some("abc"));
+        assert_eq!(variable("SERVER_NAME"), Some("example.com"));
+        assert_eq!(variable("QUERY_STRING"), Some("a=1"));
+    }
+
+    #[test]
+    fn passes_unknown_headers_through() {
+        let response = parse_output(b"Status: 201 Created\nSet-Cookie: a=1\nX-Custom: yes\nSet-Cookie: b=2\nContent-Type: text/plain\n\nhi").unwrap();
+
+        assert_eq!(response.status_code(), 201);
+        assert_eq!(response.headers().get(&HeaderName::ContentType), Some(&"text/plain".to_string()));
+        assert_eq!(response.raw_headers(), &vec![
+            ("Set-Cookie".to_string(), "a=1".to_string()),
+            ("X-Custom".to_string(), "yes".to_string()),
+            ("Set-Cookie".to_string(), "b=2".to_string())
+        ]);
+
+        let sent: Vec<u8> = response.into();
+
+        assert!(String::from_utf8_lossy(&sent).contains("Set-Cookie: a=1\nX-Custom: yes\nSet-Cookie: b=2\n"));
+    }
+}
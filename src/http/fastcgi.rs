@@ -0,0 +1,447 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, Instant}
+};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use crate::{error::Error, logging::{LogLevel, log}, http::{cgi, request::Request, response::Response}};
+
+static CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+// the record types of the FastCGI protocol we send or read
+static BEGIN_REQUEST: u8 = 1;
+static END_REQUEST: u8 = 3;
+static PARAMS: u8 = 4;
+static STDIN: u8 = 5;
+static STDOUT: u8 = 6;
+static STDERR: u8 = 7;
+
+// every connection carries a single request, so they all have the same id
+static REQUEST_ID: u16 = 1;
+static RESPONDER_ROLE: u16 = 1;
+static MAX_CONTENT_LENGTH: usize = 65_535;
+
+// the application a fastcgi response sends the request to, as it's set in the config
+pub struct Application<'a> {
+    pub upstream: &'a str,
+    pub script_filename: Option<&'a str>,
+    pub timeout: Duration,
+    pub params: &'a HashMap<String, String>
+}
+
+// a connection to the upstream, either over TCP or a Unix socket
+trait Connection: Read + Write {
+    fn set_timeout(self: &Self, timeout: Duration) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn set_timeout(self: &Self, timeout: Duration) -> io::Result<()> {
+        self.set_read_timeout(Some(timeout))?;
+        return self.set_write_timeout(Some(timeout));
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn set_timeout(self: &Self, timeout: Duration) -> io::Result<()> {
+        self.set_read_timeout(Some(timeout))?;
+        return self.set_write_timeout(Some(timeout));
+    }
+}
+
+// sends the request to the upstream as a FastCGI responder request and turns the application's
+// output into our response, like the output of a CGI script
+pub fn forward(request: &Request, port: u16, application: &Application) -> Result<Response, Error> {
+    let mut connection = connect(application.upstream)
+        .map_err(|err| Error::Upstream(format!("Cannot connect to \"{}\". {}", application.upstream, err)))?;
+    let started = Instant::now();
+
+    connection.set_timeout(application.timeout)?;
+
+    let mut params = cgi::variables(request, port);
+
+    if let Some(script_filename) = application.script_filename {
+        params.push(("SCRIPT_FILENAME".to_string(), script_filename.to_string()));
+    }
+
+    params.extend(application.params.iter().map(|(name, value)| (name.clone(), value.clone())));
+
+    send_request(&mut connection, &params, &request.body)
+        .map_err(|err| upstream_error(err, application))?;
+
+    let mut output = Vec::new();
+
+    loop {
+        let remaining = application.timeout.saturating_sub(started.elapsed());
+
+        if remaining.is_zero() {
+            return Err(upstream_error(io::ErrorKind::TimedOut.into(), application));
+        }
+
+        connection.set_timeout(remaining)?;
+
+        let (record_type, content) = read_record(&mut connection)
+            .map_err(|err| upstream_error(err, application))?;
+
+        if record_type == STDOUT {
+            output.extend_from_slice(&content);
+        } else if record_type == STDERR {
+            for line in String::from_utf8_lossy(&content).lines().filter(|line| !line.trim().is_empty()) {
+                log(LogLevel::WARN, format!("\"{}\": {}", application.upstream, line));
+            }
+        } else if record_type == END_REQUEST {
+            // the protocol status is the fifth byte, anything but 0 means the request was refused
+            if let Some(protocol_status) = content.get(4).filter(|status| **status != 0) {
+                return Err(Error::Upstream(format!("\"{}\" refused the request with protocol status {}.", application.upstream, protocol_status)));
+            }
+
+            break;
+        }
+        // management records and the like are of no use to us
+    }
+
+    return cgi::parse_output(&output);
+}
+
+// the upstream is "host:port" for TCP, or "unix:<path>" for a Unix socket
+fn connect(upstream: &str) -> io::Result<Box<dyn Connection>> {
+    if let Some(path) = upstream.strip_prefix("unix:") {
+        #[cfg(unix)]
+        return Ok(Box::new(UnixStream::connect(path)?));
+
+        #[cfg(not(unix))]
+        return Err(io::Error::new(io::ErrorKind::Unsupported, format!("Unix sockets like \"{}\" are not supported on this platform.", path)));
+    }
+
+    let mut last_err = io::Error::new(io::ErrorKind::NotFound, "The address cannot be resolved.");
+
+    for address in upstream.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(Box::new(stream)),
+            Err(err) => last_err = err
+        };
+    }
+
+    return Err(last_err);
+}
+
+// whether a connection to the upstream can be opened, used by the readiness check
+pub fn is_reachable(upstream: &str) -> bool {
+    return connect(upstream).is_ok();
+}
+
+fn upstream_error(err: io::Error, application: &Application) -> Error {
+    return match err.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Error::UpstreamTimeout(format!(
+            "\"{}\" didn't answer within {}ms.",
+            application.upstream,
+            application.timeout.as_millis()
+        )),
+        _ => Error::Upstream(format!("Cannot talk to \"{}\". {}", application.upstream, err))
+    };
+}
+
+// the request is a begin record, the params and the body as streams, each ended by an empty record
+fn send_request(connection: &mut Box<dyn Connection>, params: &[(String, String)], body: &[u8]) -> io::Result<()> {
+    let mut begin = Vec::with_capacity(8);
+    begin.extend_from_slice(&RESPONDER_ROLE.to_be_bytes());
+    // no flags, so the upstream closes the connection once it's done
+    begin.extend_from_slice(&[0; 6]);
+
+    let mut encoded_params = Vec::new();
+
+    for (name, value) in params {
+        encode_length(&mut encoded_params, name.len());
+        encode_length(&mut encoded_params, value.len());
+        encoded_params.extend_from_slice(name.as_bytes());
+        encoded_params.extend_from_slice(value.as_bytes());
+    }
+
+    let mut records = Vec::new();
+    write_record(&mut records, BEGIN_REQUEST, &begin);
+    write_stream(&mut records, PARAMS, &encoded_params);
+    write_stream(&mut records, STDIN, body);
+
+    connection.write_all(&records)?;
+    return connection.flush();
+}
+
+// names and values up to 127 bytes long have their length in a byte, longer ones in 4 bytes with the top bit set
+fn encode_length(buffer: &mut Vec<u8>, length: usize) {
+    if length < 128 {
+        buffer.push(length as u8);
+    } else {
+        buffer.extend_from_slice(&(length as u32 | 0x8000_0000).to_be_bytes());
+    }
+}
+
+fn write_stream(buffer: &mut Vec<u8>, record_type: u8, content: &[u8]) {
+    for chunk in content.chunks(MAX_CONTENT_LENGTH) {
+        write_record(buffer, record_type, chunk);
+    }
+
+    write_record(buffer, record_type, &[]);
+}
+
+fn write_record(buffer: &mut Vec<u8>, record_type: u8, content: &[u8]) {
+    buffer.extend_from_slice(&[1, record_type]);
+    buffer.extend_from_slice(&REQUEST_ID.to_be_bytes());
+    buffer.extend_from_slice(&(content.len() as u16).to_be_bytes());
+    // no padding and the reserved byte
+    buffer.extend_from_slice(&[0, 0]);
+    buffer.extend_from_slice(content);
+}
+
+fn read_record(connection: &mut Box<dyn Connection>) -> io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 8];
+    connection.read_exact(&mut header)?;
+
+    let content_length = u16::from_be_bytes([header[4], header[5]]) as usize;
+    let padding_length = header[6] as usize;
+    let mut content = vec![0u8; content_length + padding_length];
+    connection.read_exact(&mut content)?;
+    content.truncate(content_length);
+
+    return Ok((header[1], content));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+    use crate::http::{method::Method, headers::{Headers, HeaderName}, version::Version};
+
+    fn request(body: &[u8]) -> Request {
+        return Request::new(
+            Method::POST,
+            "/orders".to_string(),
+            Some("page=2".to_string()),
+            Version::new("HTTP".to_string(), "1.1".to_string()),
+            Headers::new(),
+            body.to_vec()
+        );
+    }
+
+    fn record(record_type: u8, content: &[u8], padding: u8) -> Vec<u8> {
+        let mut record = vec![1, record_type];
+        record.extend_from_slice(&REQUEST_ID.to_be_bytes());
+        record.extend_from_slice(&(content.len() as u16).to_be_bytes());
+        record.extend_from_slice(&[padding, 0]);
+        record.extend_from_slice(content);
+        record.extend(std::iter::repeat_n(0xff, padding as usize));
+
+        return record;
+    }
+
+    // reads a record the way the upstream would, returning its type and content
+    fn read(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+        let mut header = [0u8; 8];
+        stream.read_exact(&mut header).unwrap();
+
+        let mut content = vec![0u8; u16::from_be_bytes([header[4], header[5]]) as usize + header[6] as usize];
+        stream.read_exact(&mut content).unwrap();
+        content.truncate(u16::from_be_bytes([header[4], header[5]]) as usize);
+
+        return (header[1], content);
+    }
+
+    // reads a stream of records up to the empty one ending it
+    fn read_stream(stream: &mut TcpStream, record_type: u8) -> Vec<u8> {
+        let mut content = Vec::new();
+
+        loop {
+            let (read_type, read_content) = read(stream);
+
+            assert_eq!(read_type, record_type);
+
+            if read_content.is_empty() {
+                return content;
+            }
+
+            content.extend_from_slice(&read_content);
+        }
+    }
+
+    fn decode_length(encoded: &mut &[u8]) -> usize {
+        if encoded[0] < 128 {
+            let length = encoded[0] as usize;
+            *encoded = &encoded[1..];
+
+            return length;
+        }
+
+        let length = u32::from_be_bytes([encoded[0] & 0x7f, encoded[1], encoded[2], encoded[3]]) as usize;
+        *encoded = &encoded[4..];
+
+        return length;
+    }
+
+    fn decode_params(mut encoded: &[u8]) -> HashMap<String, String> {
+        let mut params = HashMap::new();
+
+        while !encoded.is_empty() {
+            let name_length = decode_length(&mut encoded);
+            let value_length = decode_length(&mut encoded);
+            let name = String::from_utf8(encoded[..name_length].to_vec()).unwrap();
+            let value = String::from_utf8(encoded[name_length..name_length + value_length].to_vec()).unwrap();
+
+            encoded = &encoded[name_length + value_length..];
+            params.insert(name, value);
+        }
+
+        return params;
+    }
+
+    // a responder on a loopback port that answers a single connection with the records built from what it was sent
+    fn responder(answer: impl FnOnce(HashMap<String, String>, Vec<u8>) -> Vec<u8> + Send + 'static) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let (begin_type, begin) = read(&mut stream);
+
+            assert_eq!(begin_type, BEGIN_REQUEST);
+            assert_eq!(begin, vec![0, 1, 0, 0, 0, 0, 0, 0]);
+
+            let params = decode_params(&read_stream(&mut stream, PARAMS));
+            let body = read_stream(&mut stream, STDIN);
+
+            stream.write_all(&answer(params, body)).unwrap();
+        });
+
+        return upstream;
+    }
+
+    fn forward_to(upstream: &str, request: Request, timeout: Duration) -> Result<Response, Error> {
+        let params = HashMap::from([("APP_ENV".to_string(), "test".to_string())]);
+        let application = Application {
+            upstream,
+            script_filename: Some("/srv/index.php"),
+            timeout,
+            params: &params
+        };
+
+        return forward(&request, 8080, &application);
+    }
+
+    #[test]
+    fn encodes_short_lengths_in_a_byte() {
+        let mut buffer = Vec::new();
+        encode_length(&mut buffer, 127);
+
+        assert_eq!(buffer, vec![127]);
+    }
+
+    #[test]
+    fn encodes_long_lengths_in_four_bytes() {
+        let mut buffer = Vec::new();
+        encode_length(&mut buffer, 128);
+        encode_length(&mut buffer, 70_000);
+
+        assert_eq!(buffer, vec![0x80, 0, 0, 128, 0x80, 0x01, 0x11, 0x70]);
+    }
+
+    #[test]
+    fn frames_records() {
+        let mut buffer = Vec::new();
+        write_record(&mut buffer, STDIN, b"abc");
+
+        assert_eq!(buffer, vec![1, STDIN, 0, 1, 0, 3, 0, 0, b'a', b'b', b'c']);
+    }
+
+    #[test]
+    fn splits_streams_into_records_and_ends_them() {
+        let content = vec![7u8; MAX_CONTENT_LENGTH + 1];
+        let mut buffer = Vec::new();
+        write_stream(&mut buffer, STDIN, &content);
+
+        assert_eq!(buffer.len(), 8 + MAX_CONTENT_LENGTH + 8 + 1 + 8);
+        assert_eq!(&buffer[4..6], &(MAX_CONTENT_LENGTH as u16).to_be_bytes());
+        assert_eq!(&buffer[8 + MAX_CONTENT_LENGTH + 4..8 + MAX_CONTENT_LENGTH + 6], &[0, 1]);
+        assert_eq!(&buffer[buffer.len() - 8..], &[1, STDIN, 0, 1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn forwards_the_request_and_reads_the_output() {
+        let upstream = responder(|params, body| {
+            assert_eq!(params.get("REQUEST_METHOD").map(String::as_str), Some("POST"));
+            assert_eq!(params.get("REQUEST_URI").map(String::as_str), Some("/orders?page=2"));
+            assert_eq!(params.get("SCRIPT_FILENAME").map(String::as_str), Some("/srv/index.php"));
+            assert_eq!(params.get("APP_ENV").map(String::as_str), Some("test"));
+            assert_eq!(body, b"\xff\x00binary");
+
+            let mut answer = record(STDOUT, b"Status: 201\r\nContent-Type: text/plain\r\n\r\ncre", 5);
+            answer.extend(record(STDERR, b"a warning\n", 0));
+            answer.extend(record(STDOUT, b"ated", 3));
+            answer.extend(record(STDOUT, b"", 0));
+            answer.extend(record(END_REQUEST, &[0, 0, 0, 0, 0, 0, 0, 0], 0));
+
+            answer
+        });
+        let response = forward_to(&upstream, request(b"\xff\x00binary"), Duration::from_secs(5)).unwrap();
+
+        assert_eq!(response.status_code(), 201);
+        assert_eq!(response.body_text(), "created");
+        assert_eq!(response.headers().get(&HeaderName::ContentType), Some(&"text/plain".to_string()));
+    }
+
+    #[test]
+    fn sends_long_params() {
+        let long = "x".repeat(300);
+        let expected = long.clone();
+        let upstream = responder(move |params, _| {
+            assert_eq!(params.get("HTTP_X_LONG"), Some(&expected));
+
+            let mut answer = record(STDOUT, b"Content-Type: text/plain\n\nok", 0);
+            answer.extend(record(END_REQUEST, &[0; 8], 0));
+
+            answer
+        });
+        let request = request(b"").with_raw_headers(vec![("X-Long".to_string(), long)]);
+        let response = forward_to(&upstream, request, Duration::from_secs(5)).unwrap();
+
+        assert_eq!(response.body_text(), "ok");
+    }
+
+    #[test]
+    fn never_sends_http_proxy() {
+        let upstream = responder(|params, _| {
+            assert_eq!(params.get("HTTP_PROXY"), None);
+
+            let mut answer = record(STDOUT, b"Content-Type: text/plain\n\nok", 0);
+            answer.extend(record(END_REQUEST, &[0; 8], 0));
+
+            answer
+        });
+        let request = request(b"").with_raw_headers(vec![("Proxy".to_string(), "http://attacker:1234".to_string())]);
+        let response = forward_to(&upstream, request, Duration::from_secs(5)).unwrap();
+
+        assert_eq!(response.body_text(), "ok");
+    }
+
+    #[test]
+    fn fails_when_the_request_is_refused() {
+        let upstream = responder(|_, _| record(END_REQUEST, &[0, 0, 0, 0, 1, 0, 0, 0], 0));
+
+        match forward_to(&upstream, request(b""), Duration::from_secs(5)) {
+            Err(Error::Upstream(message)) => assert!(message.contains("protocol status 1"), "{}", message),
+            other => panic!("expected an upstream error, got {:?}", other.map(|response| response.status_code()))
+        };
+    }
+
+    #[test]
+    fn times_out_when_the_upstream_does_not_answer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream = listener.local_addr().unwrap().to_string();
+
+        match forward_to(&upstream, request(b""), Duration::from_millis(200)) {
+            Err(Error::UpstreamTimeout(_)) => {},
+            other => panic!("expected a timeout, got {:?}", other.map(|response| response.status_code()))
+        };
+
+        drop(listener);
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    io::Write,
+    net::{Shutdown, TcpStream},
+    thread,
+    time::Duration
+};
+use socket2::SockRef;
+use crate::{
+    error::Error,
+    logging::{LogLevel, log},
+    http::{random, response::Response, headers::HeaderName},
+    config::{Fault, FaultsConfig}
+};
+
+// the most random bytes sent instead of a response
+static MAX_GARBAGE_BYTES: i64 = 1024;
+// how many times a second throttled responses are written to
+static THROTTLE_STEPS_PER_SECOND: u64 = 10;
+
+// sends the response with the faults injected into it, every fault happening with its probability,
+// the delay and the bandwidth apply to whatever is sent, and at most one of the faults breaking
+// the response happens, tried from the most to the least severe one
+pub fn send(response: Response, faults: &FaultsConfig, mut stream: &TcpStream) -> Result<(), Error> {
+    if let Some(delay) = faults.delay.as_ref().filter(|delay| random::chance(delay.probability)) {
+        let jitter = random::between(0, delay.jitter_ms as i64) as u64;
+
+        thread::sleep(Duration::from_millis(delay.ms + jitter));
+    }
+
+    if happens(&faults.reset) {
+        log(LogLevel::DEBUG, "Resetting the connection instead of responding.".to_string());
+
+        // closing a socket that lingers for no time at all resets the connection instead of closing it
+        SockRef::from(stream).set_linger(Some(Duration::ZERO))?;
+
+        return Ok(());
+    }
+
+    if happens(&faults.drop) {
+        log(LogLevel::DEBUG, "Closing the connection instead of responding.".to_string());
+        stream.shutdown(Shutdown::Both)?;
+
+        return Ok(());
+    }
+
+    let garbage = happens(&faults.garbage);
+    let truncate = !garbage && happens(&faults.truncate);
+    let body_bytes = response.body().len();
+    let bytes = match (garbage, truncate) {
+        (true, _) => {
+            log(LogLevel::DEBUG, "Sending random bytes instead of the response.".to_string());
+            random_bytes(random::between(1, MAX_GARBAGE_BYTES) as usize)
+        },
+        (_, true) => {
+            log(LogLevel::DEBUG, "Sending half of the response's body.".to_string());
+
+            // the client must know how long the body should have been to notice it's cut short
+            let mut bytes: Vec<u8> = response
+                .with_header(HeaderName::ContentLength, body_bytes.to_string())
+                .into();
+
+            bytes.truncate(bytes.len() - body_bytes + body_bytes / 2);
+            bytes
+        },
+        _ => response.into()
+    };
+
+    match faults.bandwidth.as_ref().filter(|bandwidth| random::chance(bandwidth.probability)) {
+        Some(bandwidth) => write_throttled(stream, &bytes, bandwidth.bytes_per_second)?,
+        None => stream.write_all(&bytes)?
+    };
+
+    stream.flush()?;
+
+    if garbage || truncate {
+        stream.shutdown(Shutdown::Both)?;
+    }
+
+    return Ok(());
+}
+
+fn happens(fault: &Option<Fault>) -> bool {
+    return fault.as_ref().is_some_and(|fault| random::chance(fault.probability));
+}
+
+// writes the bytes a part at a time, waiting between the parts for as long as sending them should take
+fn write_throttled(mut stream: &TcpStream, bytes: &[u8], bytes_per_second: u64) -> Result<(), Error> {
+    let bytes_per_step = (bytes_per_second / THROTTLE_STEPS_PER_SECOND).max(1) as usize;
+
+    for part in bytes.chunks(bytes_per_step) {
+        stream.write_all(part)?;
+        stream.flush()?;
+        thread::sleep(Duration::from_secs_f64(part.len() as f64 / bytes_per_second.max(1) as f64));
+    }
+
+    return Ok(());
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    return (0..len.div_ceil(8))
+        .flat_map(|_| random::next_u64().to_le_bytes())
+        .take(len)
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, net::TcpListener, time::Instant};
+    use serde_json::json;
+    use crate::http::{headers::Headers, status::Status, version::Version};
+    use super::*;
+
+    // sends the response with the faults over a loopback connection, returning what the client read
+    fn send_with(faults: serde_json::Value, body: &str) -> std::io::Result<Vec<u8>> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let faults = serde_json::from_value::<FaultsConfig>(faults).unwrap();
+        let response = Response::new(
+            Status { status_code: 200 },
+            Version::new("HTTP".to_string(), "1.1".to_string()),
+            Headers::new(),
+            body.as_bytes().to_vec()
+        );
+
+        send(response, &faults, &stream).unwrap();
+        drop(stream);
+
+        let mut bytes = Vec::new();
+
+        client.read_to_end(&mut bytes)?;
+
+        return Ok(bytes);
+    }
+
+    #[test]
+    fn sends_the_response_without_faults_that_never_happen() {
+        let bytes = send_with(json!({ "drop": { "probability": 0 }, "garbage": { "probability": 0 } }), "hello").unwrap();
+
+        assert!(bytes.starts_with(b"HTTP/1.1 200"));
+        assert!(bytes.ends_with(b"\r\nhello"));
+    }
+
+    #[test]
+    fn waits_before_sending() {
+        let started = Instant::now();
+        let bytes = send_with(json!({ "delay": { "ms": 200 } }), "hello").unwrap();
+
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(bytes.ends_with(b"hello"));
+    }
+
+    #[test]
+    fn throttles_the_bandwidth() {
+        let started = Instant::now();
+        let bytes = send_with(json!({ "bandwidth": { "bytes_per_second": 1000 } }), &"a".repeat(300)).unwrap();
+
+        assert!(started.elapsed() >= Duration::from_millis(300), "took {:?}", started.elapsed());
+        assert!(bytes.ends_with("a".repeat(300).as_bytes()));
+    }
+
+    #[test]
+    fn closes_the_connection_without_a_response() {
+        assert_eq!(send_with(json!({ "drop": {} }), "hello").unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn sends_half_of_the_body() {
+        let bytes = String::from_utf8(send_with(json!({ "truncate": {} }), "0123456789").unwrap()).unwrap();
+
+        assert!(bytes.contains("Content-Length: 10"));
+        assert!(bytes.ends_with("\r\n01234"));
+    }
+
+    #[test]
+    fn sends_garbage_instead_of_the_response() {
+        let bytes = send_with(json!({ "garbage": {}, "truncate": {} }), "hello").unwrap();
+
+        assert!(!bytes.is_empty() && bytes.len() <= MAX_GARBAGE_BYTES as usize);
+        assert!(!bytes.starts_with(b"HTTP/"));
+    }
+
+    #[test]
+    fn resets_the_connection() {
+        let result = send_with(json!({ "reset": {}, "drop": {} }), "hello");
+
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::ConnectionReset);
+    }
+
+    #[test]
+    fn inherits_the_faults_it_does_not_set() {
+        let defaults = serde_json::from_value::<FaultsConfig>(json!({ "delay": { "ms": 100 }, "drop": { "probability": 0.5 } })).unwrap();
+        let faults = serde_json::from_value::<FaultsConfig>(json!({ "delay": { "ms": 5 } })).unwrap().inherit(&defaults);
+
+        assert_eq!(faults.delay.unwrap().ms, 5);
+        assert_eq!(faults.drop.unwrap().probability, 0.5);
+        assert!(faults.reset.is_none());
+    }
+}
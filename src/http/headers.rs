@@ -1,7 +1,8 @@
 use std::{collections::HashMap, error::Error};
 use serde::{self, Serialize, Deserialize};
+use schemars::{JsonSchema, r#gen::SchemaGenerator, schema::{InstanceType, ObjectValidation, Schema, SchemaObject}};
 
-#[derive(Serialize, Deserialize, Debug, Hash, PartialEq, Eq, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Hash, PartialEq, Eq, Clone)]
 pub enum HeaderName {
     #[serde(rename="Content-MD5")]
     ContentMD5,
@@ -43,6 +44,7 @@ pub enum HeaderName {
     IfRange,
     #[serde(rename="If-Unmodified-Since")]
     IfUnmodifiedSince,
+    Location,
     #[serde(rename="Max-Forwards")]
     MaxForwards,
     Prefer,
@@ -65,7 +67,73 @@ pub enum HeaderName {
     Cookie,
     Origin,
     #[serde(rename="Accept-Datetime")]
-    AcceptDatetime
+    AcceptDatetime,
+    #[serde(rename="X-Request-Id")]
+    XRequestId,
+    #[serde(rename="traceparent")]
+    Traceparent
+}
+
+impl HeaderName {
+    pub fn all() -> Vec<Self> {
+        return vec![
+            Self::ContentMD5,
+            Self::HTTP2Settings,
+            Self::Warning,
+            Self::Pragma,
+            Self::AIM,
+            Self::Accept,
+            Self::AcceptCharset,
+            Self::AcceptEncoding,
+            Self::AcceptLanguage,
+            Self::Authorization,
+            Self::CacheControl,
+            Self::Connection,
+            Self::ContentEncoding,
+            Self::ContentLength,
+            Self::ContentType,
+            Self::Date,
+            Self::Expect,
+            Self::Forwarded,
+            Self::From,
+            Self::Host,
+            Self::IfMatch,
+            Self::IfModifiedSince,
+            Self::IfNoneMatch,
+            Self::IfRange,
+            Self::IfUnmodifiedSince,
+            Self::Location,
+            Self::MaxForwards,
+            Self::Prefer,
+            Self::ProxyAuthorization,
+            Self::Range,
+            Self::Referer,
+            Self::TE,
+            Self::Trailer,
+            Self::TransferEncoding,
+            Self::UserAgent,
+            Self::Upgrade,
+            Self::Via,
+            Self::AccessControlRequestMethod,
+            Self::AccessControlRequestHeaders,
+            Self::Cookie,
+            Self::Origin,
+            Self::AcceptDatetime,
+            Self::XRequestId,
+            Self::Traceparent
+        ];
+    }
+
+    // finds the header by its name ignoring the case, as clients send them in any case
+    pub fn find(name: &str) -> Option<Self> {
+        return Self::all()
+            .into_iter()
+            .find(|header| {
+                let header_name: String = header.clone().into();
+
+                header_name.eq_ignore_ascii_case(name)
+            });
+    }
 }
 
 impl TryFrom<String> for HeaderName {
@@ -88,7 +156,7 @@ impl Into<String> for HeaderName {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Headers(
     HashMap<HeaderName, String>
 );
@@ -102,6 +170,10 @@ impl Headers {
         return self.0.get(name);
     }
 
+    pub fn iter(self: &Self) -> impl Iterator<Item = (&HeaderName, &String)> {
+        return self.0.iter();
+    }
+
     pub fn insert(self: &mut Self, name: HeaderName, value: String) -> Result<(), Box<dyn Error>> {
         let result = self.0.insert(name, value);
     
@@ -123,6 +195,31 @@ impl Headers {
     }
 }
 
+// headers are an object with a string value for every known header name
+impl JsonSchema for Headers {
+    fn schema_name() -> String {
+        return "Headers".to_string();
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        let properties = HeaderName::all()
+            .into_iter()
+            .map(|name| (name.into(), generator.subschema_for::<String>()))
+            .collect();
+
+        return SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            object: Some(Box::new(ObjectValidation {
+                properties,
+                additional_properties: Some(Box::new(Schema::Bool(false))),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into();
+    }
+}
+
 impl Into<String> for Headers {
     fn into(self) -> String {
         let formatted = self
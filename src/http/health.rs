@@ -0,0 +1,248 @@
+use std::{
+    path::Path,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant}
+};
+use serde_json::{Value, json};
+use crate::{http::{proxy, fastcgi}, config::{ServerConfig, ResponseConfig}};
+
+// how long the readiness check waits for all the upstreams together, so that probes answer in time
+static UPSTREAMS_TIMEOUT: Duration = Duration::from_secs(2);
+
+// the result of a single readiness check, with details on what failed
+struct Check {
+    name: &'static str,
+    passed: bool,
+    details: Value
+}
+
+impl Check {
+    fn to_json(self: &Self) -> Value {
+        let status = match self.passed {
+            true => "pass",
+            false => "fail"
+        };
+
+        return json!({
+            "status": status,
+            "details": self.details
+        });
+    }
+}
+
+pub fn liveness() -> Value {
+    return json!({ "status": "alive" });
+}
+
+// runs all the readiness checks, returning whether all of them passed and their results
+pub fn readiness(config: &ServerConfig, draining: bool) -> (bool, Value) {
+    let checks = [
+        check_draining(draining),
+        check_files(config),
+        check_upstreams(config)
+    ];
+    let ready = checks.iter().all(|check| check.passed);
+    let status = match ready {
+        true => "ready",
+        false => "not ready"
+    };
+    let results = checks
+        .iter()
+        .map(|check| (check.name.to_string(), check.to_json()))
+        .collect::<serde_json::Map<String, Value>>();
+
+    return (ready, json!({
+        "status": status,
+        "checks": results
+    }));
+}
+
+fn check_draining(draining: bool) -> Check {
+    return Check {
+        name: "draining",
+        passed: !draining,
+        details: json!({ "draining": draining })
+    };
+}
+
+// every file route must have its source, otherwise it would answer with 404
+fn check_files(config: &ServerConfig) -> Check {
+    let mut missing = config
+        .responses()
+        .into_iter()
+        .filter_map(|(_, response_config)| match response_config {
+            ResponseConfig::File { source, .. } if !Path::new(source).is_file() => Some(source.clone()),
+            _ => None
+        })
+        .collect::<Vec<String>>();
+
+    missing.sort();
+    missing.dedup();
+
+    return Check {
+        name: "files",
+        passed: missing.is_empty(),
+        details: json!({ "missing": missing })
+    };
+}
+
+// every proxy and fastcgi route must be able to connect to its upstream, otherwise it would answer with 502
+fn check_upstreams(config: &ServerConfig) -> Check {
+    let mut upstreams = config
+        .responses()
+        .into_iter()
+        .filter_map(|(_, response_config)| match response_config {
+            ResponseConfig::Proxy { upstream, .. } | ResponseConfig::FastCgi { upstream, .. } => Some(upstream.clone()),
+            _ => None
+        })
+        .collect::<Vec<String>>();
+
+    upstreams.sort();
+    upstreams.dedup();
+
+    let unreachable = unreachable(upstreams, UPSTREAMS_TIMEOUT, is_reachable);
+
+    return Check {
+        name: "upstreams",
+        passed: unreachable.is_empty(),
+        details: json!({ "unreachable": unreachable })
+    };
+}
+
+// proxy upstreams are URLs, fastcgi ones are "host:port" or "unix:<path>"
+fn is_reachable(upstream: &str) -> bool {
+    return match upstream.contains("://") {
+        true => proxy::is_reachable(upstream),
+        false => fastcgi::is_reachable(upstream)
+    };
+}
+
+// checks all the upstreams at once, the ones that cannot be connected to before the timeout,
+// like the ones whose names take long to resolve, are unreachable
+fn unreachable(upstreams: Vec<String>, timeout: Duration, is_reachable: fn(&str) -> bool) -> Vec<String> {
+    let deadline = Instant::now() + timeout;
+    let (sender, receiver) = mpsc::channel();
+
+    for upstream in &upstreams {
+        let upstream = upstream.clone();
+        let sender = sender.clone();
+
+        // the thread is left to finish on its own if the check stops waiting for it
+        thread::spawn(move || {
+            let is_reachable = is_reachable(&upstream);
+
+            let _ = sender.send((upstream, is_reachable));
+        });
+    }
+
+    drop(sender);
+
+    let mut reachable = Vec::new();
+
+    // stops once every upstream answered, or once the deadline passed
+    while let Ok((upstream, is_reachable)) = receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        if is_reachable {
+            reachable.push(upstream);
+        }
+    }
+
+    return upstreams
+        .into_iter()
+        .filter(|upstream| !reachable.contains(upstream))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, net::TcpListener};
+    use super::*;
+
+    fn config(routes: &str) -> ServerConfig {
+        return ServerConfig::try_from(format!(r#"{{ "port": 8080, "routes": {} }}"#, routes)).unwrap();
+    }
+
+    // an address nothing listens on, the listener is dropped once the port is taken
+    fn closed_address() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        return listener.local_addr().unwrap().to_string();
+    }
+
+    #[test]
+    fn is_ready_with_every_check_passing() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream = format!("http://{}", listener.local_addr().unwrap());
+        let (ready, body) = readiness(&config(&format!(r#"{{ "/": [{{ "method": "GET", "response": {{ "type": "proxy", "upstream": "{}" }} }}] }}"#, upstream)), false);
+
+        assert!(ready);
+        assert_eq!(body["status"], "ready");
+        assert_eq!(body["checks"]["upstreams"], json!({ "status": "pass", "details": { "unreachable": [] } }));
+        assert_eq!(body["checks"]["files"]["status"], "pass");
+        assert_eq!(body["checks"]["draining"]["status"], "pass");
+    }
+
+    #[test]
+    fn is_not_ready_while_draining() {
+        let (ready, body) = readiness(&config("{}"), true);
+
+        assert!(!ready);
+        assert_eq!(body["status"], "not ready");
+        assert_eq!(body["checks"]["draining"], json!({ "status": "fail", "details": { "draining": true } }));
+    }
+
+    #[test]
+    fn lists_missing_files() {
+        let source = std::env::temp_dir().join(format!("quickserving-health-{}.txt", std::process::id()));
+
+        fs::write(&source, "hello").unwrap();
+
+        let config = config(&format!(r#"{{ "/": [{{ "method": "GET", "response": {{ "type": "file", "source": {} }} }}] }}"#, json!(source)));
+
+        assert!(readiness(&config, false).0);
+
+        fs::remove_file(&source).unwrap();
+
+        let (ready, body) = readiness(&config, false);
+
+        assert!(!ready);
+        assert_eq!(body["checks"]["files"]["details"]["missing"], json!([source]));
+    }
+
+    #[test]
+    fn lists_unreachable_upstreams() {
+        let proxy_upstream = format!("http://{}", closed_address());
+        let fastcgi_upstream = closed_address();
+        let config = config(&format!(r#"{{
+            "/": [{{ "method": "GET", "response": {{ "type": "proxy", "upstream": "{}" }} }}],
+            "/index.php": [{{ "method": "GET", "response": {{ "type": "fastcgi", "upstream": "{}", "script_filename": "/var/www/index.php" }} }}]
+        }}"#, proxy_upstream, fastcgi_upstream));
+        let (ready, body) = readiness(&config, false);
+        let mut expected = vec![proxy_upstream, fastcgi_upstream];
+
+        expected.sort();
+
+        assert!(!ready);
+        assert_eq!(body["checks"]["upstreams"]["details"]["unreachable"], json!(expected));
+    }
+
+    #[test]
+    fn checks_the_upstreams_within_the_timeout() {
+        // every upstream takes a second to answer, and the ones starting with "slow" longer than the timeout
+        fn is_reachable(upstream: &str) -> bool {
+            thread::sleep(Duration::from_secs(1));
+
+            if upstream.starts_with("slow") {
+                thread::sleep(Duration::from_secs(2));
+            }
+
+            return !upstream.ends_with("down");
+        }
+
+        let upstreams = ["fast", "fast down", "slow", "other", "more", "others"].map(str::to_string).to_vec();
+        let started = Instant::now();
+
+        assert_eq!(unreachable(upstreams, Duration::from_millis(1500), is_reachable), vec!["fast down", "slow"]);
+        assert!(started.elapsed() < Duration::from_millis(2500), "took {:?}", started.elapsed());
+    }
+}
@@ -0,0 +1,341 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::Mutex
+};
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+use crate::http::{request::Request, response::ResponseSummary, method::Method};
+
+// a request the server received, as it's kept in the journal
+#[derive(Serialize, Clone, Debug)]
+pub struct JournalEntry {
+    pub id: String,
+    // RFC 3339 time the request was served at
+    pub time: String,
+    pub method: Method,
+    pub path: String,
+    pub query: Option<String>,
+    // the headers in the order they were received, a header sent more than once keeps all its values
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    // the key of the route that served the request, missing when no route matched it
+    pub route: Option<String>,
+    pub status: u16
+}
+
+impl JournalEntry {
+    pub fn new(request: &Request, summary: &ResponseSummary) -> Self {
+        return Self {
+            id: request.id.clone(),
+            time: Utc::now().to_rfc3339(),
+            method: request.method.clone(),
+            path: request.path.clone(),
+            query: request.query.clone(),
+            headers: request.raw_headers.clone(),
+            body: request.body_text().to_string(),
+            route: summary.route.clone(),
+            status: summary.status_code
+        };
+    }
+
+    // whether any of the values of the header is the given one
+    fn has_header(self: &Self, name: &str, value: &str) -> bool {
+        return self.headers
+            .iter()
+            .any(|(header, header_value)| header.eq_ignore_ascii_case(name) && header_value == value);
+    }
+}
+
+// which requests to look for in the journal, every field that is set must match
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RequestFilter {
+    #[serde(default)]
+    pub method: Option<Method>,
+    #[serde(default)]
+    pub path: Option<String>,
+    // headers the request must have with exactly these values, their names are not case sensitive
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    // text the body must contain
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub route: Option<String>,
+    // whether the request must or must not have matched a route
+    #[serde(default)]
+    pub matched: Option<bool>
+}
+
+impl RequestFilter {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn method(self: Self, method: Method) -> Self {
+        return Self { method: Some(method), ..self };
+    }
+
+    pub fn path(self: Self, path: &str) -> Self {
+        return Self { path: Some(path.to_string()), ..self };
+    }
+
+    pub fn header(self: Self, name: &str, value: &str) -> Self {
+        let mut headers = self.headers;
+
+        headers.insert(name.to_string(), value.to_string());
+
+        return Self { headers, ..self };
+    }
+
+    pub fn body(self: Self, body: &str) -> Self {
+        return Self { body: Some(body.to_string()), ..self };
+    }
+
+    pub fn route(self: Self, route: &str) -> Self {
+        return Self { route: Some(route.to_string()), ..self };
+    }
+
+    pub fn matched(self: Self, matched: bool) -> Self {
+        return Self { matched: Some(matched), ..self };
+    }
+
+    pub fn matches(self: &Self, entry: &JournalEntry) -> bool {
+        let method = self.method.as_ref().is_none_or(|method| method == &entry.method);
+        let path = self.path.as_ref().is_none_or(|path| path == &entry.path);
+        let headers = self.headers
+            .iter()
+            .all(|(name, value)| entry.has_header(name, value));
+        let body = self.body.as_ref().is_none_or(|body| entry.body.contains(body.as_str()));
+        let route = self.route.as_ref().is_none_or(|route| Some(route) == entry.route.as_ref());
+        let matched = self.matched.is_none_or(|matched| matched == entry.route.is_some());
+
+        return method && path && headers && body && route && matched;
+    }
+}
+
+// describes the filter like "POST /orders with X-Api-Key: abc"
+impl fmt::Display for RequestFilter {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        let method: String = match &self.method {
+            Some(method) => method.clone().into(),
+            None => "any method".to_string()
+        };
+        let path = self.path.as_deref().unwrap_or("any path");
+
+        write!(f, "{} {}", method, path)?;
+
+        let mut headers = self.headers.iter().collect::<Vec<(&String, &String)>>();
+
+        headers.sort();
+
+        for (name, value) in headers {
+            write!(f, " with {}: {}", name, value)?;
+        }
+
+        if let Some(body) = &self.body {
+            write!(f, " with body containing `{}`", body)?;
+        }
+
+        if let Some(route) = &self.route {
+            write!(f, " served by route `{}`", route)?;
+        }
+
+        return match self.matched {
+            Some(true) => write!(f, " matching a route"),
+            Some(false) => write!(f, " matching no route"),
+            None => Ok(())
+        };
+    }
+}
+
+// how many times the requests are expected to be received
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Times {
+    Exactly(usize),
+    AtLeast(usize),
+    AtMost(usize)
+}
+
+impl Times {
+    fn allows(self: &Self, count: usize) -> bool {
+        return match self {
+            Self::Exactly(times) => count == *times,
+            Self::AtLeast(times) => count >= *times,
+            Self::AtMost(times) => count <= *times
+        };
+    }
+}
+
+impl fmt::Display for Times {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Self::Exactly(times) => write!(f, "exactly {} time(s)", times),
+            Self::AtLeast(times) => write!(f, "at least {} time(s)", times),
+            Self::AtMost(times) => write!(f, "at most {} time(s)", times)
+        };
+    }
+}
+
+// the most recent requests the server received, the oldest ones are dropped once it's full
+pub struct Journal {
+    entries: Mutex<VecDeque<JournalEntry>>
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        return Self { entries: Mutex::new(VecDeque::new()) };
+    }
+
+    pub fn record(self: &Self, entry: JournalEntry, capacity: usize) {
+        let mut entries = self.lock();
+
+        entries.push_back(entry);
+
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+    }
+
+    // the requests matching the filter, from the oldest one
+    pub fn entries(self: &Self, filter: &RequestFilter) -> Vec<JournalEntry> {
+        return self.lock()
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .cloned()
+            .collect();
+    }
+
+    // the requests no route matched, usually the ones a mock is missing for
+    pub fn unmatched(self: &Self) -> Vec<JournalEntry> {
+        return self.entries(&RequestFilter::new().matched(false));
+    }
+
+    pub fn count(self: &Self, filter: &RequestFilter) -> usize {
+        return self.lock()
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .count();
+    }
+
+    // checks the requests matching the filter were received the expected number of times,
+    // returning how many times they were or why the verification failed
+    pub fn verify(self: &Self, filter: &RequestFilter, times: Times) -> Result<usize, String> {
+        let count = self.count(filter);
+
+        if !times.allows(count) {
+            return Err(format!("Expected {} to be received {}, but it was received {} time(s).", filter, times, count));
+        }
+
+        return Ok(count);
+    }
+
+    pub fn clear(self: &Self) {
+        self.lock().clear();
+    }
+
+    fn lock(self: &Self) -> std::sync::MutexGuard<'_, VecDeque<JournalEntry>> {
+        return match self.entries.lock() {
+            Ok(entries) => entries,
+            Err(poisoned) => poisoned.into_inner()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::{headers::Headers, version::Version};
+    use super::*;
+
+    fn entry(method: Method, path: &str, headers: Vec<(&str, &str)>, route: Option<&str>) -> JournalEntry {
+        let request = Request::new(
+            method,
+            path.to_string(),
+            None,
+            Version::new("HTTP".to_string(), "1.1".to_string()),
+            Headers::new(),
+            b"{\"item\": \"book\"}".to_vec()
+        ).with_raw_headers(headers.into_iter().map(|(name, value)| (name.to_string(), value.to_string())).collect());
+        let summary = ResponseSummary { status_code: 200, body_bytes: 0, route: route.map(|route| route.to_string()) };
+
+        return JournalEntry::new(&request, &summary);
+    }
+
+    fn journal() -> Journal {
+        let journal = Journal::new();
+
+        journal.record(entry(Method::POST, "/orders", vec![("X-Api-Key", "abc")], Some("/orders")), 10);
+        journal.record(entry(Method::POST, "/orders", vec![("x-api-key", "def")], Some("/orders")), 10);
+        journal.record(entry(Method::GET, "/orders", Vec::new(), Some("/orders")), 10);
+        journal.record(entry(Method::GET, "/missing", Vec::new(), None), 10);
+
+        return journal;
+    }
+
+    #[test]
+    fn matches_every_field_that_is_set() {
+        let entry = entry(Method::POST, "/orders", vec![("X-Api-Key", "abc")], Some("/orders"));
+
+        assert!(RequestFilter::new().matches(&entry));
+        assert!(RequestFilter::new().method(Method::POST).path("/orders").body("book").route("/orders").matched(true).matches(&entry));
+        assert!(!RequestFilter::new().method(Method::GET).matches(&entry));
+        assert!(!RequestFilter::new().path("/order").matches(&entry));
+        assert!(!RequestFilter::new().body("pen").matches(&entry));
+        assert!(!RequestFilter::new().route("/").matches(&entry));
+        assert!(!RequestFilter::new().matched(false).matches(&entry));
+    }
+
+    #[test]
+    fn matches_header_names_ignoring_the_case() {
+        let entry = entry(Method::GET, "/", vec![("X-Api-Key", "abc")], None);
+
+        assert!(RequestFilter::new().header("x-api-key", "abc").matches(&entry));
+        assert!(RequestFilter::new().header("X-API-KEY", "abc").matches(&entry));
+        assert!(!RequestFilter::new().header("X-Api-Key", "ABC").matches(&entry));
+        assert!(!RequestFilter::new().header("X-Other", "abc").matches(&entry));
+    }
+
+    #[test]
+    fn keeps_every_value_of_repeated_headers() {
+        let entry = entry(Method::GET, "/", vec![("Cookie", "a=1"), ("Accept", "text/html"), ("Cookie", "b=2")], None);
+
+        assert_eq!(entry.headers.len(), 3);
+        assert!(RequestFilter::new().header("Cookie", "a=1").matches(&entry));
+        assert!(RequestFilter::new().header("cookie", "b=2").matches(&entry));
+    }
+
+    #[test]
+    fn verifies_the_number_of_times() {
+        let journal = journal();
+        let filter = RequestFilter::new().method(Method::POST).path("/orders");
+
+        assert_eq!(journal.verify(&filter, Times::Exactly(2)), Ok(2));
+        assert_eq!(
+            journal.verify(&filter, Times::Exactly(1)),
+            Err("Expected POST /orders to be received exactly 1 time(s), but it was received 2 time(s).".to_string())
+        );
+        assert_eq!(journal.verify(&filter, Times::AtLeast(2)), Ok(2));
+        assert_eq!(journal.verify(&filter, Times::AtLeast(1)), Ok(2));
+        assert!(journal.verify(&filter, Times::AtLeast(3)).is_err());
+        assert_eq!(journal.verify(&filter, Times::AtMost(2)), Ok(2));
+        assert!(journal.verify(&filter, Times::AtMost(1)).is_err());
+        assert_eq!(journal.verify(&filter.header("X-API-KEY", "abc"), Times::Exactly(1)), Ok(1));
+    }
+
+    #[test]
+    fn lists_unmatched_requests_and_drops_the_oldest_ones() {
+        let journal = journal();
+
+        assert_eq!(journal.unmatched().iter().map(|entry| entry.path.as_str()).collect::<Vec<&str>>(), vec!["/missing"]);
+
+        journal.record(entry(Method::GET, "/latest", Vec::new(), None), 2);
+
+        assert_eq!(journal.entries(&RequestFilter::new()).iter().map(|entry| entry.path.as_str()).collect::<Vec<&str>>(), vec!["/missing", "/latest"]);
+
+        journal.clear();
+
+        assert_eq!(journal.count(&RequestFilter::new()), 0);
+    }
+}
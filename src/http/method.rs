@@ -1,6 +1,7 @@
 use serde::{self, Serialize, Deserialize};
+use schemars::JsonSchema;
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Hash)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Debug, Hash)]
 pub enum Method {
     GET,
     HEAD,
@@ -13,6 +14,22 @@ pub enum Method {
     CONNECT
 }
 
+impl Method {
+    pub fn all() -> Vec<Self> {
+        return vec![
+            Self::GET,
+            Self::HEAD,
+            Self::OPTIONS,
+            Self::TRACE,
+            Self::PUT,
+            Self::DELETE,
+            Self::POST,
+            Self::PATCH,
+            Self::CONNECT
+        ];
+    }
+}
+
 impl TryFrom<String> for Method {
     type Error = String;
 
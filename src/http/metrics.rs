@@ -0,0 +1,244 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{Mutex, atomic::{AtomicI64, AtomicU64, Ordering}},
+    time::Duration
+};
+use serde_json::{Value, json};
+use crate::{error::ParseError, http::response::ResponseSummary};
+
+// upper bounds of the latency histogram buckets, in seconds
+static LATENCY_BUCKETS: [f64; 11] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+// the counters of a single route and method
+struct RouteStats {
+    statuses: HashMap<u16, u64>,
+    // cumulative counts of requests that took at most as long as the matching bucket
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    latency_sum: f64
+}
+
+impl RouteStats {
+    fn new() -> Self {
+        return Self {
+            statuses: HashMap::new(),
+            buckets: [0; LATENCY_BUCKETS.len()],
+            count: 0,
+            latency_sum: 0.0
+        };
+    }
+
+    fn observe(self: &mut Self, status_code: u16, latency: f64) {
+        *self.statuses.entry(status_code).or_insert(0) += 1;
+
+        for (idx, bound) in LATENCY_BUCKETS.iter().enumerate() {
+            if latency <= *bound {
+                self.buckets[idx] += 1;
+            }
+        }
+
+        self.count += 1;
+        self.latency_sum += latency;
+    }
+}
+
+// counters of everything the server does, they are always collected, as that's only a few
+// atomic operations and a short lock per request, and rendered in the Prometheus text format
+pub struct Metrics {
+    // keyed by route and method, requests that matched no route have an empty route
+    routes: Mutex<HashMap<(String, String), RouteStats>>,
+    in_flight: AtomicI64,
+    sent_bytes: AtomicU64,
+    malformed: AtomicU64,
+    headers_too_large: AtomicU64,
+    body_too_large: AtomicU64,
+    unsupported_method: AtomicU64,
+    reloads: AtomicU64,
+    failed_reloads: AtomicU64
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        return Self {
+            routes: Mutex::new(HashMap::new()),
+            in_flight: AtomicI64::new(0),
+            sent_bytes: AtomicU64::new(0),
+            malformed: AtomicU64::new(0),
+            headers_too_large: AtomicU64::new(0),
+            body_too_large: AtomicU64::new(0),
+            unsupported_method: AtomicU64::new(0),
+            reloads: AtomicU64::new(0),
+            failed_reloads: AtomicU64::new(0)
+        };
+    }
+
+    pub fn connection_opened(self: &Self) {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_closed(self: &Self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    // the number of connections being served right now
+    pub fn in_flight(self: &Self) -> i64 {
+        return self.in_flight.load(Ordering::Relaxed);
+    }
+
+    pub fn record_request(self: &Self, method: &str, summary: &ResponseSummary, latency: Duration) {
+        let key = (summary.route.clone().unwrap_or_default(), method.to_string());
+        let mut routes = match self.routes.lock() {
+            Ok(routes) => routes,
+            Err(poisoned) => poisoned.into_inner()
+        };
+
+        routes
+            .entry(key)
+            .or_insert_with(RouteStats::new)
+            .observe(summary.status_code, latency.as_secs_f64());
+        drop(routes);
+
+        self.sent_bytes.fetch_add(summary.body_bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_parse_error(self: &Self, err: &ParseError, summary: &ResponseSummary) {
+        let counter = match err {
+            ParseError::Malformed(_) => &self.malformed,
+            ParseError::HeadersTooLarge => &self.headers_too_large,
+            ParseError::BodyTooLarge => &self.body_too_large,
+            ParseError::UnsupportedMethod(_) => &self.unsupported_method
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.sent_bytes.fetch_add(summary.body_bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_reload(self: &Self, succeeded: bool) {
+        let counter = match succeeded {
+            true => &self.reloads,
+            false => &self.failed_reloads
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // the totals of all the metrics as JSON, with the requests counted by route
+    pub fn stats(self: &Self) -> Value {
+        let routes = match self.routes.lock() {
+            Ok(routes) => routes,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        let mut requests = 0;
+        let mut by_route = serde_json::Map::new();
+
+        for ((route, _), stats) in routes.iter() {
+            let count = by_route
+                .get(route)
+                .and_then(|count| count.as_u64())
+                .unwrap_or(0);
+
+            requests += stats.count;
+            by_route.insert(route.clone(), json!(count + stats.count));
+        }
+
+        drop(routes);
+
+        return json!({
+            "requests": requests,
+            "requests_by_route": by_route,
+            "in_flight": self.in_flight.load(Ordering::Relaxed),
+            "sent_bytes": self.sent_bytes.load(Ordering::Relaxed),
+            "parse_errors": self.malformed.load(Ordering::Relaxed)
+                + self.headers_too_large.load(Ordering::Relaxed)
+                + self.body_too_large.load(Ordering::Relaxed)
+                + self.unsupported_method.load(Ordering::Relaxed),
+            "reloads": self.reloads.load(Ordering::Relaxed),
+            "failed_reloads": self.failed_reloads.load(Ordering::Relaxed)
+        });
+    }
+
+    // renders all the metrics in the Prometheus text exposition format
+    pub fn render(self: &Self) -> String {
+        let mut out = String::new();
+        let routes = match self.routes.lock() {
+            Ok(routes) => routes,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        // sorted, so that the output is stable between scrapes
+        let mut keys = routes.keys().collect::<Vec<&(String, String)>>();
+
+        keys.sort();
+
+        let _ = writeln!(out, "# HELP quickserving_requests_total Served requests by route, method and status.");
+        let _ = writeln!(out, "# TYPE quickserving_requests_total counter");
+
+        for key in &keys {
+            let stats = &routes[*key];
+            let mut statuses = stats.statuses.iter().collect::<Vec<(&u16, &u64)>>();
+
+            statuses.sort();
+
+            for (status, count) in statuses {
+                let _ = writeln!(
+                    out,
+                    "quickserving_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}",
+                    escape(&key.0), escape(&key.1), status, count
+                );
+            }
+        }
+
+        let _ = writeln!(out, "# HELP quickserving_request_duration_seconds Time it took to serve requests by route and method.");
+        let _ = writeln!(out, "# TYPE quickserving_request_duration_seconds histogram");
+
+        for key in &keys {
+            let stats = &routes[*key];
+            let labels = format!("route=\"{}\",method=\"{}\"", escape(&key.0), escape(&key.1));
+
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(stats.buckets.iter()) {
+                let _ = writeln!(out, "quickserving_request_duration_seconds_bucket{{{},le=\"{}\"}} {}", labels, bound, count);
+            }
+
+            let _ = writeln!(out, "quickserving_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, stats.count);
+            let _ = writeln!(out, "quickserving_request_duration_seconds_sum{{{}}} {}", labels, stats.latency_sum);
+            let _ = writeln!(out, "quickserving_request_duration_seconds_count{{{}}} {}", labels, stats.count);
+        }
+
+        drop(routes);
+
+        let _ = writeln!(out, "# HELP quickserving_connections_in_flight Connections being served right now.");
+        let _ = writeln!(out, "# TYPE quickserving_connections_in_flight gauge");
+        let _ = writeln!(out, "quickserving_connections_in_flight {}", self.in_flight.load(Ordering::Relaxed));
+
+        let _ = writeln!(out, "# HELP quickserving_sent_bytes_total Bytes of response bodies sent.");
+        let _ = writeln!(out, "# TYPE quickserving_sent_bytes_total counter");
+        let _ = writeln!(out, "quickserving_sent_bytes_total {}", self.sent_bytes.load(Ordering::Relaxed));
+
+        let _ = writeln!(out, "# HELP quickserving_parse_errors_total Requests that could not be parsed by reason.");
+        let _ = writeln!(out, "# TYPE quickserving_parse_errors_total counter");
+
+        for (reason, counter) in [
+            ("malformed", &self.malformed),
+            ("headers_too_large", &self.headers_too_large),
+            ("body_too_large", &self.body_too_large),
+            ("unsupported_method", &self.unsupported_method)
+        ] {
+            let _ = writeln!(out, "quickserving_parse_errors_total{{reason=\"{}\"}} {}", reason, counter.load(Ordering::Relaxed));
+        }
+
+        let _ = writeln!(out, "# HELP quickserving_config_reloads_total Config reloads by result.");
+        let _ = writeln!(out, "# TYPE quickserving_config_reloads_total counter");
+        let _ = writeln!(out, "quickserving_config_reloads_total{{result=\"success\"}} {}", self.reloads.load(Ordering::Relaxed));
+        let _ = writeln!(out, "quickserving_config_reloads_total{{result=\"failure\"}} {}", self.failed_reloads.load(Ordering::Relaxed));
+
+        return out;
+    }
+}
+
+// escapes a label value, backslashes, quotes and new lines are not allowed in them
+fn escape(value: &str) -> String {
+    return value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+}
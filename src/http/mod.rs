@@ -1,7 +1,23 @@
+pub mod admin;
+pub mod cgi;
 pub mod headers;
+pub mod faults;
+pub mod fastcgi;
+pub mod health;
+pub mod journal;
 pub mod version;
 pub mod method;
+pub mod metrics;
+pub mod proxy;
+pub mod random;
+pub mod record;
 pub mod status;
+pub mod template;
 pub mod request;
+pub mod request_id;
 pub mod response;
+pub mod scenarios;
+pub mod script;
+pub mod sequences;
 pub mod server;
+pub mod shutdown;
@@ -0,0 +1,182 @@
+use std::{
+    io::Read,
+    net::{TcpStream, ToSocketAddrs},
+    sync::OnceLock,
+    time::Duration
+};
+use crate::{error::Error, http::{request::Request, response::Response, headers::{Headers, HeaderName}, status::Status, version::Version}};
+
+static TIMEOUT: Duration = Duration::from_secs(30);
+static CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+// headers describing a single connection or the encoding of the body, they are not forwarded
+// as the connection to the upstream is a different one and the body is sent decoded
+static HOP_BY_HOP_HEADERS: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "transfer-encoding",
+    "te",
+    "upgrade",
+    "host",
+    "content-length",
+    "content-encoding"
+];
+
+// the client is shared by all the requests, so that connections to upstreams are reused
+fn agent() -> &'static ureq::Agent {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+
+    return AGENT.get_or_init(|| ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .redirects(0)
+        .build());
+}
+
+// the URL the request is forwarded to, the upstream with the request's path and query
+pub fn upstream_url(upstream: &str, request: &Request) -> String {
+    let url = format!("{}{}", upstream.trim_end_matches('/'), request.path);
+
+    return match &request.query {
+        Some(query) => format!("{}?{}", url, query),
+        None => url
+    };
+}
+
+// sends the request to the upstream and turns its answer into our response,
+// error statuses of the upstream are passed on to the client as they are
+pub fn forward(request: &Request, upstream: &str) -> Result<Response, Error> {
+    let method: String = request.method.clone().into();
+    let mut upstream_request = agent().request(&method, &upstream_url(upstream, request));
+
+    for (name, value) in &request.raw_headers {
+        if HOP_BY_HOP_HEADERS.contains(&name.to_lowercase().as_str()) || name.eq_ignore_ascii_case("accept-encoding") {
+            continue;
+        }
+
+        upstream_request = upstream_request.set(name, value);
+    }
+
+    // the upstream gets the same request id, so that its logs can be correlated with ours
+    upstream_request = upstream_request.set("X-Request-Id", &request.id);
+
+    let upstream_response = match upstream_request.send_bytes(&request.body) {
+        Ok(response) => response,
+        Err(ureq::Error::Status(_, response)) => response,
+        Err(ureq::Error::Transport(err)) => return Err(Error::Upstream(err.to_string()))
+    };
+    let status_code = upstream_response.status();
+    let mut headers = Vec::new();
+    let names = upstream_response.headers_names();
+
+    // the names are listed once for every value, a header sent more than once, like Set-Cookie, keeps all its values
+    for (idx, name) in names.iter().enumerate() {
+        if HOP_BY_HOP_HEADERS.contains(&name.as_str()) || names[..idx].contains(name) {
+            continue;
+        }
+
+        for value in upstream_response.all(name) {
+            headers.push((name.clone(), value.to_string()));
+        }
+    }
+
+    // the body is read as it was sent, it may not be text and it may be larger than ureq lets a string be
+    let mut body = Vec::new();
+
+    if let Err(err) = upstream_response.into_reader().read_to_end(&mut body) {
+        return Err(Error::Upstream(format!("Cannot read the response body. {}", err)));
+    }
+
+    let content_length = body.len().to_string();
+
+    return Ok(Response::new(
+        Status { status_code },
+        Version::new("HTTP".to_string(), "1.1".to_string()),
+        Headers::new(),
+        body
+    ).with_headers(headers).with_header(HeaderName::ContentLength, content_length));
+}
+
+// whether a connection to the upstream can be opened, used by the readiness check
+pub fn is_reachable(upstream: &str) -> bool {
+    let (scheme, rest) = match upstream.split_once("://") {
+        Some(parts) => parts,
+        None => return false
+    };
+    let authority = rest.split('/').next().unwrap_or(rest);
+    let address = match authority.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => authority.to_string(),
+        _ => match scheme {
+            "https" => format!("{}:443", authority),
+            _ => format!("{}:80", authority)
+        }
+    };
+    let addresses = match address.to_socket_addrs() {
+        Ok(addresses) => addresses,
+        Err(_) => return false
+    };
+
+    return addresses
+        .into_iter()
+        .any(|address| TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).is_ok());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, net::TcpListener, thread};
+    use crate::http::{method::Method, version::Version};
+    use super::*;
+
+    // answers a single request with the body, returning the upstream's URL
+    fn upstream(body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut head = Vec::new();
+            let mut byte = [0u8; 1];
+
+            while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                head.push(byte[0]);
+            }
+
+            let _ = write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\n\r\n", body.len());
+            let _ = stream.write_all(&body);
+        });
+
+        return upstream;
+    }
+
+    fn get(upstream: &str) -> Response {
+        let request = Request::new(
+            Method::GET,
+            "/image.png".to_string(),
+            None,
+            Version::new("HTTP".to_string(), "1.1".to_string()),
+            Headers::new(),
+            Vec::new()
+        );
+
+        return forward(&request, upstream).unwrap();
+    }
+
+    #[test]
+    fn passes_binary_bodies_through() {
+        let body = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0xff, 0xfe, 0xc3];
+        let response = get(&upstream(body.clone()));
+
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.body(), &body);
+        assert_eq!(response.headers().get(&HeaderName::ContentLength), Some(&body.len().to_string()));
+        assert_eq!(response.headers().get(&HeaderName::ContentType), Some(&"image/png".to_string()));
+    }
+
+    #[test]
+    fn passes_large_bodies_through() {
+        let body = vec![b'a'; 11 * 1024 * 1024];
+        let response = get(&upstream(body.clone()));
+
+        assert_eq!(response.status_code(), 200);
+        assert_eq!(response.body().len(), body.len());
+    }
+}
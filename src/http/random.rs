@@ -0,0 +1,127 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// the std hasher is seeded randomly for every RandomState, which makes it a good enough source of
+// randomness for ids and mock data, it's not meant for anything that has to be unpredictable
+pub fn next_u64() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or(0);
+    let mut hasher = RandomState::new().build_hasher();
+
+    hasher.write_u128(nanos);
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+
+    return hasher.finish();
+}
+
+// a random integer between min and max, both included
+pub fn between(min: i64, max: i64) -> i64 {
+    let (min, max) = match min <= max {
+        true => (min, max),
+        false => (max, min)
+    };
+    let span = (max as i128 - min as i128 + 1) as u128;
+
+    return (min as i128 + (next_u64() as u128 % span) as i128) as i64;
+}
+
+// a random version 4 UUID, like "1b4e28ba-2fa1-41d2-883f-0016d3cca427"
+pub fn uuid() -> String {
+    let high = next_u64();
+    let low = next_u64();
+    // the version is the first digit of the third group, the variant the first bits of the fourth
+    let high = (high & 0xffff_ffff_ffff_0fff) | 0x0000_0000_0000_4000;
+    let low = (low & 0x3fff_ffff_ffff_ffff) | 0x8000_0000_0000_0000;
+
+    return format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff
+    );
+}
+
+// a seeded generator, it gives the same numbers for the same seed, so that runs can be reproduced
+#[derive(Debug, Clone)]
+pub struct SeededRandom {
+    state: u64
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        return Self { state: seed };
+    }
+
+    // SplitMix64, which is small and passes the usual statistical tests
+    pub fn next_u64(self: &mut Self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.state;
+
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+        return z ^ (z >> 31);
+    }
+}
+
+// whether something that happens with the probability, from 0 to 1, happens this time
+pub fn chance(probability: f64) -> bool {
+    // the top 53 bits are as many as a f64 holds exactly
+    let roll = (next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+
+    return roll < probability;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_the_numbers_of_a_seed() {
+        let mut first = SeededRandom::new(42);
+        let mut second = SeededRandom::new(42);
+        let mut other = SeededRandom::new(43);
+        let numbers = (0..10).map(|_| first.next_u64()).collect::<Vec<u64>>();
+
+        assert_eq!((0..10).map(|_| second.next_u64()).collect::<Vec<u64>>(), numbers);
+        assert_ne!((0..10).map(|_| other.next_u64()).collect::<Vec<u64>>(), numbers);
+    }
+
+    #[test]
+    fn stays_between_the_bounds() {
+        for _ in 0..1000 {
+            assert!((-3..=3).contains(&between(-3, 3)));
+            assert!((-3..=3).contains(&between(3, -3)));
+        }
+
+        assert_eq!(between(5, 5), 5);
+    }
+
+    #[test]
+    fn creates_version_4_uuids() {
+        let uuid = uuid();
+        let groups = uuid.split('-').collect::<Vec<&str>>();
+
+        assert_eq!(groups.iter().map(|group| group.len()).collect::<Vec<usize>>(), vec![8, 4, 4, 4, 12]);
+        assert!(groups[2].starts_with('4'));
+        assert!(["8", "9", "a", "b"].iter().any(|variant| groups[3].starts_with(variant)));
+        assert_ne!(uuid, super::uuid());
+    }
+
+    #[test]
+    fn happens_with_certain_probabilities() {
+        assert!((0..100).all(|_| chance(1.0)));
+        assert!((0..100).all(|_| !chance(0.0)));
+    }
+}
@@ -0,0 +1,359 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::PathBuf,
+    sync::Mutex,
+    time::Instant
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::Utc;
+use serde_json::{Value, json};
+use crate::{
+    error::Error,
+    logging::{LogLevel, log},
+    http::{proxy, request::Request, response::Response, headers::{Headers, HeaderName}, template::CompiledTemplate},
+    config::{RouteConfig, ResponseConfig, ResponseHTTPConfig, RawHeader}
+};
+
+// response headers that describe the recorded response rather than its content, they are not recorded
+static SKIPPED_HEADERS: [HeaderName; 3] = [HeaderName::ContentLength, HeaderName::Date, HeaderName::XRequestId];
+
+pub struct RecordOptions {
+    // base URL of the server every request is forwarded to
+    pub upstream: String,
+    // the port the recording is served on when replayed
+    pub port: u16,
+    // where the config with the recorded routes is written to
+    pub output: PathBuf,
+    // when set, response bodies are saved to files in this directory and served as file responses
+    pub bodies: Option<PathBuf>,
+    // when set, the traffic is also written to this file as HAR
+    pub har: Option<PathBuf>
+}
+
+struct Recording {
+    // sorted, so that the written config is stable
+    routes: BTreeMap<String, Vec<RouteConfig>>,
+    har_entries: Vec<Value>,
+    saved_bodies: usize
+}
+
+// forwards every request to the upstream and records the request and response pairs as routes,
+// the recording is written out after every request, so it's complete whenever the server is stopped
+pub struct Recorder {
+    options: RecordOptions,
+    recording: Mutex<Recording>
+}
+
+impl Recorder {
+    pub fn new(options: RecordOptions) -> Self {
+        return Self {
+            options,
+            recording: Mutex::new(Recording {
+                routes: BTreeMap::new(),
+                har_entries: Vec::new(),
+                saved_bodies: 0
+            })
+        };
+    }
+
+    pub fn upstream(self: &Self) -> &str {
+        return &self.options.upstream;
+    }
+
+    pub fn forward(self: &Self, request: &Request) -> Result<Response, Error> {
+        let started = Utc::now();
+        let timer = Instant::now();
+        let response = proxy::forward(request, &self.options.upstream)?;
+        let elapsed = timer.elapsed();
+        let mut recording = match self.recording.lock() {
+            Ok(recording) => recording,
+            Err(poisoned) => poisoned.into_inner()
+        };
+
+        let route_config = self.route_config(request, &response, &mut recording);
+        let route_configs = recording.routes.entry(request.path.clone()).or_default();
+
+        // a repeated request replaces the response recorded for it before
+        route_configs.retain(|existing| !existing.same_requests(&route_config));
+        route_configs.push(route_config);
+
+        if self.options.har.is_some() {
+            let entry = har_entry(&self.options.upstream, request, &response, &started.to_rfc3339(), elapsed.as_secs_f64() * 1000.0);
+
+            recording.har_entries.push(entry);
+        }
+
+        if let Err(err) = self.write(&recording) {
+            log(LogLevel::ERROR, format!("Cannot write the recording. {}", err));
+        }
+
+        return Ok(response);
+    }
+
+    fn route_config(self: &Self, request: &Request, response: &Response, recording: &mut Recording) -> RouteConfig {
+        let mut headers = Headers::new();
+
+        for (name, value) in response.headers().iter() {
+            if !SKIPPED_HEADERS.contains(name) {
+                let _ = headers.insert(name.clone(), value.clone());
+            }
+        }
+
+        // the headers we don't know, like Set-Cookie, are recorded as they were sent
+        let raw_headers = response.raw_headers()
+            .iter()
+            .map(|(name, value)| RawHeader { name: name.clone(), value: value.clone() })
+            .collect();
+        let status = match response.status_code() {
+            200 => None,
+            status_code => Some(status_code)
+        };
+        let http = ResponseHTTPConfig { status, headers, raw_headers };
+        let response_config = match &self.options.bodies {
+            Some(dir) => {
+                recording.saved_bodies += 1;
+
+                let source = dir.join(body_file_name(request, response, recording.saved_bodies));
+
+                match fs::create_dir_all(dir).and_then(|_| fs::write(&source, response.body())) {
+                    Ok(_) => ResponseConfig::File { source: source.display().to_string(), templated: false, http },
+                    Err(err) => {
+                        log(LogLevel::ERROR, format!("Cannot save response body to \"{}\", recording it as text. {}", source.display(), err));
+                        text_response(request, response, http)
+                    }
+                }
+            },
+            None => text_response(request, response, http)
+        };
+        let mut route_config = RouteConfig::new(request.method.clone(), response_config);
+        let query = request.query_params().into_iter().collect::<HashMap<String, String>>();
+
+        if !query.is_empty() {
+            route_config = route_config.with_query(query);
+        }
+
+        if !request.body.is_empty() {
+            route_config = route_config.with_body(request.body_text().to_string());
+        }
+
+        return route_config;
+    }
+
+    fn write(self: &Self, recording: &Recording) -> Result<(), Error> {
+        let config = json!({
+            "port": self.options.port,
+            "routes": recording.routes
+        });
+
+        fs::write(&self.options.output, to_pretty_json(&config)?)?;
+
+        if let Some(har) = &self.options.har {
+            let log = json!({
+                "log": {
+                    "version": "1.2",
+                    "creator": {
+                        "name": "quickserving",
+                        "version": env!("CARGO_PKG_VERSION")
+                    },
+                    "entries": recording.har_entries
+                }
+            });
+
+            fs::write(har, to_pretty_json(&log)?)?;
+        }
+
+        return Ok(());
+    }
+}
+
+fn to_pretty_json(value: &Value) -> Result<String, Error> {
+    return serde_json::to_string_pretty(value).map_err(|err| Error::Config(err.to_string()));
+}
+
+// text responses can only hold text, so a body that is not text is recorded with its invalid bytes replaced
+fn text_response(request: &Request, response: &Response, http: ResponseHTTPConfig) -> ResponseConfig {
+    if std::str::from_utf8(response.body()).is_err() {
+        log(LogLevel::WARN, format!("The response to {} is not text, record it with --bodies to replay it as it was sent.", request.path));
+    }
+
+    return ResponseConfig::Text { text: response.body_text().to_string(), templated: false, http, compiled: CompiledTemplate::default() };
+}
+
+// names the body file after the request, e.g. "3_get_api_users.json"
+fn body_file_name(request: &Request, response: &Response, idx: usize) -> String {
+    let method: String = request.method.clone().into();
+    let path = request.path
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    let content_type = response.headers()
+        .get(&HeaderName::ContentType)
+        .map(|content_type| content_type.to_lowercase())
+        .unwrap_or_default();
+    let extension = match content_type.as_str() {
+        content_type if content_type.contains("json") => "json",
+        content_type if content_type.contains("html") => "html",
+        content_type if content_type.contains("xml") => "xml",
+        content_type if content_type.contains("javascript") => "js",
+        content_type if content_type.contains("css") => "css",
+        _ => "txt"
+    };
+
+    return format!("{}_{}{}.{}", idx, method.to_lowercase(), path.trim_end_matches('_'), extension);
+}
+
+fn har_headers(headers: impl Iterator<Item = (String, String)>) -> Value {
+    return headers
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect();
+}
+
+// the text of the content as it is, or base64 encoded when it's not text
+fn har_content(body: &[u8], mime_type: &str) -> Value {
+    return match std::str::from_utf8(body) {
+        Ok(text) => json!({ "size": body.len(), "mimeType": mime_type, "text": text }),
+        Err(_) => json!({ "size": body.len(), "mimeType": mime_type, "text": STANDARD.encode(body), "encoding": "base64" })
+    };
+}
+
+// a HAR 1.2 entry of the request and response pair
+fn har_entry(upstream: &str, request: &Request, response: &Response, started: &str, time: f64) -> Value {
+    let method: String = request.method.clone().into();
+    let request_content_type = request.header("Content-Type").unwrap_or("").to_string();
+    let response_content_type = response.headers()
+        .get(&HeaderName::ContentType)
+        .cloned()
+        .unwrap_or_default();
+    let mut request_json = json!({
+        "method": method,
+        "url": proxy::upstream_url(upstream, request),
+        "httpVersion": request.version.to_string(),
+        "headers": har_headers(request.raw_headers.iter().cloned()),
+        "queryString": har_headers(request.query_params().into_iter()),
+        "cookies": [],
+        "headersSize": -1,
+        "bodySize": request.body.len()
+    });
+
+    if !request.body.is_empty() {
+        request_json["postData"] = json!({
+            "mimeType": request_content_type,
+            "text": request.body_text()
+        });
+    }
+
+    let response_headers = response.headers()
+        .iter()
+        .map(|(name, value)| (name.clone().into(), value.clone()))
+        .chain(response.raw_headers().iter().cloned());
+
+    return json!({
+        "startedDateTime": started,
+        "time": time,
+        "request": request_json,
+        "response": {
+            "status": response.status_code(),
+            "statusText": "",
+            "httpVersion": "HTTP/1.1",
+            "headers": har_headers(response_headers),
+            "cookies": [],
+            "content": har_content(response.body(), &response_content_type),
+            "redirectURL": "",
+            "headersSize": -1,
+            "bodySize": response.body().len()
+        },
+        "cache": {},
+        "timings": {
+            "send": 0,
+            "wait": time,
+            "receive": 0
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::{method::Method, status::Status, version::Version};
+    use super::*;
+
+    fn recorder(bodies: Option<PathBuf>) -> Recorder {
+        return Recorder::new(RecordOptions {
+            upstream: "http://127.0.0.1:9".to_string(),
+            port: 8080,
+            output: std::env::temp_dir().join("quickserving-record-unused.json"),
+            bodies,
+            har: None
+        });
+    }
+
+    fn request() -> Request {
+        return Request::new(
+            Method::GET,
+            "/logo.png".to_string(),
+            None,
+            Version::new("HTTP".to_string(), "1.1".to_string()),
+            Headers::new(),
+            Vec::new()
+        );
+    }
+
+    fn response(body: Vec<u8>) -> Response {
+        return Response::new(
+            Status { status_code: 200 },
+            Version::new("HTTP".to_string(), "1.1".to_string()),
+            Headers::new(),
+            body
+        ).with_headers(vec![
+            ("Content-Type".to_string(), "image/png".to_string()),
+            ("Set-Cookie".to_string(), "a=1".to_string()),
+            ("Set-Cookie".to_string(), "b=2".to_string())
+        ]);
+    }
+
+    fn route_config(recorder: &Recorder, response: &Response) -> RouteConfig {
+        let mut recording = recorder.recording.lock().unwrap();
+
+        return recorder.route_config(&request(), response, &mut recording);
+    }
+
+    #[test]
+    fn records_headers_outside_the_known_set() {
+        let route_config = route_config(&recorder(None), &response(b"png".to_vec()));
+        let http = match route_config.response() {
+            ResponseConfig::Text { http, .. } => http,
+            _ => panic!("expected a text response")
+        };
+
+        assert_eq!(http.headers.get(&HeaderName::ContentType), Some(&"image/png".to_string()));
+        assert_eq!(http.raw_header_pairs(), vec![
+            ("Set-Cookie".to_string(), "a=1".to_string()),
+            ("Set-Cookie".to_string(), "b=2".to_string())
+        ]);
+    }
+
+    #[test]
+    fn saves_bodies_byte_for_byte() {
+        let dir = std::env::temp_dir().join(format!("quickserving-record-{}", std::process::id()));
+        let body = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0xff, 0x00];
+        let route_config = route_config(&recorder(Some(dir.clone())), &response(body.clone()));
+        let source = match route_config.response() {
+            ResponseConfig::File { source, .. } => source.clone(),
+            _ => panic!("expected a file response")
+        };
+
+        assert_eq!(fs::read(&source).unwrap(), body);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn encodes_binary_har_content() {
+        let binary = har_entry("http://127.0.0.1:9", &request(), &response(vec![0xff, 0xfe, 0x00]), "", 0.0);
+        let text = har_entry("http://127.0.0.1:9", &request(), &response(b"hello".to_vec()), "", 0.0);
+
+        assert_eq!(binary["response"]["content"], json!({ "size": 3, "mimeType": "image/png", "text": "//4A", "encoding": "base64" }));
+        assert_eq!(text["response"]["content"], json!({ "size": 5, "mimeType": "image/png", "text": "hello" }));
+        assert_eq!(binary["response"]["headers"].as_array().unwrap().len(), 3);
+    }
+}
@@ -1,74 +1,215 @@
-use std::{error::Error, io::{Read, Write}, net::TcpStream};
-use crate::http::{response::Response, server::Server, method::Method, headers::{Headers,HeaderName}, version::Version};
+use std::{borrow::Cow, io::{self, Read}, net::TcpStream, time::Duration};
+use serde_json::{Map, Value, json};
+use crate::{error::{Error, ParseError}, http::{request_id, method::Method, headers::{Headers,HeaderName}, version::Version}};
+
+// the maximum size of request line and headers we accept, larger requests are answered with 431
+static MAX_HEAD_SIZE: usize = 8192;
+// the maximum size of request bodies we accept, larger requests are answered with 413
+static MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+// how long a client may stall while sending its request or reading our response, so that
+// a client that stops halfway doesn't keep the connection's thread forever
+static TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Debug)]
 pub struct Request {
+    // the id of the request, taken from its X-Request-Id or traceparent header, or generated
+    pub id: String,
     pub method: Method,
     pub path: String,
+    pub query: Option<String>,
     pub version: Version,
     pub headers: Headers,
-    pub body: String,
+    // every header as it was sent, including the ones we don't know
+    pub raw_headers: Vec<(String, String)>,
+    // the body as it was sent, which is not necessarily text
+    pub body: Vec<u8>,
 }
 
 impl Request {
     pub fn new(
         method: Method,
         path: String,
+        query: Option<String>,
         version: Version,
         headers: Headers,
-        body: String,
+        body: Vec<u8>,
     ) -> Self {
+        let id = request_id::from_headers(&headers).unwrap_or_else(request_id::generate);
+
         return Self {
+            id,
             method,
             path,
+            query,
             version,
+            raw_headers: Vec::new(),
             headers,
             body,
         };
     }
 
-    pub fn read_from_stream(mut stream: &TcpStream) -> Result<Self, Box<dyn Error>> {
+    pub fn with_raw_headers(self: Self, raw_headers: Vec<(String, String)>) -> Self {
+        return Self {
+            raw_headers,
+            ..self
+        };
+    }
+
+    // finds the value of any header sent with the request, ignoring the case of its name
+    pub fn header(self: &Self, name: &str) -> Option<&str> {
+        return self.raw_headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str());
+    }
+
+    // the body as text, with anything that is not UTF-8 replaced
+    pub fn body_text(self: &Self) -> Cow<'_, str> {
+        return String::from_utf8_lossy(&self.body);
+    }
+
+    // the decoded parameters of the query string, in the order they were sent
+    pub fn query_params(self: &Self) -> Vec<(String, String)> {
+        let query = match &self.query {
+            Some(query) => query,
+            None => return vec![]
+        };
+
+        return query
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .map(|param| match param.split_once('=') {
+                Some((key, value)) => (percent_decode(key), percent_decode(value)),
+                None => (percent_decode(param), String::new())
+            })
+            .collect();
+    }
+
+    // the request as a JSON object, with the query parameters and the body parsed as JSON next to their raw forms
+    pub fn to_json(self: &Self) -> Value {
+        let mut args = Map::new();
+
+        // a parameter sent more than once is listed with all its values
+        for (key, value) in self.query_params() {
+            match args.get_mut(&key) {
+                Some(Value::Array(values)) => values.push(Value::String(value)),
+                Some(first) => *first = json!([first.clone(), value]),
+                None => { args.insert(key, Value::String(value)); }
+            };
+        }
+
+        let mut headers = Map::new();
+
+        for (name, value) in &self.raw_headers {
+            match headers.get_mut(name) {
+                Some(Value::String(values)) => *values = format!("{}, {}", values, value),
+                _ => { headers.insert(name.clone(), Value::String(value.clone())); }
+            };
+        }
+
+        let method: String = self.method.clone().into();
+
+        return json!({
+            "id": self.id,
+            "method": method,
+            "path": self.path,
+            "query": self.query,
+            "args": args,
+            "version": self.version.to_string(),
+            "headers": headers,
+            "body": self.body_text(),
+            "json": serde_json::from_slice::<Value>(&self.body).ok()
+        });
+    }
+
+    // the request as HTTP text, with the headers as they were sent
+    pub fn to_raw(self: &Self) -> String {
+        let method: String = self.method.clone().into();
+        let target = match &self.query {
+            Some(query) => format!("{}?{}", self.path, query),
+            None => self.path.clone()
+        };
+        let headers = self.raw_headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect::<String>();
+
+        return format!("{} {} {}\r\n{}\r\n{}", method, target, self.version.to_string(), headers, self.body_text());
+    }
+
+    pub fn read_from_stream(mut stream: &TcpStream) -> Result<Self, Error> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
         // we initialize out request buffer that we will be reading request's data into
-        let mut request_buf = [0u8; 8092];
-        // this will represent all out decoded data of request
-        let mut request = String::new();
-
-        match stream.read(&mut request_buf) {
-            Ok(n) => {
-                let request_chunk = String::from_utf8_lossy(&request_buf[0..n]).to_string();
-                // we decode the request chunk we read from network i/o
-                // and append it to out request string
-                request.push_str(request_chunk.as_str());
-            },
-            _ => ()
+        let mut request_buf = [0u8; 8192];
+        // this will represent all the raw data of request we have read so far
+        let mut request = Vec::<u8>::new();
+
+        // we read until the end of headers, refusing the requests that have too large headers
+        let head_end = loop {
+            if let Some(idx) = find_head_end(&request) {
+                break idx;
+            }
+
+            if request.len() > MAX_HEAD_SIZE {
+                return Err(ParseError::HeadersTooLarge.into());
+            }
+
+            let n = stream.read(&mut request_buf)?;
+
+            if n == 0 && request.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Connection closed before sending a request.").into());
+            }
+
+            if n == 0 {
+                return Err(ParseError::Malformed("Request ended before the end of headers.".to_string()).into());
+            }
+
+            request.extend_from_slice(&request_buf[0..n]);
         };
 
+        if head_end > MAX_HEAD_SIZE {
+            return Err(ParseError::HeadersTooLarge.into());
+        }
+
+        let head = String::from_utf8_lossy(&request[0..head_end]).to_string();
+        let mut body = request[head_end..].to_vec();
         // we parse our request
-        let request = Self::try_from(request);
+        let mut request = Self::try_from(head)?;
+
+        // then read the rest of the body, if the client told us its length
+        let content_length = match request.headers.get(&HeaderName::ContentLength) {
+            Some(value) => match value.trim().parse::<usize>() {
+                Ok(length) => length,
+                Err(_) => return Err(ParseError::Malformed("Invalid Content-Length header.".to_string()).into())
+            },
+            None => body.len()
+        };
 
-        if request.is_err() {
-            return Err("Error while parsing request. Invalid request.".into());
+        if content_length > MAX_BODY_SIZE {
+            return Err(ParseError::BodyTooLarge.into());
         }
 
-        let request = request.unwrap();
+        while body.len() < content_length {
+            let n = stream.read(&mut request_buf)?;
 
-        return Ok(request);
-    }
-    
-    pub fn respond(self: &Self, create_response: fn(&Server, &Self) -> Response, server: &Server, mut stream: &TcpStream) -> Result<(), Box<dyn Error>> {
-        let response = create_response(server, self);
-        let response_string: String = response.into();
+            if n == 0 {
+                return Err(ParseError::Malformed("Request body is shorter than its Content-Length.".to_string()).into());
+            }
 
-        stream.write_all(response_string.as_bytes()).unwrap();
-        stream.flush().unwrap();
+            body.extend_from_slice(&request_buf[0..n]);
+        }
 
-        return Ok(());
+        body.truncate(content_length);
+        request.body = body;
 
+        return Ok(request);
     }
 }
 
 impl TryFrom<String> for Request {
-    type Error = String;
+    type Error = ParseError;
 
     fn try_from(string: String) -> Result<Self, Self::Error> {
         let lines = string
@@ -76,6 +217,7 @@ impl TryFrom<String> for Request {
             .enumerate();
 
         let mut headers = Headers::new();
+        let mut raw_headers = Vec::new();
         let mut body = "".to_string();
 
         let mut first_line: Option<(Method, String, Version)> = None;
@@ -84,12 +226,7 @@ impl TryFrom<String> for Request {
         for (idx, line) in lines {
             match (idx, line) {
                 (0, _) => {
-                    let p = match process_first_line(line.to_string()) {
-                        Ok(p) => p,
-                        Err(_) => return Err("Invalid request.".to_string())
-                    };
-
-                    first_line = Some(p);
+                    first_line = Some(process_first_line(line.to_string())?);
                 },
                 (i, "") if i > 0 => { 
                     is_body = true;
@@ -100,88 +237,274 @@ impl TryFrom<String> for Request {
                     body += "\n";
                 },
                 (_, line) => {
-                    let (name, value) = match process_header_line(line.to_string()) {
-                        Ok(v) => v,
-                        Err(_) => return Err("Invalid request".into())
-                    };
+                    let (key, value) = process_header_line(line.to_string())?;
+
+                    // headers we don't know are not an error, they are only kept as raw headers
+                    if let Some(name) = HeaderName::find(&key) {
+                        let _ = headers.insert(name, value.clone());
+                    }
 
-                    let _ = headers.insert(name, value);
+                    raw_headers.push((key, value));
                 }
             }
         }
 
         let first_line = match first_line {
             Some(first_line) => first_line,
-            None => return Err("Invalid request.".to_string())
+            None => return Err(ParseError::Malformed("Empty request.".to_string()))
         };
 
-        let method = match Method::try_from(first_line.0) {
-            Ok(method) => method,
-            Err(err) => return Err(err.to_string())
-        };
-        let path = first_line.1;
-        let version = match Version::try_from(first_line.2) {
-            Ok(version) => version,
-            Err(err) => return Err(err.to_string())
+        let method = first_line.0;
+        // the query is not a part of the path the routes are matched against
+        let (path, query) = match first_line.1.split_once('?') {
+            Some((path, query)) => (path.to_string(), Some(query.to_string())),
+            None => (first_line.1, None)
         };
+        let version = first_line.2;
 
         return Ok(Request::new(
             method,
             path,
+            query,
             version,
             headers,
-            body
-        ));
+            body.into_bytes()
+        ).with_raw_headers(raw_headers));
     }
 }
 
-fn process_first_line(line: String) -> Result<(Method, String, Version), String> {
-    let mut method = "".to_string();
-    let mut path = "".to_string();
-    let mut version = "".to_string();
+fn process_first_line(line: String) -> Result<(Method, String, Version), ParseError> {
+    let parts = line
+        .split(' ')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>();
 
-    let mut i = 0;
+    if parts.len() != 3 {
+        return Err(ParseError::Malformed("Invalid request line.".to_string()));
+    }
 
-    for c in line.chars() {
-        if c == ' ' {
-            i += 1;
-            continue;
-        }
-        
-        match i {
-            0 => { method.push(c) },
-            1 => { path.push(c) },
-            2 => { version.push(c) },
-            _ => ()
-        };
+    let (method, path, version) = (parts[0], parts[1], parts[2]);
+
+    if !method.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(ParseError::Malformed("Invalid request method.".to_string()));
     }
 
-    let method = Method::try_from(method)?;
-    let version = Version::try_from(version)?;
+    let method = match Method::try_from(method.to_string()) {
+        Ok(method) => method,
+        Err(_) => return Err(ParseError::UnsupportedMethod(method.to_string()))
+    };
 
-    return Ok((method, path, version));
+    if !path.starts_with('/') && path != "*" {
+        return Err(ParseError::Malformed("Invalid request path.".to_string()));
+    }
+
+    if !version.starts_with("HTTP/") {
+        return Err(ParseError::Malformed("Invalid HTTP version.".to_string()));
+    }
+
+    let version = match Version::try_from(version.to_string()) {
+        Ok(version) => version,
+        Err(err) => return Err(ParseError::Malformed(err))
+    };
+
+    return Ok((method, path.to_string(), version));
+}
+
+fn process_header_line(line: String) -> Result<(String, String), ParseError> {
+    let (key, value) = match line.split_once(':') {
+        Some(pair) => pair,
+        None => return Err(ParseError::Malformed(format!("Invalid header line \"{}\".", line)))
+    };
+
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return Err(ParseError::Malformed(format!("Invalid header name \"{}\".", key)));
+    }
+
+    return Ok((key.to_string(), value.trim().to_string()));
 }
 
-fn process_header_line(line: String) -> Result<(HeaderName, String), String> {
-    let mut key = "".to_string();
-    let mut value = "".to_string();
+// decodes %XX escapes and pluses of a query string part, invalid escapes are kept as they are
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
 
-    let mut i = 0;
+    while idx < bytes.len() {
+        let escaped = match bytes[idx] {
+            b'%' => s
+                .get(idx + 1..idx + 3)
+                .filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None
+        };
 
-    for c in line.chars() {
-        match (i, c) {
-            (0, ' ') => {
-                i += 1; 
-                continue;
+        match (escaped, bytes[idx]) {
+            (Some(byte), _) => {
+                decoded.push(byte);
+                idx += 3;
             },
-            (0, ':') => { continue; },
-            (0, _) => { key.push(c); },
-            (1, _) => { value.push(c); },
-            _ => ()
-        }
+            (None, b'+') => {
+                decoded.push(b' ');
+                idx += 1;
+            },
+            (None, byte) => {
+                decoded.push(byte);
+                idx += 1;
+            }
+        };
+    }
+
+    return String::from_utf8_lossy(&decoded).to_string();
+}
+
+// finds where the headers end, accepting bare newlines as well as CRLF
+fn find_head_end(buf: &[u8]) -> Option<usize> {
+    let crlf = buf
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|idx| idx + 4);
+    let lf = buf
+        .windows(2)
+        .position(|window| window == b"\n\n")
+        .map(|idx| idx + 2);
+
+    return match (crlf, lf) {
+        (Some(crlf), Some(lf)) => Some(crlf.min(lf)),
+        (crlf, lf) => crlf.or(lf)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, net::TcpListener, thread};
+    use super::*;
+
+    fn parse(head: &str) -> Result<Request, ParseError> {
+        return Request::try_from(head.to_string());
     }
-    
-    let name = HeaderName::try_from(key)?;
 
-    return Ok((name, value));
+    // sends the bytes from a client socket and reads the request on the server side
+    fn read(bytes: Vec<u8>) -> Result<Request, Error> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            let _ = stream.write_all(&bytes);
+            let _ = stream.shutdown(std::net::Shutdown::Write);
+            // the stream is kept open until the server has read it
+            let _ = stream.read(&mut [0u8; 1]);
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let request = Request::read_from_stream(&stream);
+
+        drop(stream);
+        client.join().unwrap();
+
+        return request;
+    }
+
+    #[test]
+    fn parses_the_request_line() {
+        let request = parse("POST /users/42?tab=orders&page=2 HTTP/1.1\r\n\r\n").unwrap();
+
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.path, "/users/42");
+        assert_eq!(request.query.as_deref(), Some("tab=orders&page=2"));
+        assert_eq!(request.version.to_string(), "HTTP/1.1");
+    }
+
+    #[test]
+    fn rejects_malformed_requests() {
+        assert!(matches!(parse(""), Err(ParseError::Malformed(_))));
+        assert!(matches!(parse("GET /\r\n\r\n"), Err(ParseError::Malformed(_))));
+        assert!(matches!(parse("GET users HTTP/1.1\r\n\r\n"), Err(ParseError::Malformed(_))));
+        assert!(matches!(parse("GET / FTP/1.1\r\n\r\n"), Err(ParseError::Malformed(_))));
+        assert!(matches!(parse("GET / HTTP/1.1\r\nno colon\r\n\r\n"), Err(ParseError::Malformed(_))));
+        assert!(matches!(parse("GET / HTTP/1.1\r\nBad Name: 1\r\n\r\n"), Err(ParseError::Malformed(_))));
+        assert!(matches!(parse("BREW / HTTP/1.1\r\n\r\n"), Err(ParseError::UnsupportedMethod(method)) if method == "BREW"));
+    }
+
+    #[test]
+    fn finds_headers_ignoring_the_case() {
+        let request = parse("GET / HTTP/1.1\r\nContent-Type: text/plain\r\nX-Custom:  yes \r\nx-custom: again\r\n\r\n").unwrap();
+
+        assert_eq!(request.headers.get(&HeaderName::ContentType), Some(&"text/plain".to_string()));
+        assert_eq!(request.header("X-CUSTOM"), Some("yes"));
+        assert_eq!(request.header("X-Missing"), None);
+        assert_eq!(request.raw_headers.len(), 3);
+    }
+
+    #[test]
+    fn decodes_query_params() {
+        let request = parse("GET /?name=Ada+Lovelace&tag=a%2Fb&tag=c&flag&bad=%zz HTTP/1.1\r\n\r\n").unwrap();
+
+        assert_eq!(request.path, "/");
+        assert_eq!(request.query_params(), vec![
+            ("name".to_string(), "Ada Lovelace".to_string()),
+            ("tag".to_string(), "a/b".to_string()),
+            ("tag".to_string(), "c".to_string()),
+            ("flag".to_string(), "".to_string()),
+            ("bad".to_string(), "%zz".to_string())
+        ]);
+    }
+
+    #[test]
+    fn finds_the_end_of_the_head() {
+        assert_eq!(find_head_end(b"GET / HTTP/1.1\r\n\r\nbody"), Some(18));
+        assert_eq!(find_head_end(b"GET / HTTP/1.1\n\nbody"), Some(16));
+        assert_eq!(find_head_end(b"GET / HTTP/1.1\r\n"), None);
+    }
+
+    #[test]
+    fn reads_the_body_from_the_stream() {
+        let mut bytes = b"POST /upload HTTP/1.1\r\nContent-Length: 4\r\n\r\n".to_vec();
+        bytes.extend_from_slice(&[0, 159, 146, 150]);
+        bytes.extend_from_slice(b"ignored");
+
+        let request = read(bytes).unwrap();
+
+        assert_eq!(request.path, "/upload");
+        assert_eq!(request.body, vec![0, 159, 146, 150]);
+    }
+
+    #[test]
+    fn refuses_requests_that_are_too_large() {
+        let large_head = format!("GET / HTTP/1.1\r\nX-Large: {}\r\n\r\n", "a".repeat(MAX_HEAD_SIZE));
+        let large_body = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY_SIZE + 1);
+
+        assert!(matches!(read(large_head.into_bytes()), Err(Error::Parse(ParseError::HeadersTooLarge))));
+        assert!(matches!(read(large_body.into_bytes()), Err(Error::Parse(ParseError::BodyTooLarge))));
+    }
+
+    #[test]
+    fn refuses_short_and_invalid_bodies() {
+        let short = b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc".to_vec();
+        let invalid = b"POST / HTTP/1.1\r\nContent-Length: ten\r\n\r\n".to_vec();
+
+        assert!(matches!(read(short), Err(Error::Parse(ParseError::Malformed(_)))));
+        assert!(matches!(read(invalid), Err(Error::Parse(ParseError::Malformed(_)))));
+    }
+
+    #[test]
+    fn echoes_the_request_as_json() {
+        let request = read(b"POST /anything?tag=a&tag=b&page=2 HTTP/1.1\r\nAccept: text/html\r\nAccept: application/json\r\nContent-Length: 12\r\n\r\n{\"id\": \"42\"}".to_vec()).unwrap();
+        let echo = request.to_json();
+
+        assert_eq!(echo["method"], "POST");
+        assert_eq!(echo["path"], "/anything");
+        assert_eq!(echo["query"], "tag=a&tag=b&page=2");
+        assert_eq!(echo["args"], json!({ "tag": ["a", "b"], "page": "2" }));
+        assert_eq!(echo["version"], "HTTP/1.1");
+        assert_eq!(echo["headers"]["Accept"], "text/html, application/json");
+        assert_eq!(echo["body"], "{\"id\": \"42\"}");
+        assert_eq!(echo["json"], json!({ "id": "42" }));
+        assert_eq!(parse("GET / HTTP/1.1\r\n\r\n").unwrap().to_json()["json"], Value::Null);
+    }
+
+    #[test]
+    fn echoes_the_request_as_raw_text() {
+        let bytes = b"PUT /items/1?force=true HTTP/1.1\r\nX-Custom: yes\r\nContent-Length: 5\r\n\r\nhello".to_vec();
+
+        assert_eq!(read(bytes.clone()).unwrap().to_raw().into_bytes(), bytes);
+    }
 }
@@ -0,0 +1,109 @@
+use crate::http::{random, headers::{Headers, HeaderName}};
+
+// incoming ids longer than this are ignored, so that clients can't flood the logs
+static MAX_ID_LENGTH: usize = 128;
+
+// takes the request id from the X-Request-Id header, or the trace id from the W3C traceparent header
+pub fn from_headers(headers: &Headers) -> Option<String> {
+    if let Some(id) = headers.get(&HeaderName::XRequestId) {
+        let id = id.trim();
+        let is_valid = !id.is_empty()
+            && id.len() <= MAX_ID_LENGTH
+            && id.chars().all(|c| c.is_ascii_graphic());
+
+        if is_valid {
+            return Some(id.to_string());
+        }
+    }
+
+    // traceparent is "version-traceid-parentid-flags", the trace id being 32 hex digits
+    let traceparent = headers.get(&HeaderName::Traceparent)?;
+    let trace_id = traceparent.trim().split('-').nth(1)?;
+    let is_valid = trace_id.len() == 32
+        && trace_id.chars().all(|c| c.is_ascii_hexdigit())
+        && trace_id.chars().any(|c| c != '0');
+
+    if !is_valid {
+        return None;
+    }
+
+    return Some(trace_id.to_lowercase());
+}
+
+// generates a random 128 bit id as 32 hex digits, so it can be used as a trace id as well
+pub fn generate() -> String {
+    return format!("{:016x}{:016x}", random::next_u64(), random::next_u64());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(values: Vec<(HeaderName, &str)>) -> Headers {
+        let mut headers = Headers::new();
+
+        for (name, value) in values {
+            let _ = headers.insert(name, value.to_string());
+        }
+
+        return headers;
+    }
+
+    fn traceparent(value: &str) -> Option<String> {
+        return from_headers(&headers(vec![(HeaderName::Traceparent, value)]));
+    }
+
+    #[test]
+    fn takes_the_request_id() {
+        assert_eq!(from_headers(&headers(vec![(HeaderName::XRequestId, " abc-123 ")])), Some("abc-123".to_string()));
+        assert_eq!(from_headers(&headers(Vec::new())), None);
+    }
+
+    #[test]
+    fn takes_the_trace_id_of_a_valid_traceparent() {
+        assert_eq!(
+            traceparent("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01"),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736".to_string())
+        );
+    }
+
+    #[test]
+    fn ignores_malformed_traceparents() {
+        assert_eq!(traceparent("00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01"), None);
+        assert_eq!(traceparent("00-4bf92f3577b34da6a3ce929d0e0e47360-00f067aa0ba902b7-01"), None);
+        assert_eq!(traceparent("00-4bf92f3577b34da6a3ce929d0e0e473g-00f067aa0ba902b7-01"), None);
+        assert_eq!(traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01"), None);
+        assert_eq!(traceparent("4bf92f3577b34da6a3ce929d0e0e4736"), None);
+        assert_eq!(traceparent(""), None);
+    }
+
+    #[test]
+    fn ignores_invalid_request_ids() {
+        let oversized = "a".repeat(MAX_ID_LENGTH + 1);
+        let longest = "a".repeat(MAX_ID_LENGTH);
+
+        assert_eq!(from_headers(&headers(vec![(HeaderName::XRequestId, &oversized)])), None);
+        assert_eq!(from_headers(&headers(vec![(HeaderName::XRequestId, &longest)])), Some(longest.clone()));
+        assert_eq!(from_headers(&headers(vec![(HeaderName::XRequestId, "has space")])), None);
+        assert_eq!(from_headers(&headers(vec![(HeaderName::XRequestId, "  ")])), None);
+    }
+
+    #[test]
+    fn falls_back_to_the_traceparent() {
+        let headers = headers(vec![
+            (HeaderName::XRequestId, &"a".repeat(MAX_ID_LENGTH + 1)),
+            (HeaderName::Traceparent, "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+        ]);
+
+        assert_eq!(from_headers(&headers), Some("4bf92f3577b34da6a3ce929d0e0e4736".to_string()));
+    }
+
+    #[test]
+    fn generates_trace_ids() {
+        let id = generate();
+
+        assert_eq!(id.len(), 32);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(id, generate());
+    }
+}
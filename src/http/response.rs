@@ -1,10 +1,26 @@
-use crate::http::{headers::Headers, version::Version, status::Status};
+use std::{borrow::Cow, io::Write, net::TcpStream};
+use crate::{error::Error, http::{faults, headers::{Headers, HeaderName}, version::Version, status::Status}, config::FaultsConfig};
+
+// what was sent to the client, kept after the response itself is sent
+#[derive(Debug, Clone)]
+pub struct ResponseSummary {
+    pub status_code: u16,
+    pub body_bytes: usize,
+    pub route: Option<String>
+}
 
 pub struct Response {
     status: Status,
     version: Version,
     headers: Headers,
-    body: String,
+    // the headers we don't know, like Set-Cookie, in the order they were added and sent after the known ones
+    raw_headers: Vec<(String, String)>,
+    // the body as it is sent, which is not necessarily text
+    body: Vec<u8>,
+    // the key of the route the response was created from, if any
+    route: Option<String>,
+    // the faults injected into the response when it's sent
+    faults: Option<FaultsConfig>
 }
 
 impl Response {
@@ -12,27 +28,118 @@ impl Response {
         status: Status,
         version: Version,
         headers: Headers,
-        body: String,
+        body: Vec<u8>,
     ) -> Self {
         return Self {
             status,
             version,
             headers,
+            raw_headers: Vec::new(),
             body,
+            route: None,
+            faults: None
+        };
+    }
+
+    pub fn with_header(self: Self, name: HeaderName, value: String) -> Self {
+        let mut headers = self.headers;
+        let _ = headers.insert(name, value);
+
+        return Self {
+            headers,
+            ..self
+        };
+    }
+
+    // adds headers by their name, the ones we know replace any earlier value
+    // while the others are kept as they are, so that they can be repeated
+    pub fn with_headers(self: Self, headers: Vec<(String, String)>) -> Self {
+        let mut response = self;
+
+        for (name, value) in headers {
+            match HeaderName::find(&name) {
+                Some(header) => { let _ = response.headers.insert(header, value); },
+                None => response.raw_headers.push((name, value))
+            };
+        }
+
+        return response;
+    }
+
+    pub fn with_route(self: Self, route: String) -> Self {
+        return Self {
+            route: Some(route),
+            ..self
+        };
+    }
+
+    pub fn with_faults(self: Self, faults: FaultsConfig) -> Self {
+        return Self {
+            faults: Some(faults).filter(|faults| !faults.is_empty()),
+            ..self
+        };
+    }
+
+    pub fn status_code(self: &Self) -> u16 {
+        return self.status.status_code;
+    }
+
+    pub fn headers(self: &Self) -> &Headers {
+        return &self.headers;
+    }
+
+    pub fn raw_headers(self: &Self) -> &Vec<(String, String)> {
+        return &self.raw_headers;
+    }
+
+    pub fn body(self: &Self) -> &Vec<u8> {
+        return &self.body;
+    }
+
+    // the body as text, with anything that is not UTF-8 replaced
+    pub fn body_text(self: &Self) -> Cow<'_, str> {
+        return String::from_utf8_lossy(&self.body);
+    }
+
+    pub fn summary(self: &Self) -> ResponseSummary {
+        return ResponseSummary {
+            status_code: self.status.status_code,
+            body_bytes: self.body.len(),
+            route: self.route.clone()
         };
     }
+
+    pub fn send(mut self: Self, mut stream: &TcpStream) -> Result<(), Error> {
+        if let Some(faults) = self.faults.take() {
+            return faults::send(self, &faults, stream);
+        }
+
+        let bytes: Vec<u8> = self.into();
+
+        stream.write_all(&bytes)?;
+        stream.flush()?;
+
+        return Ok(());
+    }
 }
 
-impl Into<String> for Response {
-    fn into(self) -> String {
-        let headers: String = self.headers.into();
+impl Into<Vec<u8>> for Response {
+    fn into(self) -> Vec<u8> {
+        let mut headers: String = self.headers.into();
+
+        for (name, value) in &self.raw_headers {
+            headers.push_str(&format!("{}: {}\n", name, value));
+        }
 
-        return format!(
-            "{} {}\n{}\r\n{}",
+        let mut bytes = format!(
+            "{} {}\n{}\r\n",
             self.version.to_string(),
             self.status.status_code,
-            headers,
-            self.body
-        );
+            headers
+        ).into_bytes();
+
+        bytes.extend_from_slice(&self.body);
+
+        return bytes;
     }
 }
@@ -0,0 +1,53 @@
+use std::{collections::HashMap, sync::{Mutex, MutexGuard}};
+
+// the current states of the scenarios, the ones that never moved are in their initial state,
+// which is kept in the config so that a reloaded config can change it
+pub struct Scenarios {
+    states: Mutex<HashMap<String, String>>
+}
+
+impl Scenarios {
+    pub fn new() -> Self {
+        return Self { states: Mutex::new(HashMap::new()) };
+    }
+
+    // the state the scenario moved to, None when it's still in its initial state
+    pub fn get(self: &Self, name: &str) -> Option<String> {
+        return self.lock().get(name);
+    }
+
+    pub fn set(self: &Self, name: &str, state: &str) {
+        self.lock().set(name, state);
+    }
+
+    // moves every scenario back to its initial state
+    pub fn reset(self: &Self) {
+        self.lock().states.clear();
+    }
+
+    // keeps the states from changing until the returned ones are dropped, so that a request can
+    // match a route in a state and move the scenario on without another request doing the same in between
+    pub fn lock(self: &Self) -> LockedScenarios<'_> {
+        let states = match self.states.lock() {
+            Ok(states) => states,
+            Err(poisoned) => poisoned.into_inner()
+        };
+
+        return LockedScenarios { states };
+    }
+}
+
+pub struct LockedScenarios<'a> {
+    states: MutexGuard<'a, HashMap<String, String>>
+}
+
+impl<'a> LockedScenarios<'a> {
+    // the state the scenario moved to, None when it's still in its initial state
+    pub fn get(self: &Self, name: &str) -> Option<String> {
+        return self.states.get(name).cloned();
+    }
+
+    pub fn set(self: &mut Self, name: &str, state: &str) {
+        self.states.insert(name.to_string(), state.to_string());
+    }
+}
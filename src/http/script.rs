@@ -0,0 +1,312 @@
+#[cfg(feature = "script")]
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::Instant};
+use std::time::Duration;
+#[cfg(feature = "script")]
+use serde_json::Value;
+#[cfg(feature = "script")]
+use crate::{logging::{LogLevel, log}, http::{template, headers::HeaderName, status::{Status, StatusCode}, version::Version}};
+use crate::{error::Error, config::ResponseHTTPConfig, http::{request::Request, response::Response}};
+
+// a script compiled when the config is loaded, it's empty until then and without the "script" feature
+#[derive(Clone, Default)]
+pub struct CompiledScript {
+    #[cfg(feature = "script")]
+    ast: Option<Arc<rhai::AST>>
+}
+
+impl CompiledScript {
+    #[cfg(feature = "script")]
+    pub fn compile(code: &str) -> Result<Self, String> {
+        let ast = rhai::Engine::new()
+            .compile(code)
+            .map_err(|err| err.to_string())?;
+
+        return Ok(Self { ast: Some(Arc::new(ast)) });
+    }
+
+    #[cfg(not(feature = "script"))]
+    pub fn compile(_: &str) -> Result<Self, String> {
+        return Err("script responses need quickserving built with the \"script\" feature".to_string());
+    }
+}
+
+// the state every script keeps between its runs, keyed by the route and the position of the response in it,
+// each behind a lock of its own, so that only the runs of the same script wait on each other
+pub struct Scripts {
+    #[cfg(feature = "script")]
+    states: Mutex<HashMap<String, Arc<Mutex<rhai::Dynamic>>>>
+}
+
+impl Scripts {
+    pub fn new() -> Self {
+        return Self {
+            #[cfg(feature = "script")]
+            states: Mutex::new(HashMap::new())
+        };
+    }
+
+    // forgets the state of every script
+    pub fn reset(self: &Self) {
+        #[cfg(feature = "script")]
+        lock(&self.states).clear();
+    }
+
+    // runs the script with the request, the runs of a script happen one at a time so that its state stays consistent
+    #[cfg(feature = "script")]
+    pub fn run(
+        self: &Self,
+        script: &CompiledScript,
+        key: &str,
+        request: &Request,
+        route: &str,
+        timeout: Duration,
+        response_config: &ResponseHTTPConfig
+    ) -> Result<Response, Error> {
+        let ast = match &script.ast {
+            Some(ast) => ast,
+            None => return Err(Error::Script("The script was not compiled.".to_string()))
+        };
+        let request = rhai::serde::to_dynamic(&template::context(request, route)["request"])
+            .map_err(|err| Error::Script(err.to_string()))?;
+        let engine = engine(timeout);
+        let state = lock(&self.states)
+            .entry(key.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(rhai::Map::new().into())))
+            .clone();
+        let mut state = lock(&state);
+        let mut scope = rhai::Scope::new();
+
+        scope.push_constant("request", request);
+        scope.push("state", std::mem::take(&mut *state));
+
+        let result = engine.eval_ast_with_scope::<rhai::Dynamic>(&mut scope, ast);
+
+        // the state is kept even when the script fails, with whatever it changed before
+        if let Some(changed) = scope.get_value::<rhai::Dynamic>("state") {
+            *state = changed;
+        }
+
+        drop(state);
+
+        let result = match result {
+            Ok(result) => result,
+            Err(err) => return Err(Error::Script(match *err {
+                rhai::EvalAltResult::ErrorTerminated(..) => format!("The script ran for more than {}ms.", timeout.as_millis()),
+                err => err.to_string()
+            }))
+        };
+        let result = rhai::serde::from_dynamic::<Value>(&result).map_err(|err| Error::Script(err.to_string()))?;
+
+        return into_response(result, response_config);
+    }
+
+    #[cfg(not(feature = "script"))]
+    pub fn run(
+        self: &Self,
+        _: &CompiledScript,
+        _: &str,
+        _: &Request,
+        _: &str,
+        _: Duration,
+        _: &ResponseHTTPConfig
+    ) -> Result<Response, Error> {
+        return Err(Error::Script("Script responses need quickserving built with the \"script\" feature.".to_string()));
+    }
+}
+
+#[cfg(feature = "script")]
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    return match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner()
+    };
+}
+
+// the engine of a single run, which is stopped once it runs for longer than the timeout
+#[cfg(feature = "script")]
+fn engine(timeout: Duration) -> rhai::Engine {
+    let mut engine = rhai::Engine::new();
+    let started = Instant::now();
+
+    engine
+        .on_progress(move |_| match started.elapsed() > timeout {
+            true => Some(rhai::Dynamic::UNIT),
+            false => None
+        })
+        .on_print(|text| log(LogLevel::INFO, format!("Script: {}", text)))
+        .on_debug(|text, _, position| log(LogLevel::DEBUG, format!("Script at {}: {}", position, text)))
+        .register_fn("to_json", |value: rhai::Dynamic| {
+            return rhai::serde::from_dynamic::<Value>(&value)
+                .map(|value| value.to_string())
+                .unwrap_or_default();
+        })
+        .register_fn("parse_json", |text: &str| {
+            return serde_json::from_str::<Value>(text)
+                .ok()
+                .and_then(|value| rhai::serde::to_dynamic(value).ok())
+                .unwrap_or(rhai::Dynamic::UNIT);
+        });
+
+    return engine;
+}
+
+// a map of only "status", "headers" and "body" is the response, anything else is the body,
+// written as JSON unless it's a string, a status or headers that cannot be sent are an upstream error
+// like they are for CGI scripts
+#[cfg(feature = "script")]
+fn into_response(result: Value, response_config: &ResponseHTTPConfig) -> Result<Response, Error> {
+    let is_response = result.as_object().is_some_and(|fields| {
+        !fields.is_empty() && fields.keys().all(|key| ["status", "headers", "body"].contains(&key.as_str()))
+    });
+    let (status, script_headers, body) = match result {
+        Value::Object(mut fields) if is_response => (fields.remove("status"), fields.remove("headers"), fields.remove("body")),
+        body => (None, None, Some(body))
+    };
+    let status_code = match status {
+        Some(status) => match status.as_u64().filter(|code| (100..=599).contains(code)) {
+            Some(code) => code as u16,
+            None => return Err(Error::Upstream(format!("The script's status {} is not between 100 and 599.", status)))
+        },
+        None => match response_config.status {
+            Some(status_code) => status_code,
+            None => StatusCode::OK.into()
+        }
+    };
+    let mut headers = response_config.headers.clone();
+    let body = match body {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(body)) => body,
+        Some(body) => {
+            if headers.get(&HeaderName::ContentType).is_none() {
+                let _ = headers.insert(HeaderName::ContentType, "application/json".to_string());
+            }
+
+            body.to_string()
+        }
+    };
+
+    let script_headers = match script_headers {
+        Some(Value::Object(script_headers)) => script_headers
+            .into_iter()
+            .map(|(name, value)| match value {
+                Value::String(value) => (name, value),
+                value => (name, value.to_string())
+            })
+            .collect(),
+        None | Some(Value::Null) => Vec::new(),
+        Some(_) => return Err(Error::Upstream("The script's headers are not a map.".to_string()))
+    };
+    let content_length = body.len().to_string();
+
+    return Ok(Response::new(
+        Status { status_code },
+        Version::new("HTTP".to_string(), "1.1".to_string()),
+        headers,
+        body.into_bytes()
+    ).with_headers(response_config.raw_header_pairs()).with_headers(script_headers).with_header(HeaderName::ContentLength, content_length));
+}
+
+
+#[cfg(all(test, feature = "script"))]
+mod tests {
+    use super::*;
+    use std::thread;
+    use crate::http::{method::Method, headers::Headers};
+
+    fn run(scripts: &Scripts, code: &str, key: &str) -> Result<Response, Error> {
+        let request = Request::new(
+            Method::GET,
+            "/count".to_string(),
+            None,
+            Version::new("HTTP".to_string(), "1.1".to_string()),
+            Headers::new(),
+            Vec::new()
+        );
+
+        return scripts.run(&CompiledScript::compile(code).unwrap(), key, &request, "/count", Duration::from_secs(5), &ResponseHTTPConfig::default());
+    }
+
+    static COUNTER: &str = "if state.count == () { state.count = 0; } state.count += 1; state.count.to_string()";
+
+    #[test]
+    fn keeps_the_state_of_every_script() {
+        let scripts = Scripts::new();
+
+        assert_eq!(run(&scripts, COUNTER, "/count 0").unwrap().body_text(), "1");
+        assert_eq!(run(&scripts, COUNTER, "/count 0").unwrap().body_text(), "2");
+        assert_eq!(run(&scripts, COUNTER, "/count 1").unwrap().body_text(), "1");
+
+        scripts.reset();
+
+        assert_eq!(run(&scripts, COUNTER, "/count 0").unwrap().body_text(), "1");
+    }
+
+    #[test]
+    fn keeps_the_state_of_concurrent_runs() {
+        let scripts = Arc::new(Scripts::new());
+        let runs = (0..8)
+            .map(|_| {
+                let scripts = scripts.clone();
+
+                thread::spawn(move || run(&scripts, COUNTER, "/count 0").unwrap().body_text().to_string())
+            })
+            .collect::<Vec<_>>();
+        let mut counts = runs
+            .into_iter()
+            .map(|run| run.join().unwrap().parse::<u32>().unwrap())
+            .collect::<Vec<u32>>();
+
+        counts.sort();
+
+        assert_eq!(counts, (1..=8).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn runs_other_scripts_while_one_is_running() {
+        let scripts = Arc::new(Scripts::new());
+        let slow = {
+            let scripts = scripts.clone();
+
+            thread::spawn(move || run(&scripts, "let started = timestamp(); while started.elapsed < 0.5 {} \"slow\"", "/slow 0"))
+        };
+
+        thread::sleep(Duration::from_millis(100));
+
+        let started = Instant::now();
+
+        assert_eq!(run(&scripts, COUNTER, "/count 0").unwrap().body_text(), "1");
+        assert!(started.elapsed() < Duration::from_millis(300), "waited {:?} on the other script", started.elapsed());
+        assert_eq!(slow.join().unwrap().unwrap().body_text(), "slow");
+    }
+
+    #[test]
+    fn keeps_the_state_when_the_script_fails() {
+        let scripts = Scripts::new();
+
+        assert!(run(&scripts, "state.count = 41; throw \"failed\"", "/count 0").is_err());
+        assert_eq!(run(&scripts, "state.count += 1; state.count.to_string()", "/count 0").unwrap().body_text(), "42");
+    }
+
+    #[test]
+    fn takes_the_status_and_headers_from_the_script() {
+        let response = run(&Scripts::new(), "#{ status: 201, headers: #{ \"Set-Cookie\": \"id=1\" }, body: #{ id: 1 } }", "/count 0").unwrap();
+
+        assert_eq!(response.status_code(), 201);
+        assert_eq!(response.raw_headers(), &vec![("Set-Cookie".to_string(), "id=1".to_string())]);
+        assert_eq!(response.headers().get(&HeaderName::ContentType), Some(&"application/json".to_string()));
+        assert_eq!(response.body_text(), "{\"id\":1}");
+    }
+
+    #[test]
+    fn refuses_statuses_outside_the_valid_range() {
+        for status in ["99", "600", "999", "\"ok\""] {
+            let result = run(&Scripts::new(), &format!("#{{ status: {} }}", status), "/count 0");
+
+            assert!(matches!(result, Err(Error::Upstream(message)) if message.contains("not between 100 and 599")), "{}", status);
+        }
+
+        assert_eq!(run(&Scripts::new(), "#{ status: 599 }", "/count 0").unwrap().status_code(), 599);
+        assert!(matches!(run(&Scripts::new(), "#{ headers: [1] }", "/count 0"), Err(Error::Upstream(_))));
+        assert!(matches!(run(&Scripts::new(), "throw \"failed\"", "/count 0"), Err(Error::Script(_))));
+    }
+}
@@ -0,0 +1,201 @@
+use std::{collections::HashMap, sync::Mutex};
+use crate::{http::random::{self, SeededRandom}, config::{ResponseConfig, SequenceEnd}};
+
+struct State {
+    // how many times every sequence has responded
+    counts: HashMap<String, usize>,
+    // the generators of seeded random responses, so that their picks continue where they left off
+    generators: HashMap<String, SeededRandom>
+}
+
+// what sequences and random responses respond with, they are keyed by their place in the config,
+// like "/orders 0/1" for the second response of the sequence that is the first response of /orders
+pub struct Sequences {
+    state: Mutex<State>
+}
+
+impl Sequences {
+    pub fn new() -> Self {
+        return Self {
+            state: Mutex::new(State {
+                counts: HashMap::new(),
+                generators: HashMap::new()
+            })
+        };
+    }
+
+    // picks the response to respond with, going through sequences and random responses until
+    // a response that is neither of them
+    pub fn pick<'a>(self: &Self, response_config: &'a ResponseConfig, key: &str) -> &'a ResponseConfig {
+        let idx = match response_config {
+            ResponseConfig::Sequence { responses, then } if !responses.is_empty() => {
+                let count = self.next_count(key);
+
+                match then {
+                    SequenceEnd::Last => count.min(responses.len() - 1),
+                    SequenceEnd::Cycle => count % responses.len()
+                }
+            },
+            ResponseConfig::Random { responses, seed } => {
+                // the validation makes sure that the weights don't overflow when added up
+                let total = responses.iter().map(|weighted| weighted.weight).sum::<u64>();
+
+                if total == 0 {
+                    return response_config;
+                }
+
+                let mut roll = match seed {
+                    Some(seed) => self.next_seeded(key, *seed),
+                    None => random::next_u64()
+                } % total;
+
+                let mut picked = 0;
+
+                // every response takes as many of the possible rolls as its weight
+                for (idx, weighted) in responses.iter().enumerate() {
+                    if roll < weighted.weight {
+                        picked = idx;
+                        break;
+                    }
+
+                    roll -= weighted.weight;
+                }
+
+                picked
+            },
+            _ => return response_config
+        };
+
+        return match response_config.nested().get(idx) {
+            Some(nested) => self.pick(nested, &format!("{}/{}", key, idx)),
+            None => response_config
+        };
+    }
+
+    // starts every sequence over and reseeds every random response
+    pub fn reset(self: &Self) {
+        let mut state = self.lock();
+
+        state.counts.clear();
+        state.generators.clear();
+    }
+
+    fn next_count(self: &Self, key: &str) -> usize {
+        let mut state = self.lock();
+        let count = state.counts.entry(key.to_string()).or_insert(0);
+
+        *count += 1;
+
+        return *count - 1;
+    }
+
+    fn next_seeded(self: &Self, key: &str, seed: u64) -> u64 {
+        return self.lock()
+            .generators
+            .entry(key.to_string())
+            .or_insert_with(|| SeededRandom::new(seed))
+            .next_u64();
+    }
+
+    fn lock(self: &Self) -> std::sync::MutexGuard<'_, State> {
+        return match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn sequence(then: &str) -> ResponseConfig {
+        return serde_json::from_value(json!({
+            "type": "sequence",
+            "then": then,
+            "responses": [
+                { "type": "text", "text": "first" },
+                { "type": "text", "text": "second" },
+                { "type": "text", "text": "third" }
+            ]
+        })).unwrap();
+    }
+
+    fn random(seed: u64) -> ResponseConfig {
+        return serde_json::from_value(json!({
+            "type": "random",
+            "seed": seed,
+            "responses": [
+                { "weight": 1, "response": { "type": "text", "text": "a" } },
+                { "weight": 2, "response": { "type": "text", "text": "b" } },
+                { "weight": 3, "response": { "type": "text", "text": "c" } }
+            ]
+        })).unwrap();
+    }
+
+    fn texts(sequences: &Sequences, response_config: &ResponseConfig, key: &str, picks: usize) -> Vec<String> {
+        return (0..picks)
+            .map(|_| match sequences.pick(response_config, key) {
+                ResponseConfig::Text { text, .. } => text.clone(),
+                _ => panic!("picked a response that is not text")
+            })
+            .collect();
+    }
+
+    #[test]
+    fn stops_at_the_last_response() {
+        let sequences = Sequences::new();
+
+        assert_eq!(texts(&sequences, &sequence("last"), "/ 0", 5), vec!["first", "second", "third", "third", "third"]);
+    }
+
+    #[test]
+    fn cycles_through_the_responses() {
+        let sequences = Sequences::new();
+
+        assert_eq!(texts(&sequences, &sequence("cycle"), "/ 0", 5), vec!["first", "second", "third", "first", "second"]);
+    }
+
+    #[test]
+    fn counts_every_sequence_on_its_own_and_starts_over_on_reset() {
+        let sequences = Sequences::new();
+        let sequence = sequence("last");
+
+        assert_eq!(texts(&sequences, &sequence, "/ 0", 2), vec!["first", "second"]);
+        assert_eq!(texts(&sequences, &sequence, "/ 1", 1), vec!["first"]);
+
+        sequences.reset();
+
+        assert_eq!(texts(&sequences, &sequence, "/ 0", 1), vec!["first"]);
+    }
+
+    #[test]
+    fn picks_the_same_responses_for_the_same_seed() {
+        let picks = texts(&Sequences::new(), &random(42), "/ 0", 20);
+
+        assert_eq!(texts(&Sequences::new(), &random(42), "/ 0", 20), picks);
+        assert_ne!(texts(&Sequences::new(), &random(7), "/ 0", 20), picks);
+
+        let sequences = Sequences::new();
+
+        assert_eq!(texts(&sequences, &random(42), "/ 0", 10), picks[..10]);
+
+        sequences.reset();
+
+        assert_eq!(texts(&sequences, &random(42), "/ 0", 20), picks);
+    }
+
+    #[test]
+    fn picks_only_responses_with_a_weight() {
+        let response_config = serde_json::from_value(json!({
+            "type": "random",
+            "responses": [
+                { "weight": 0, "response": { "type": "text", "text": "never" } },
+                { "weight": 1, "response": { "type": "text", "text": "always" } }
+            ]
+        })).unwrap();
+
+        assert!(texts(&Sequences::new(), &response_config, "/ 0", 50).iter().all(|text| text == "always"));
+    }
+}
@@ -1,120 +1,766 @@
 use std::{
-    error::Error,
     fs::File,
     io::Read,
-    net::TcpListener,
+    net::{IpAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
 };
 use chrono::Utc;
-use crate::{logging::{LogLevel, log}, http::{request::Request, response::Response, headers::{Headers,HeaderName}, version::Version, status::StatusCode}, config::{ServerConfig, ResponseConfig, ResponseHTTPConfig}};
+use crate::{error::Error, logging::{LogLevel, log, access::{AccessLogger, AccessLogEntry}, trace::RequestSpan}, http::{admin::Admin, cgi, fastcgi, health, proxy, template::{self, CompiledTemplate, Template}, record::Recorder, journal::{Journal, JournalEntry}, scenarios::Scenarios, script::{CompiledScript, Scripts}, sequences::Sequences, request::Request, request_id, metrics::Metrics, shutdown::Shutdown, method::Method, response::{Response, ResponseSummary}, headers::{Headers,HeaderName}, version::Version, status::{Status, StatusCode}}, config::{ServerConfig, ResponseConfig, ResponseHTTPConfig, EchoFormat, format::ConfigFormat, shared::SharedConfig, watcher::ConfigWatcher}};
 
 
 pub struct Server {
-    config: ServerConfig
+    config: SharedConfig,
+    config_file: Option<(PathBuf, ConfigFormat)>,
+    access_logger: AccessLogger,
+    metrics: Arc<Metrics>,
+    journal: Arc<Journal>,
+    scenarios: Arc<Scenarios>,
+    sequences: Arc<Sequences>,
+    scripts: Arc<Scripts>,
+    // forwards and records every request instead of serving the routes when set
+    recorder: Option<Recorder>,
+    shutdown: Shutdown
 }
 
 impl Server {
     pub fn new(config: ServerConfig) -> Self {
-        return Self { config };
+        let config = SharedConfig::new(config);
+        let metrics = Arc::new(Metrics::new());
+
+        return Self { 
+            config: config.clone(),
+            config_file: None,
+            access_logger: AccessLogger::new(),
+            metrics: metrics.clone(),
+            journal: Arc::new(Journal::new()),
+            scenarios: Arc::new(Scenarios::new()),
+            sequences: Arc::new(Sequences::new()),
+            scripts: Arc::new(Scripts::new()),
+            recorder: None,
+            shutdown: Shutdown::new(config, metrics)
+        };
+    }
+
+    // makes the server reload its config whenever the file at the path changes
+    pub fn watch_config_file(self: Self, path: PathBuf, format: ConfigFormat) -> Self {
+        return Self {
+            config_file: Some((path, format)),
+            ..self
+        };
+    }
+
+    // makes the server forward every request to an upstream and record the traffic as routes
+    pub fn record(self: Self, recorder: Recorder) -> Self {
+        return Self {
+            recorder: Some(recorder),
+            ..self
+        };
+    }
+
+    pub fn config(self: &Self) -> SharedConfig {
+        return self.config.clone();
+    }
+
+    pub fn metrics(self: &Self) -> Arc<Metrics> {
+        return self.metrics.clone();
+    }
+
+    pub fn journal(self: &Self) -> Arc<Journal> {
+        return self.journal.clone();
+    }
+
+    pub fn scenarios(self: &Self) -> Arc<Scenarios> {
+        return self.scenarios.clone();
     }
 
-    pub fn listen(self: Self) -> Result<(), Box<dyn Error>> {
+    pub fn sequences(self: &Self) -> Arc<Sequences> {
+        return self.sequences.clone();
+    }
+
+    pub fn scripts(self: &Self) -> Arc<Scripts> {
+        return self.scripts.clone();
+    }
+
+    pub fn shutdown(self: &Self) -> Shutdown {
+        return self.shutdown.clone();
+    }
+
+    pub fn listen(self: Self) -> Result<(), Error> {
+        let port = self.config.load().port;
         // we bind our listener to port from self
-        let listener = TcpListener::bind(format!("0.0.0.0:{}", self.config.port));
+        let listener = TcpListener::bind(format!("0.0.0.0:{}", port))?;
+        
+        if let Some((path, format)) = &self.config_file {
+            ConfigWatcher::new(path.clone(), *format, self.config.clone())
+                .with_metrics(self.metrics.clone())
+                .spawn();
+        }
+
+        if let Some(admin) = &self.config.load().admin {
+            let admin_listener = TcpListener::bind((admin.host.as_str(), admin.port))?;
 
-        if listener.is_err() {
-            return Err("this port is already in use.".into());
+            Admin::new(self.config.clone(), self.config_file.clone(), self.metrics.clone(), self.journal.clone(), self.scenarios.clone(), self.sequences.clone(), self.scripts.clone()).spawn(admin_listener);
+            log(LogLevel::INFO, format!("Serving the admin API on {}:{}.", admin.host, admin.port));
         }
 
-        let listener = listener.unwrap();
+        log(LogLevel::INFO, format!("Serving on port {}.", port));
         
-        log(LogLevel::INFO, format!("Serving on port {}.", self.config.port));
-        
-        loop {
-            // we read every request that the listener has recieved and try to handle it
-            let (stream, _) = listener.accept().unwrap();
-            
-            let request = Request::read_from_stream(&stream)
-                .unwrap();
-
-            let _ = request.respond(
-                create_response,
-                &self,
-                &stream
-            );
+        let server = Arc::new(self);
+
+        for stream in listener.incoming() {
+            // a failed connection must never take down the listener, so we just log it
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    log(LogLevel::WARN, format!("Cannot accept connection. {}", err));
+                    continue;
+                }
+            };
+            let server = server.clone();
+
+            // every connection is handled on its own thread, so a slow or broken client
+            // doesn't block the others
+            thread::spawn(move || handle_connection(&server, stream));
         }
+
+        return Ok(());
     }
 
+    fn record_journal(self: &Self, request: &Request, summary: &ResponseSummary) {
+        if let Some(journal) = &self.config.load().journal {
+            self.journal.record(JournalEntry::new(request, summary), journal.capacity);
+        }
+    }
+
+    fn log_access(self: &Self, entry: AccessLogEntry) {
+        if let Some(access_log) = &self.config.load().access_log {
+            self.access_logger.log(access_log, &entry);
+        }
+    }
+}
+
+// a single client connection and everything we know about it
+struct Connection {
+    stream: TcpStream,
+    client_ip: Option<IpAddr>,
+    started: Instant,
+    span: RequestSpan
+}
+
+fn handle_connection(server: &Server, stream: TcpStream) {
+    let client_ip = stream
+        .peer_addr()
+        .ok()
+        .map(|addr| addr.ip());
+    let connection = Connection {
+        stream,
+        client_ip,
+        started: Instant::now(),
+        span: RequestSpan::new(client_ip)
+    };
+
+    server.metrics.connection_opened();
+    connection.span.in_scope(|| serve_connection(server, &connection));
+    server.metrics.connection_closed();
+}
+
+fn serve_connection(server: &Server, connection: &Connection) {
+    let request = match Request::read_from_stream(&connection.stream) {
+        Ok(request) => request,
+        Err(Error::Parse(err)) => {
+            log(LogLevel::WARN, format!("Cannot parse request. {}", err));
+            
+            // we don't know the id the client sent, so we make one up for the logs and the response
+            let id = request_id::generate();
+            let response = create_error_response(err.status_code())
+                .with_header(HeaderName::XRequestId, id.clone());
+            let summary = response.summary();
+
+            if let Err(err) = response.send(&connection.stream) {
+                log(LogLevel::WARN, format!("Cannot send response. {}", err));
+            }
+
+            connection.span.record_id(&id);
+            connection.span.record_response(&summary, connection.started.elapsed());
+            server.metrics.record_parse_error(&err, &summary);
+            server.log_access(AccessLogEntry::new(&id, None, connection.client_ip, summary, connection.started.elapsed()));
+            
+            return;
+        },
+        Err(err) => {
+            log(LogLevel::WARN, format!("Cannot read request. {}", err));
+            return;
+        }
+    };
+
+    connection.span.record_request(&request);
+    log(LogLevel::DEBUG, format!("Received {} {} request.", Into::<String>::into(request.method.clone()), request.path));
+
+    let response = create_response(server, &request)
+        .with_header(HeaderName::XRequestId, request.id.clone());
+    let summary = response.summary();
+
+    // the request is in the journal before the client has the response, so that it can be verified right after,
+    // and even when the response cannot be sent
+    server.record_journal(&request, &summary);
+
+    match response.send(&connection.stream) {
+        Ok(()) => {
+            let method: String = request.method.clone().into();
+
+            connection.span.record_response(&summary, connection.started.elapsed());
+            server.metrics.record_request(&method, &summary, connection.started.elapsed());
+            server.log_access(AccessLogEntry::new(&request.id, Some(&request), connection.client_ip, summary, connection.started.elapsed()));
+        },
+        Err(err) => {
+            log(LogLevel::WARN, format!("Cannot send response. {}", err));
+        }
+    };
 }
 
 fn create_response(server: &Server, request: &Request) -> Response {
-    let response_config = match server.config.find_response_config(&request.path, &request.method) {
+    let config = server.config.load();
+
+    if let Some(metrics) = &config.metrics {
+        if request.path == metrics.path && request.method == Method::GET {
+            return create_metrics_response(server).with_route(metrics.path.clone());
+        }
+    }
+
+    if let Some(health) = &config.health {
+        if request.path == health.liveness_path && request.method == Method::GET {
+            return create_json_response(StatusCode::OK, health::liveness())
+                .with_route(health.liveness_path.clone());
+        }
+
+        if request.path == health.readiness_path && request.method == Method::GET {
+            let (ready, body) = health::readiness(&config, server.shutdown.is_draining());
+            let status_code = match ready {
+                true => StatusCode::OK,
+                false => StatusCode::ServiceUnavailable
+            };
+
+            return create_json_response(status_code, body)
+                .with_route(health.readiness_path.clone());
+        }
+    }
+
+    if let Some(recorder) = &server.recorder {
+        return match recorder.forward(request) {
+            Ok(response) => response,
+            Err(err) => {
+                log(LogLevel::WARN, format!("Cannot forward request to \"{}\". {}", recorder.upstream(), err));
+                create_error_response(StatusCode::BadGateway)
+            }
+        };
+    }
+
+    // the scenarios are locked from matching the route until moving them on, so that two requests
+    // can't both match the state that only one of them should move on from
+    let mut scenarios = server.scenarios.lock();
+    let (route, route_config) = match config.find_route(request, &scenarios) {
         Some(route_info) => route_info,
         None => return create_404_response()
     };
 
-    let response = match response_config {
-        ResponseConfig::Text { text, http } => create_text_response(
+    if let Some((scenario, state)) = route_config.transition() {
+        scenarios.set(scenario, state);
+        log(LogLevel::DEBUG, format!("Scenario \"{}\" moved to state \"{}\".", scenario, state));
+    }
+
+    drop(scenarios);
+
+    // templated responses are rendered with the request's data
+    let template_context = |templated: bool| match templated {
+        true => Some(template::context(request, &route)),
+        false => None
+    };
+    // sequences, random responses and scripts are keyed by the route and the position of the response in it
+    let position = config.routes[&route]
+        .iter()
+        .position(|other| std::ptr::eq(other, route_config))
+        .unwrap_or(0);
+    let key = format!("{} {}", route, position);
+    let response_config = server.sequences.pick(route_config.response(), &key);
+    let response = match response_config.clone() {
+        ResponseConfig::Text { text, templated, http, compiled } => create_text_response(
             &text,
-            &http
+            &compiled,
+            &http.inherit(&config.defaults.http),
+            template_context(templated).as_ref()
         ),
-        ResponseConfig::File { source, http } => create_file_response(
+        ResponseConfig::File { source, templated, http } => create_file_response(
             &source,
-            &http
-        )
+            &http.inherit(&config.defaults.http),
+            template_context(templated).as_ref()
+        ),
+        ResponseConfig::Json { body, pretty, templated, http, compiled } => create_json_body_response(
+            &body,
+            &compiled,
+            pretty,
+            &http.inherit(&config.defaults.http),
+            template_context(templated).as_ref()
+        ),
+        ResponseConfig::Proxy { upstream, http } => create_proxy_response(
+            request,
+            &upstream,
+            &http.inherit(&config.defaults.http)
+        ),
+        ResponseConfig::Cgi { command, args, working_dir, timeout_ms, env, inherit_env, http } => create_cgi_response(
+            request,
+            config.port,
+            &cgi::Script {
+                command: &command,
+                args: &args,
+                working_dir: working_dir.as_deref(),
+                timeout: Duration::from_millis(timeout_ms),
+                env: &env,
+                inherit_env
+            },
+            &http.inherit(&config.defaults.http)
+        ),
+        ResponseConfig::FastCgi { upstream, script_filename, timeout_ms, params, http } => create_fastcgi_response(
+            request,
+            config.port,
+            &fastcgi::Application {
+                upstream: &upstream,
+                script_filename: script_filename.as_deref(),
+                timeout: Duration::from_millis(timeout_ms),
+                params: &params
+            },
+            &http.inherit(&config.defaults.http)
+        ),
+        ResponseConfig::Script { timeout_ms, http, compiled, .. } => create_script_response(
+            server,
+            &compiled,
+            &key,
+            request,
+            &route,
+            Duration::from_millis(timeout_ms),
+            &http.inherit(&config.defaults.http)
+        ),
+        ResponseConfig::Echo { format, http } => create_echo_response(
+            request,
+            format,
+            &http.inherit(&config.defaults.http)
+        ),
+        // only empty ones are left unpicked, which the validation doesn't let through
+        ResponseConfig::Sequence { .. } | ResponseConfig::Random { .. } => create_error_response(StatusCode::InternalServerError)
     };
 
-    return response;
+    return response
+        .with_route(route)
+        .with_faults(route_config.faults().inherit(&config.defaults.faults));
 }
 
 fn create_404_response() -> Response { 
+    return create_error_response(StatusCode::NotFound);
+}
+
+fn create_error_response(status_code: StatusCode) -> Response {
+    let code: u16 = status_code.into();
+    let body = format!("<h1>{}</h1>", code);
     let mut headers = Headers::new();
     let _ = headers.insert(HeaderName::ContentType, "text/html".to_string());
     let _ = headers.insert(HeaderName::Host, "quickserving".to_string());
     let _ = headers.insert(HeaderName::Date, Utc::now().to_string());
-    let _ = headers.insert(HeaderName::ContentLength, 12.to_string());
+    let _ = headers.insert(HeaderName::ContentLength, body.len().to_string());
+
+    return Response::new(
+        Status { status_code: code },
+        Version::new("HTTP".to_string(), "1.1".to_string()),
+        headers,
+        body.into_bytes(),
+    );
+}
+
+fn create_metrics_response(server: &Server) -> Response {
+    let body = server.metrics.render();
+    let mut headers = Headers::new();
+    let _ = headers.insert(HeaderName::ContentType, "text/plain; version=0.0.4".to_string());
+    let _ = headers.insert(HeaderName::ContentLength, body.len().to_string());
+
+    return Response::new(
+        StatusCode::OK.into(),
+        Version::new("HTTP".to_string(), "1.1".to_string()),
+        headers,
+        body.into_bytes()
+    );
+}
+
+pub fn create_json_response(status_code: StatusCode, value: serde_json::Value) -> Response {
+    let body = value.to_string();
+    let mut headers = Headers::new();
+    let _ = headers.insert(HeaderName::ContentType, "application/json".to_string());
+    let _ = headers.insert(HeaderName::ContentLength, body.len().to_string());
 
     return Response::new(
-        StatusCode::NotFound.into(),
+        status_code.into(),
         Version::new("HTTP".to_string(), "1.1".to_string()),
         headers,
-        "<h1>404</h1>".to_string(),
+        body.into_bytes()
     );
 }
 
+fn create_proxy_response(
+    request: &Request,
+    upstream: &String,
+    response_config: &ResponseHTTPConfig
+) -> Response {
+    return match proxy::forward(request, upstream) {
+        Ok(response) => override_http(response, response_config),
+        Err(err) => {
+            log(LogLevel::WARN, format!("Cannot forward request to \"{}\". {}", upstream, err));
+            create_error_response(StatusCode::BadGateway)
+        }
+    };
+}
+
+fn create_cgi_response(
+    request: &Request,
+    port: u16,
+    script: &cgi::Script,
+    response_config: &ResponseHTTPConfig
+) -> Response {
+    return match cgi::run(request, port, script) {
+        Ok(response) => override_http(response, response_config),
+        Err(err) => {
+            log(LogLevel::WARN, format!("Cannot run CGI script \"{}\". {}", script.command, err));
+            create_upstream_error_response(&err)
+        }
+    };
+}
+
+fn create_fastcgi_response(
+    request: &Request,
+    port: u16,
+    application: &fastcgi::Application,
+    response_config: &ResponseHTTPConfig
+) -> Response {
+    return match fastcgi::forward(request, port, application) {
+        Ok(response) => override_http(response, response_config),
+        Err(err) => {
+            log(LogLevel::WARN, format!("Cannot forward request to FastCGI upstream \"{}\". {}", application.upstream, err));
+            create_upstream_error_response(&err)
+        }
+    };
+}
+
+// a timed out upstream is answered with 504, and any other failure with 502
+fn create_upstream_error_response(err: &Error) -> Response {
+    return create_error_response(match err {
+        Error::UpstreamTimeout(_) => StatusCode::GatewayTimeout,
+        _ => StatusCode::BadGateway
+    });
+}
+
+// the upstream's status is kept unless the config replaces it, and the config's headers are added to the upstream's ones
+fn override_http(response: Response, response_config: &ResponseHTTPConfig) -> Response {
+    let status = match response_config.status {
+        Some(status_code) => Status { status_code },
+        None => Status { status_code: response.status_code() }
+    };
+    let mut headers = response.headers().clone();
+
+    for (name, value) in response_config.headers.iter() {
+        let _ = headers.insert(name.clone(), value.clone());
+    }
+
+    return Response::new(
+        status,
+        Version::new("HTTP".to_string(), "1.1".to_string()),
+        headers,
+        response.body().clone()
+    ).with_headers(response.raw_headers().clone()).with_headers(response_config.raw_header_pairs());
+}
+
+fn create_script_response(
+    server: &Server,
+    script: &CompiledScript,
+    key: &str,
+    request: &Request,
+    route: &str,
+    timeout: Duration,
+    response_config: &ResponseHTTPConfig
+) -> Response {
+    return match server.scripts.run(script, key, request, route, timeout, response_config) {
+        Ok(response) => response,
+        Err(err) => {
+            log(LogLevel::WARN, format!("Cannot run script of route \"{}\". {}", route, err));
+
+            match err {
+                Error::Upstream(_) => create_upstream_error_response(&err),
+                _ => create_error_response(StatusCode::InternalServerError)
+            }
+        }
+    };
+}
+
+fn create_echo_response(
+    request: &Request,
+    format: EchoFormat,
+    response_config: &ResponseHTTPConfig
+) -> Response {
+    let (body, content_type) = match format {
+        EchoFormat::Json => (serde_json::to_string_pretty(&request.to_json()).unwrap_or_default(), "application/json"),
+        EchoFormat::Raw => (request.to_raw(), "text/plain")
+    };
+
+    return create_typed_response(body, content_type, response_config);
+}
+
+fn create_json_body_response(
+    body: &serde_json::Value,
+    compiled: &CompiledTemplate,
+    pretty: bool,
+    response_config: &ResponseHTTPConfig,
+    template_context: Option<&serde_json::Value>
+) -> Response {
+    let rendered;
+    let body = match template_context {
+        Some(context) => match compiled.render_json(context) {
+            Some(body) => {
+                rendered = body;
+                &rendered
+            },
+            None => return create_uncompiled_template_response()
+        },
+        None => body
+    };
+    let body = match pretty {
+        true => serde_json::to_string_pretty(body),
+        false => serde_json::to_string(body)
+    };
+
+    return create_typed_response(body.unwrap_or_default(), "application/json", response_config);
+}
+
+// a response with the given content type, unless the config's headers replace it
+fn create_typed_response(body: String, content_type: &str, response_config: &ResponseHTTPConfig) -> Response {
+    let mut headers = Headers::new();
+    let _ = headers.insert(HeaderName::ContentType, content_type.to_string());
+
+    for (name, value) in response_config.headers.iter() {
+        let _ = headers.insert(name.clone(), value.clone());
+    }
+
+    let _ = headers.insert(HeaderName::ContentLength, body.len().to_string());
+
+    return Response::new(
+        status_of(response_config),
+        Version::new("HTTP".to_string(), "1.1".to_string()),
+        headers,
+        body.into_bytes()
+    ).with_headers(response_config.raw_header_pairs());
+}
+
 fn create_text_response(
     text: &String, 
-    response_config: &ResponseHTTPConfig
+    compiled: &CompiledTemplate,
+    response_config: &ResponseHTTPConfig,
+    template_context: Option<&serde_json::Value>
 ) -> Response {
+    let text = match template_context {
+        Some(context) => match compiled.render(context) {
+            Some(text) => text,
+            None => return create_uncompiled_template_response()
+        },
+        None => text.to_string()
+    };
+
     return Response::new(
-        StatusCode::OK.into(),
+        status_of(response_config),
         Version::new("HTTP".to_string(), "1.1".to_string()),
         response_config.headers.clone(),
-        text.to_string()
-    );
+        text.into_bytes()
+    ).with_headers(response_config.raw_header_pairs());
 }
 
 fn create_file_response(
     path: &String, 
-    response_config: &ResponseHTTPConfig
+    response_config: &ResponseHTTPConfig,
+    template_context: Option<&serde_json::Value>
 ) -> Response {
     let resource = match File::open(path) {
         Ok(mut file) => {
-            let mut buffer = String::new();
-            let _ = file.read_to_string(&mut buffer);
+            let mut buffer = Vec::new();
+            let _ = file.read_to_end(&mut buffer);
         
             buffer
         },
         Err(_) => return create_404_response()
     };
+    // only templates are read as text, other files are sent as they are, which may not be text
+    let resource = match template_context {
+        Some(context) => match render_template(&String::from_utf8_lossy(&resource), context) {
+            Some(resource) => resource.into_bytes(),
+            None => return create_error_response(StatusCode::InternalServerError)
+        },
+        None => resource
+    };
 
     let mut headers = response_config.headers.clone();
     let _ = headers.remove(HeaderName::ContentLength);
     let _ = headers.insert(HeaderName::ContentLength, resource.len().to_string());
 
     return Response::new(
-        StatusCode::OK.into(),
+        status_of(response_config),
         Version::new("HTTP".to_string(), "1.1".to_string()),
         headers,
         resource,
-    );
+    ).with_headers(response_config.raw_header_pairs());
+}
+
+// the templates of configs are compiled when they are loaded, so this only happens to a config that skipped that
+fn create_uncompiled_template_response() -> Response {
+    log(LogLevel::ERROR, "Cannot render template, it was not compiled.".to_string());
+
+    return create_error_response(StatusCode::InternalServerError);
+}
+
+// text and json templates are compiled when the config is loaded, but files can change after that,
+// so their templates are parsed when they are read and a broken one is logged and answered with 500
+fn render_template(source: &str, context: &serde_json::Value) -> Option<String> {
+    return match Template::parse(source) {
+        Ok(template) => Some(template.render(context)),
+        Err(err) => {
+            log(LogLevel::WARN, format!("Cannot render template. {}", err));
+            None
+        }
+    };
+}
+
+// the status from the config, 200 when it doesn't set one
+fn status_of(response_config: &ResponseHTTPConfig) -> Status {
+    return match response_config.status {
+        Some(status_code) => Status { status_code },
+        None => StatusCode::OK.into()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_a_scenario_on_only_once_for_concurrent_requests() {
+        let config = ServerConfig::try_from(r#"{
+            "port": 8080,
+            "scenarios": { "order": {} },
+            "routes": {
+                "/order": [
+                    { "method": "POST", "scenario": "order", "states": ["started"], "next_state": "placed", "response": { "type": "text", "text": "placed" } },
+                    { "method": "POST", "scenario": "order", "states": ["placed"], "response": { "type": "text", "text": "already placed" } }
+                ]
+            }
+        }"#.to_string()).unwrap();
+        let server = Arc::new(Server::new(config));
+        let requests = (0..16)
+            .map(|_| {
+                let server = server.clone();
+
+                thread::spawn(move || {
+                    let request = Request::new(
+                        Method::POST,
+                        "/order".to_string(),
+                        None,
+                        Version::new("HTTP".to_string(), "1.1".to_string()),
+                        Headers::new(),
+                        Vec::new()
+                    );
+
+                    create_response(&server, &request).body_text().to_string()
+                })
+            })
+            .collect::<Vec<_>>();
+        let bodies = requests
+            .into_iter()
+            .map(|request| request.join().unwrap())
+            .collect::<Vec<String>>();
+
+        assert_eq!(bodies.iter().filter(|body| *body == "placed").count(), 1);
+        assert_eq!(bodies.iter().filter(|body| *body == "already placed").count(), 15);
+    }
+
+    #[test]
+    fn echoes_the_request() {
+        let config = ServerConfig::try_from(r#"{
+            "port": 8080,
+            "routes": {
+                "/anything": [
+                    { "method": "POST", "response": { "type": "echo" } },
+                    { "method": "PUT", "response": { "type": "echo", "format": "raw", "http": { "status": 202 } } }
+                ]
+            }
+        }"#.to_string()).unwrap();
+        let server = Server::new(config);
+        let request = |method: Method| Request::new(
+            method,
+            "/anything".to_string(),
+            Some("tag=a".to_string()),
+            Version::new("HTTP".to_string(), "1.1".to_string()),
+            Headers::new(),
+            b"hello".to_vec()
+        ).with_raw_headers(vec![("X-Custom".to_string(), "yes".to_string())]);
+
+        let json = create_response(&server, &request(Method::POST));
+        let echo = serde_json::from_slice::<serde_json::Value>(json.body()).unwrap();
+
+        assert_eq!(json.status_code(), 200);
+        assert_eq!(json.headers().get(&HeaderName::ContentType), Some(&"application/json".to_string()));
+        assert_eq!(echo["path"], "/anything");
+        assert_eq!(echo["args"]["tag"], "a");
+        assert_eq!(echo["headers"]["X-Custom"], "yes");
+        assert_eq!(echo["body"], "hello");
+
+        let raw = create_response(&server, &request(Method::PUT));
+
+        assert_eq!(raw.status_code(), 202);
+        assert_eq!(raw.headers().get(&HeaderName::ContentType), Some(&"text/plain".to_string()));
+        assert_eq!(raw.body_text(), "PUT /anything?tag=a HTTP/1.1\r\nX-Custom: yes\r\n\r\nhello");
+    }
+
+    #[test]
+    fn responds_with_inline_json() {
+        let config = ServerConfig::try_from(r#"{
+            "port": 8080,
+            "routes": {
+                "/users/{id}": [
+                    { "method": "GET", "response": { "type": "json", "body": { "id": 1, "tags": ["a"], "deleted": null } } },
+                    { "method": "POST", "response": { "type": "json", "body": [1, 2], "pretty": true, "http": { "status": 201 } } },
+                    { "method": "PUT", "response": { "type": "json", "body": { "id": "{{request.params.id}}" }, "templated": true, "http": { "headers": { "Content-Type": "application/vnd.api+json" } } } }
+                ]
+            }
+        }"#.to_string()).unwrap();
+        let server = Server::new(config);
+        let request = |method: Method| Request::new(
+            method,
+            "/users/42".to_string(),
+            None,
+            Version::new("HTTP".to_string(), "1.1".to_string()),
+            Headers::new(),
+            Vec::new()
+        );
+
+        let compact = create_response(&server, &request(Method::GET));
+
+        assert_eq!(compact.status_code(), 200);
+        assert_eq!(compact.headers().get(&HeaderName::ContentType), Some(&"application/json".to_string()));
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(compact.body()).unwrap(), serde_json::json!({ "id": 1, "tags": ["a"], "deleted": null }));
+        assert!(!compact.body_text().contains([' ', '\n']));
+        assert_eq!(compact.headers().get(&HeaderName::ContentLength), Some(&compact.body().len().to_string()));
+
+        let pretty = create_response(&server, &request(Method::POST));
+
+        assert_eq!(pretty.status_code(), 201);
+        assert_eq!(pretty.body_text(), "[\n  1,\n  2\n]");
+
+        let templated = create_response(&server, &request(Method::PUT));
+
+        assert_eq!(templated.headers().get(&HeaderName::ContentType), Some(&"application/vnd.api+json".to_string()));
+        assert_eq!(templated.body_text(), r#"{"id":"42"}"#);
+    }
 }
@@ -0,0 +1,57 @@
+use std::{
+    sync::{Arc, atomic::{AtomicBool, Ordering}},
+    thread,
+    time::{Duration, Instant}
+};
+use crate::{logging::{LogLevel, log}, http::metrics::Metrics, config::shared::SharedConfig};
+
+static POLL_INTERVAL: Duration = Duration::from_millis(50);
+// connections still open after this long are cut off
+static MAX_WAIT: Duration = Duration::from_secs(30);
+
+// shuts the server down gracefully, it's cloned out of the server before it starts listening,
+// so that it can be triggered from elsewhere, e.g. a signal handler
+#[derive(Clone)]
+pub struct Shutdown {
+    draining: Arc<AtomicBool>,
+    config: SharedConfig,
+    metrics: Arc<Metrics>
+}
+
+impl Shutdown {
+    pub fn new(config: SharedConfig, metrics: Arc<Metrics>) -> Self {
+        return Self {
+            draining: Arc::new(AtomicBool::new(false)),
+            config,
+            metrics
+        };
+    }
+
+    pub fn is_draining(self: &Self) -> bool {
+        return self.draining.load(Ordering::Relaxed);
+    }
+
+    // reports the server as not ready for the drain period from the config, so that load balancers
+    // stop sending it requests, and then waits for the connections being served to finish
+    pub fn drain(self: &Self) {
+        let drain_seconds = match &self.config.load().health {
+            Some(health) => health.drain_seconds,
+            None => 0
+        };
+
+        self.draining.store(true, Ordering::Relaxed);
+        log(LogLevel::INFO, format!("Shutting down, draining for {} second(s).", drain_seconds));
+        thread::sleep(Duration::from_secs(drain_seconds));
+
+        let started = Instant::now();
+
+        while self.metrics.in_flight() > 0 {
+            if started.elapsed() > MAX_WAIT {
+                log(LogLevel::WARN, format!("Closing {} connection(s) that are still open.", self.metrics.in_flight()));
+                break;
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
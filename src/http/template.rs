@@ -0,0 +1,817 @@
+use std::sync::Arc;
+use chrono::{Utc, format::{StrftimeItems, Item}};
+use serde_json::{Map, Value, json};
+use crate::{http::{random, request::Request}, config::path};
+
+// templates nested deeper than this are refused, so that rendering them can't exhaust the stack
+static MAX_DEPTH: usize = 32;
+
+// a parsed template, like "Hello, {{request.query.name}}!"
+//
+// the language is a small, logic-less subset of Handlebars: {{expression}} outputs a value,
+// {{#if expression}}...{{else}}...{{/if}}, {{#unless expression}}...{{/unless}} and
+// {{#each expression}}...{{else}}...{{/each}} render their blocks depending on the values,
+// and {{! comment }} is left out. Expressions are paths like request.json.user.name, literals,
+// or helper calls like `eq request.method "POST"`, with (parentheses) around nested calls.
+// Templates can only read the request and have no side effects, so they are safe to render.
+#[derive(Debug, Clone)]
+pub struct Template {
+    nodes: Vec<Node>
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Output(Expr),
+    If { condition: Expr, negated: bool, then: Vec<Node>, otherwise: Vec<Node> },
+    Each { items: Expr, body: Vec<Node>, otherwise: Vec<Node> }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(Value),
+    // the segments of a path, the first one is the variable
+    Path(Vec<String>),
+    Call(Helper, Vec<Expr>)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Helper {
+    Now,
+    Uuid,
+    RandomInt,
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+    And,
+    Or,
+    Not,
+    Default,
+    Json,
+    Upper,
+    Lower,
+    Len
+}
+
+impl Helper {
+    fn find(name: &str) -> Option<Self> {
+        return match name {
+            "now" => Some(Self::Now),
+            "uuid" => Some(Self::Uuid),
+            "random_int" => Some(Self::RandomInt),
+            "eq" => Some(Self::Eq),
+            "ne" => Some(Self::Ne),
+            "gt" => Some(Self::Gt),
+            "lt" => Some(Self::Lt),
+            "and" => Some(Self::And),
+            "or" => Some(Self::Or),
+            "not" => Some(Self::Not),
+            "default" => Some(Self::Default),
+            "json" => Some(Self::Json),
+            "upper" => Some(Self::Upper),
+            "lower" => Some(Self::Lower),
+            "len" => Some(Self::Len),
+            _ => None
+        };
+    }
+
+    // the minimum and maximum number of arguments
+    fn arity(self: &Self) -> (usize, usize) {
+        return match self {
+            Self::Uuid => (0, 0),
+            Self::Now => (0, 1),
+            Self::Not | Self::Json | Self::Upper | Self::Lower | Self::Len => (1, 1),
+            Self::RandomInt | Self::Eq | Self::Ne | Self::Gt | Self::Lt | Self::Default => (2, 2),
+            Self::And | Self::Or => (2, usize::MAX)
+        };
+    }
+}
+
+// the templates of a text or json response parsed when the config is loaded, so that they are not
+// parsed again for every request, it's empty until then and for responses that are not templated
+#[derive(Clone, Default)]
+pub struct CompiledTemplate {
+    root: Option<Arc<Compiled>>
+}
+
+// a JSON body has a template for each of its strings, and the rest of it is kept as it is
+enum Compiled {
+    Template(Template),
+    Array(Vec<Compiled>),
+    Object(Vec<(String, Compiled)>),
+    Value(Value)
+}
+
+impl CompiledTemplate {
+    pub fn compile(source: &str) -> Result<Self, String> {
+        return Ok(Self { root: Some(Arc::new(Compiled::Template(Template::parse(source)?))) });
+    }
+
+    pub fn compile_json(value: &Value) -> Result<Self, String> {
+        return Ok(Self { root: Some(Arc::new(compile_value(value)?)) });
+    }
+
+    // the rendered text, None when the template was not compiled
+    pub fn render(self: &Self, context: &Value) -> Option<String> {
+        return match self.root.as_deref() {
+            Some(Compiled::Template(template)) => Some(template.render(context)),
+            _ => None
+        };
+    }
+
+    // the rendered JSON body, None when the template was not compiled
+    pub fn render_json(self: &Self, context: &Value) -> Option<Value> {
+        return self.root.as_deref().map(|compiled| render_value(compiled, context));
+    }
+}
+
+fn compile_value(value: &Value) -> Result<Compiled, String> {
+    return Ok(match value {
+        Value::String(source) => Compiled::Template(Template::parse(source)?),
+        Value::Array(items) => Compiled::Array(items.iter().map(compile_value).collect::<Result<_, _>>()?),
+        Value::Object(fields) => Compiled::Object(
+            fields.iter()
+                .map(|(key, field)| Ok((key.clone(), compile_value(field)?)))
+                .collect::<Result<_, String>>()?
+        ),
+        other => Compiled::Value(other.clone())
+    });
+}
+
+fn render_value(compiled: &Compiled, context: &Value) -> Value {
+    return match compiled {
+        Compiled::Template(template) => Value::String(template.render(context)),
+        Compiled::Array(items) => Value::Array(items.iter().map(|item| render_value(item, context)).collect()),
+        Compiled::Object(fields) => Value::Object(
+            fields.iter()
+                .map(|(key, field)| (key.clone(), render_value(field, context)))
+                .collect()
+        ),
+        Compiled::Value(value) => value.clone()
+    };
+}
+
+// the item of the innermost #each block, or the whole context outside of them
+struct Scope<'a> {
+    this: &'a Value,
+    index: Option<usize>,
+    key: Option<&'a String>
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = Parser { source, offset: 0 };
+        let (nodes, end) = parser.parse_nodes(0)?;
+
+        if let Some(end) = end {
+            return Err(parser.error(&format!("unexpected `{{{{{}}}}}`", end)));
+        }
+
+        return Ok(Self { nodes });
+    }
+
+    pub fn render(self: &Self, context: &Value) -> String {
+        let mut out = String::new();
+        let scope = Scope { this: context, index: None, key: None };
+
+        render_nodes(&self.nodes, context, &scope, &mut out);
+
+        return out;
+    }
+}
+
+// what the templates of a route response can use: the request and the params of its route's path
+pub fn context(request: &Request, route: &str) -> Value {
+    let mut query = Map::new();
+
+    // a parameter sent more than once keeps its first value
+    for (key, value) in request.query_params() {
+        query.entry(key).or_insert(Value::String(value));
+    }
+
+    let headers = request.raw_headers
+        .iter()
+        .map(|(name, value)| (name.to_lowercase(), Value::String(value.clone())))
+        .collect::<Map<String, Value>>();
+    let params = path::match_path(route, &request.path).unwrap_or_default();
+    let method: String = request.method.clone().into();
+
+    return json!({
+        "request": {
+            "id": request.id,
+            "method": method,
+            "path": request.path,
+            "query": query,
+            "headers": headers,
+            "params": params,
+            "body": request.body_text(),
+            // the body parsed as JSON, null when it isn't JSON
+            "json": serde_json::from_slice::<Value>(&request.body).unwrap_or(Value::Null)
+        }
+    });
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    offset: usize
+}
+
+impl<'a> Parser<'a> {
+    // parses nodes until the end of the source or until a block tag like {{/if}} or {{else}},
+    // which is returned for the caller to handle
+    fn parse_nodes(self: &mut Self, depth: usize) -> Result<(Vec<Node>, Option<String>), String> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("blocks are nested too deep"));
+        }
+
+        let mut nodes = Vec::new();
+
+        loop {
+            let rest = &self.source[self.offset..];
+            let start = match rest.find("{{") {
+                Some(start) => start,
+                None => {
+                    if !rest.is_empty() {
+                        nodes.push(Node::Text(rest.to_string()));
+                    }
+
+                    self.offset = self.source.len();
+
+                    return Ok((nodes, None));
+                }
+            };
+
+            if start > 0 {
+                nodes.push(Node::Text(rest[..start].to_string()));
+            }
+
+            self.offset += start;
+
+            let end = match self.source[self.offset..].find("}}") {
+                Some(end) => end,
+                None => return Err(self.error("`{{` is never closed"))
+            };
+            let tag = self.source[self.offset + 2..self.offset + end].trim().to_string();
+            let tag_offset = self.offset;
+
+            self.offset += end + 2;
+
+            if tag.starts_with('!') {
+                continue;
+            }
+
+            if tag == "else" || tag.starts_with('/') {
+                self.offset = tag_offset;
+                return Ok((nodes, Some(tag)));
+            }
+
+            let (block, expression) = match tag.strip_prefix('#') {
+                Some(block) => match block.split_once(char::is_whitespace) {
+                    Some((name, expression)) => (Some(name.to_string()), expression.to_string()),
+                    None => (Some(block.to_string()), String::new())
+                },
+                None => (None, tag.clone())
+            };
+            let expr = match parse_expression(&expression) {
+                Ok(expr) => expr,
+                Err(err) => {
+                    self.offset = tag_offset;
+                    return Err(self.error(&err));
+                }
+            };
+            let block = match block {
+                Some(block) => block,
+                None => {
+                    nodes.push(Node::Output(expr));
+                    continue;
+                }
+            };
+
+            if !["if", "unless", "each"].contains(&block.as_str()) {
+                self.offset = tag_offset;
+                return Err(self.error(&format!("unknown block `#{}`, expected #if, #unless or #each", block)));
+            }
+
+            let (body, otherwise) = self.parse_block(&block, depth)?;
+
+            nodes.push(match block.as_str() {
+                "each" => Node::Each { items: expr, body, otherwise },
+                _ => Node::If { condition: expr, negated: block == "unless", then: body, otherwise }
+            });
+        }
+    }
+
+    // parses the body of a block up to its closing tag, with the part after {{else}} if it has one
+    fn parse_block(self: &mut Self, block: &str, depth: usize) -> Result<(Vec<Node>, Vec<Node>), String> {
+        let closing = format!("/{}", block);
+        let (body, end) = self.parse_nodes(depth + 1)?;
+        let (otherwise, end) = match end.as_deref() {
+            Some("else") => {
+                self.skip_tag();
+                self.parse_nodes(depth + 1)?
+            },
+            _ => (Vec::new(), end)
+        };
+
+        return match end {
+            Some(end) if end == closing => {
+                self.skip_tag();
+                Ok((body, otherwise))
+            },
+            Some(end) => Err(self.error(&format!("expected `{{{{{}}}}}` but found `{{{{{}}}}}`", closing, end))),
+            None => Err(self.error(&format!("`{{{{#{}}}}}` is never closed", block)))
+        };
+    }
+
+    fn skip_tag(self: &mut Self) {
+        if let Some(end) = self.source[self.offset..].find("}}") {
+            self.offset += end + 2;
+        }
+    }
+
+    fn error(self: &Self, message: &str) -> String {
+        let line = self.source[..self.offset].matches('\n').count() + 1;
+
+        return format!("{} on line {} of the template", message, line);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Str(String),
+    Word(String)
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => (),
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '"' => {
+                let mut string = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => string.push('\n'),
+                            Some('t') => string.push('\t'),
+                            Some(escaped) => string.push(escaped),
+                            None => return Err("string is never closed".to_string())
+                        },
+                        Some(c) => string.push(c),
+                        None => return Err("string is never closed".to_string())
+                    };
+                }
+
+                tokens.push(Token::Str(string));
+            },
+            c => {
+                let mut word = c.to_string();
+
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !['(', ')', '"'].contains(c)) {
+                    word.push(c);
+                }
+
+                tokens.push(Token::Word(word));
+            }
+        };
+    }
+
+    return Ok(tokens);
+}
+
+fn parse_expression(expression: &str) -> Result<Expr, String> {
+    let tokens = tokenize(expression)?;
+
+    if tokens.is_empty() {
+        return Err("empty expression".to_string());
+    }
+
+    let mut idx = 0;
+    let expr = parse_call(&tokens, &mut idx, true)?;
+
+    if idx < tokens.len() {
+        return Err(format!("unexpected `{}` in `{}`", describe(&tokens[idx]), expression.trim()));
+    }
+
+    return Ok(expr);
+}
+
+// a helper call with its arguments, or a single term when it's not a call
+fn parse_call(tokens: &[Token], idx: &mut usize, top_level: bool) -> Result<Expr, String> {
+    let helper = match tokens.get(*idx) {
+        Some(Token::Word(word)) => Helper::find(word),
+        _ => None
+    };
+    let helper = match helper {
+        Some(helper) => helper,
+        None => return parse_term(tokens, idx)
+    };
+    let name = describe(&tokens[*idx]);
+    let mut args = Vec::new();
+
+    *idx += 1;
+
+    while let Some(token) = tokens.get(*idx) {
+        if token == &Token::Close {
+            break;
+        }
+
+        args.push(parse_term(tokens, idx)?);
+    }
+
+    if !top_level && tokens.get(*idx) != Some(&Token::Close) {
+        return Err(format!("`({}` is never closed", name));
+    }
+
+    let (min, max) = helper.arity();
+
+    if args.len() < min || args.len() > max {
+        let expected = match (min, max) {
+            (min, max) if min == max => format!("{}", min),
+            (min, usize::MAX) => format!("at least {}", min),
+            (min, max) => format!("{} to {}", min, max)
+        };
+
+        return Err(format!("`{}` takes {} argument(s) but got {}", name, expected, args.len()));
+    }
+
+    // a literal format can be checked now, instead of failing every time it's rendered
+    if let (Helper::Now, Some(Expr::Literal(Value::String(format)))) = (helper, args.first()) {
+        if !is_valid_time_format(format) {
+            return Err(format!("invalid time format `{}`", format));
+        }
+    }
+
+    return Ok(Expr::Call(helper, args));
+}
+
+fn parse_term(tokens: &[Token], idx: &mut usize) -> Result<Expr, String> {
+    let token = match tokens.get(*idx) {
+        Some(token) => token,
+        None => return Err("missing argument".to_string())
+    };
+
+    *idx += 1;
+
+    return match token {
+        Token::Str(string) => Ok(Expr::Literal(Value::String(string.clone()))),
+        Token::Close => Err("unexpected `)`".to_string()),
+        Token::Open => {
+            let expr = parse_call(tokens, idx, false)?;
+
+            match tokens.get(*idx) {
+                Some(Token::Close) => {
+                    *idx += 1;
+                    Ok(expr)
+                },
+                _ => Err("`(` is never closed".to_string())
+            }
+        },
+        Token::Word(word) => parse_word(word)
+    };
+}
+
+fn parse_word(word: &str) -> Result<Expr, String> {
+    match word {
+        "true" => return Ok(Expr::Literal(Value::Bool(true))),
+        "false" => return Ok(Expr::Literal(Value::Bool(false))),
+        "null" => return Ok(Expr::Literal(Value::Null)),
+        _ => ()
+    };
+
+    if let Ok(number) = word.parse::<i64>() {
+        return Ok(Expr::Literal(json!(number)));
+    }
+
+    if let Ok(number) = word.parse::<f64>() {
+        return Ok(Expr::Literal(json!(number)));
+    }
+
+    if Helper::find(word).is_some() {
+        return Err(format!("helper `{}` must be wrapped in parentheses when used as an argument", word));
+    }
+
+    let segments = word.split('.').map(|segment| segment.to_string()).collect::<Vec<String>>();
+
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return Err(format!("invalid path `{}`", word));
+    }
+
+    return match segments[0].as_str() {
+        "request" | "this" => Ok(Expr::Path(segments)),
+        "@index" | "@key" if segments.len() == 1 => Ok(Expr::Path(segments)),
+        _ => Err(format!("unknown variable `{}`, expected request, this, @index, @key or a helper", segments[0]))
+    };
+}
+
+fn describe(token: &Token) -> String {
+    return match token {
+        Token::Open => "(".to_string(),
+        Token::Close => ")".to_string(),
+        Token::Str(string) => format!("\"{}\"", string),
+        Token::Word(word) => word.clone()
+    };
+}
+
+fn is_valid_time_format(format: &str) -> bool {
+    return !StrftimeItems::new(format).any(|item| item == Item::Error);
+}
+
+fn render_nodes(nodes: &[Node], context: &Value, scope: &Scope, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Output(expr) => out.push_str(&to_text(&evaluate(expr, context, scope))),
+            Node::If { condition, negated, then, otherwise } => {
+                let branch = match is_truthy(&evaluate(condition, context, scope)) != *negated {
+                    true => then,
+                    false => otherwise
+                };
+
+                render_nodes(branch, context, scope, out);
+            },
+            Node::Each { items, body, otherwise } => {
+                let items = evaluate(items, context, scope);
+                let entries: Vec<(Option<&String>, &Value)> = match &items {
+                    Value::Array(items) => items.iter().map(|item| (None, item)).collect(),
+                    Value::Object(items) => items.iter().map(|(key, item)| (Some(key), item)).collect(),
+                    _ => Vec::new()
+                };
+
+                if entries.is_empty() {
+                    render_nodes(otherwise, context, scope, out);
+                }
+
+                for (index, (key, this)) in entries.into_iter().enumerate() {
+                    render_nodes(body, context, &Scope { this, index: Some(index), key }, out);
+                }
+            }
+        };
+    }
+}
+
+fn evaluate(expr: &Expr, context: &Value, scope: &Scope) -> Value {
+    return match expr {
+        Expr::Literal(value) => value.clone(),
+        Expr::Path(segments) => resolve(segments, context, scope),
+        Expr::Call(helper, args) => {
+            let args = args
+                .iter()
+                .map(|arg| evaluate(arg, context, scope))
+                .collect::<Vec<Value>>();
+
+            call(*helper, &args)
+        }
+    };
+}
+
+fn resolve(segments: &[String], context: &Value, scope: &Scope) -> Value {
+    let mut value = match segments[0].as_str() {
+        "this" => scope.this,
+        "@index" => return scope.index.map(|index| json!(index)).unwrap_or(Value::Null),
+        "@key" => return scope.key.map(|key| json!(key)).unwrap_or(Value::Null),
+        _ => context.get(&segments[0]).unwrap_or(&Value::Null)
+    };
+
+    for segment in &segments[1..] {
+        value = match value {
+            Value::Object(object) => object
+                .get(segment)
+                // header names are not case sensitive, so neither are the keys
+                .or_else(|| object.iter().find(|(key, _)| key.eq_ignore_ascii_case(segment)).map(|(_, value)| value))
+                .unwrap_or(&Value::Null),
+            Value::Array(items) => segment
+                .parse::<usize>()
+                .ok()
+                .and_then(|idx| items.get(idx))
+                .unwrap_or(&Value::Null),
+            _ => &Value::Null
+        };
+    }
+
+    return value.clone();
+}
+
+fn call(helper: Helper, args: &[Value]) -> Value {
+    return match helper {
+        Helper::Now => match args.first().and_then(|format| format.as_str()) {
+            Some(format) if is_valid_time_format(format) => json!(Utc::now().format(format).to_string()),
+            _ => json!(Utc::now().to_rfc3339())
+        },
+        Helper::Uuid => json!(random::uuid()),
+        Helper::RandomInt => match (as_integer(&args[0]), as_integer(&args[1])) {
+            (Some(min), Some(max)) => json!(random::between(min, max)),
+            _ => Value::Null
+        },
+        Helper::Eq => json!(loosely_equal(&args[0], &args[1])),
+        Helper::Ne => json!(!loosely_equal(&args[0], &args[1])),
+        Helper::Gt => json!(compare(&args[0], &args[1]) == Some(std::cmp::Ordering::Greater)),
+        Helper::Lt => json!(compare(&args[0], &args[1]) == Some(std::cmp::Ordering::Less)),
+        Helper::And => json!(args.iter().all(is_truthy)),
+        Helper::Or => json!(args.iter().any(is_truthy)),
+        Helper::Not => json!(!is_truthy(&args[0])),
+        Helper::Default => match is_truthy(&args[0]) {
+            true => args[0].clone(),
+            false => args[1].clone()
+        },
+        Helper::Json => json!(args[0].to_string()),
+        Helper::Upper => json!(to_text(&args[0]).to_uppercase()),
+        Helper::Lower => json!(to_text(&args[0]).to_lowercase()),
+        Helper::Len => match &args[0] {
+            Value::String(string) => json!(string.chars().count()),
+            Value::Array(items) => json!(items.len()),
+            Value::Object(object) => json!(object.len()),
+            _ => json!(0)
+        }
+    };
+}
+
+// the value as it's written into the response, strings without their quotes
+fn to_text(value: &Value) -> String {
+    return match value {
+        Value::Null => String::new(),
+        Value::String(string) => string.clone(),
+        value => value.to_string()
+    };
+}
+
+fn is_truthy(value: &Value) -> bool {
+    return match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(number) => number.as_f64() != Some(0.0),
+        Value::String(string) => !string.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(object) => !object.is_empty()
+    };
+}
+
+// query params and headers are always strings, so "2" is equal to 2
+fn loosely_equal(left: &Value, right: &Value) -> bool {
+    return match (left, right) {
+        (Value::String(_), Value::String(_)) => left == right,
+        (Value::String(_), _) | (_, Value::String(_)) => to_text(left) == to_text(right),
+        _ => left == right
+    };
+}
+
+fn compare(left: &Value, right: &Value) -> Option<std::cmp::Ordering> {
+    return match (as_number(left), as_number(right)) {
+        (Some(left), Some(right)) => left.partial_cmp(&right),
+        _ => Some(to_text(left).cmp(&to_text(right)))
+    };
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    return match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(string) => string.trim().parse::<f64>().ok(),
+        _ => None
+    };
+}
+
+fn as_integer(value: &Value) -> Option<i64> {
+    return as_number(value).map(|number| number as i64);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(source: &str, context: Value) -> String {
+        return Template::parse(source).unwrap().render(&context);
+    }
+
+    fn parse_error(source: &str) -> String {
+        return Template::parse(source).unwrap_err();
+    }
+
+    #[test]
+    fn outputs_paths() {
+        let context = json!({ "request": { "query": { "name": "Ada" }, "json": { "tags": ["a", "b"] } } });
+
+        assert_eq!(render("Hello, {{request.query.name}}!", context.clone()), "Hello, Ada!");
+        assert_eq!(render("{{request.json.tags.1}}", context.clone()), "b");
+        assert_eq!(render("{{request.json.tags}}", context), "[\"a\",\"b\"]");
+    }
+
+    #[test]
+    fn matches_keys_regardless_of_case() {
+        let context = json!({ "request": { "headers": { "x-user": "ada" } } });
+
+        assert_eq!(render("{{request.headers.X-User}}", context), "ada");
+    }
+
+    #[test]
+    fn renders_missing_fields_as_nothing() {
+        let context = json!({ "request": { "query": {} } });
+
+        assert_eq!(render("[{{request.query.name}}]", context.clone()), "[]");
+        assert_eq!(render("[{{request.query.name.first}}]", context.clone()), "[]");
+        assert_eq!(render("[{{request.json.items.0}}]", context.clone()), "[]");
+        assert_eq!(render("{{default request.query.name \"guest\"}}", context), "guest");
+    }
+
+    #[test]
+    fn renders_if_and_unless_blocks() {
+        let template = "{{#if request.query.admin}}admin{{else}}user{{/if}}";
+
+        assert_eq!(render(template, json!({ "request": { "query": { "admin": "yes" } } })), "admin");
+        assert_eq!(render(template, json!({ "request": { "query": {} } })), "user");
+        assert_eq!(render("{{#unless request.json}}no body{{/unless}}", json!({ "request": { "json": null } })), "no body");
+        assert_eq!(render("{{#if (eq request.method \"POST\")}}created{{/if}}", json!({ "request": { "method": "POST" } })), "created");
+    }
+
+    #[test]
+    fn renders_each_blocks() {
+        let context = json!({ "request": { "json": { "items": ["a", "b"], "counts": { "x": 1 }, "none": [] } } });
+
+        assert_eq!(render("{{#each request.json.items}}{{@index}}={{this}};{{/each}}", context.clone()), "0=a;1=b;");
+        assert_eq!(render("{{#each request.json.counts}}{{@key}}={{this}}{{/each}}", context.clone()), "x=1");
+        assert_eq!(render("{{#each request.json.none}}item{{else}}empty{{/each}}", context), "empty");
+    }
+
+    #[test]
+    fn nests_blocks() {
+        let context = json!({ "request": { "json": { "users": [{ "name": "ada", "admin": true }, { "name": "bob", "admin": false }] } } });
+        let template = "{{#each request.json.users}}{{#if this.admin}}{{upper this.name}}{{else}}{{this.name}}{{/if}} {{/each}}";
+
+        assert_eq!(render(template, context), "ADA bob ");
+    }
+
+    #[test]
+    fn unescapes_string_literals() {
+        let context = json!({ "request": {} });
+
+        assert_eq!(render("{{default request.name \"say \\\"hi\\\"\"}}", context.clone()), "say \"hi\"");
+        assert_eq!(render("{{default request.name \"a\\nb\\tc\"}}", context), "a\nb\tc");
+    }
+
+    #[test]
+    fn leaves_out_comments() {
+        assert_eq!(render("a{{! a comment }}b", json!({})), "ab");
+    }
+
+    #[test]
+    fn compares_strings_and_numbers_loosely() {
+        let context = json!({ "request": { "query": { "page": "2" } } });
+
+        assert_eq!(render("{{eq request.query.page 2}}", context.clone()), "true");
+        assert_eq!(render("{{gt 3 request.query.page}}", context.clone()), "true");
+        assert_eq!(render("{{len request.query.page}}", context), "1");
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        assert_eq!(parse_error("Hello {{request.name"), "`{{` is never closed on line 1 of the template");
+        assert_eq!(parse_error("{{#if request.a}}\nyes"), "`{{#if}}` is never closed on line 2 of the template");
+        assert_eq!(parse_error("{{#if request.a}}{{/each}}"), "expected `{{/if}}` but found `{{/each}}` on line 1 of the template");
+        assert_eq!(parse_error("{{/if}}"), "unexpected `{{/if}}` on line 1 of the template");
+        assert_eq!(parse_error("{{#with request}}{{/with}}"), "unknown block `#with`, expected #if, #unless or #each on line 1 of the template");
+        assert_eq!(parse_error("{{}}"), "empty expression on line 1 of the template");
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert!(parse_error("{{user.name}}").starts_with("unknown variable `user`"));
+        assert!(parse_error("{{eq request.a}}").starts_with("`eq` takes 2 argument(s) but got 1"));
+        assert!(parse_error("{{not (eq request.a 1}}").starts_with("`(eq` is never closed"));
+        assert!(parse_error("{{default request.a \"x}}").starts_with("string is never closed"));
+        assert!(parse_error("{{eq uuid 1}}").starts_with("helper `uuid` must be wrapped in parentheses"));
+        assert!(parse_error("{{now \"%Q\"}}").starts_with("invalid time format `%Q`"));
+    }
+
+    #[test]
+    fn rejects_blocks_nested_too_deep() {
+        let source = "{{#if request}}".repeat(MAX_DEPTH + 2);
+
+        assert!(parse_error(&source).starts_with("blocks are nested too deep"));
+    }
+
+    #[test]
+    fn renders_compiled_json_bodies() {
+        let body = json!({ "id": "{{request.params.id}}", "tags": ["{{upper request.query.tag}}", 1], "ok": true });
+        let compiled = CompiledTemplate::compile_json(&body).unwrap();
+        let context = json!({ "request": { "params": { "id": "7" }, "query": { "tag": "new" } } });
+
+        assert_eq!(compiled.render_json(&context), Some(json!({ "id": "7", "tags": ["NEW", 1], "ok": true })));
+        assert_eq!(compiled.render(&context), None);
+        assert!(CompiledTemplate::compile_json(&json!({ "a": ["{{#if}}"] })).is_err());
+    }
+
+    #[test]
+    fn renders_nothing_until_compiled() {
+        assert_eq!(CompiledTemplate::default().render(&json!({})), None);
+        assert_eq!(CompiledTemplate::compile("{{request.method}}").unwrap().render(&json!({ "request": { "method": "GET" } })), Some("GET".to_string()));
+    }
+}
@@ -27,7 +27,7 @@ impl TryFrom<String> for Version {
         }
 
         let version = Version::new(
-            version_split.get(0).unwrap().to_string(),
+            version_split.first().unwrap().to_string(),
             version_split.get(1).unwrap().to_string()
         );
 
@@ -1,3 +1,5 @@
 pub mod http;
 pub mod logging;
 pub mod config;
+pub mod error;
+pub mod cli;
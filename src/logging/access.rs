@@ -0,0 +1,288 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    net::IpAddr,
+    sync::Mutex,
+    time::Duration
+};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use crate::{
+    logging::{LogLevel, log},
+    http::{request::Request, response::ResponseSummary, headers::HeaderName},
+    config::AccessLogConfig
+};
+
+pub enum AccessLogFormat {
+    // Apache common log format
+    Common,
+    // Apache combined log format, the common one with referer and user agent
+    Combined,
+    // one JSON object per line
+    Json,
+    // a template with %placeholders%
+    Custom(String)
+}
+
+impl From<&str> for AccessLogFormat {
+    fn from(s: &str) -> Self {
+        return match s {
+            "common" => Self::Common,
+            "combined" => Self::Combined,
+            "json" => Self::Json,
+            template => Self::Custom(template.to_string())
+        };
+    }
+}
+
+impl AccessLogFormat {
+    pub fn format(self: &Self, entry: &AccessLogEntry) -> String {
+        return match self {
+            Self::Common => format!(
+                "{} - - [{}] \"{}\" {} {}",
+                entry.client_ip(),
+                entry.time.format("%d/%b/%Y:%H:%M:%S %z"),
+                entry.request_line(),
+                entry.status_code,
+                entry.body_bytes
+            ),
+            Self::Combined => format!(
+                "{} - - [{}] \"{}\" {} {} \"{}\" \"{}\"",
+                entry.client_ip(),
+                entry.time.format("%d/%b/%Y:%H:%M:%S %z"),
+                entry.request_line(),
+                entry.status_code,
+                entry.body_bytes,
+                entry.referer.as_deref().unwrap_or("-"),
+                entry.user_agent.as_deref().unwrap_or("-")
+            ),
+            Self::Json => json!({
+                "time": entry.time.to_rfc3339(),
+                "request_id": entry.request_id,
+                "client_ip": entry.client_ip,
+                "method": entry.method,
+                "path": entry.path,
+                "query": entry.query,
+                "protocol": entry.protocol,
+                "status": entry.status_code,
+                "bytes": entry.body_bytes,
+                "duration_ms": entry.duration.as_secs_f64() * 1000.0,
+                "user_agent": entry.user_agent,
+                "referer": entry.referer
+            })
+            .to_string(),
+            Self::Custom(template) => format_custom(template, entry)
+        };
+    }
+}
+
+// the placeholders are replaced in a single pass over the template, so the values,
+// which clients control, are never searched for placeholders themselves
+fn format_custom(template: &str, entry: &AccessLogEntry) -> String {
+    let mut line = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('%') {
+        line.push_str(&rest[..start]);
+
+        let after = &rest[start + 1..];
+        let placeholder = after
+            .find('%')
+            .and_then(|end| placeholder_value(&after[..end], entry).map(|value| (end, value)));
+
+        match placeholder {
+            Some((end, value)) => {
+                line.push_str(&value);
+                rest = &after[end + 1..];
+            },
+            // a % that doesn't start a known placeholder is written as it is
+            None => {
+                line.push('%');
+                rest = after;
+            }
+        };
+    }
+
+    line.push_str(rest);
+
+    return line;
+}
+
+fn placeholder_value(name: &str, entry: &AccessLogEntry) -> Option<String> {
+    return match name {
+        "request_id" => Some(entry.request_id.clone()),
+        "client_ip" => Some(entry.client_ip()),
+        "method" => Some(entry.method.clone().unwrap_or("-".to_string())),
+        "path" => Some(entry.path.clone().unwrap_or("-".to_string())),
+        "query" => Some(entry.query.clone().unwrap_or_default()),
+        "protocol" => Some(entry.protocol.clone().unwrap_or("-".to_string())),
+        "status" => Some(entry.status_code.to_string()),
+        "bytes" => Some(entry.body_bytes.to_string()),
+        "duration" => Some(entry.duration.as_millis().to_string()),
+        "user_agent" => Some(entry.user_agent.clone().unwrap_or("-".to_string())),
+        "referer" => Some(entry.referer.clone().unwrap_or("-".to_string())),
+        "date" => Some(entry.time.format("%Y-%m-%d").to_string()),
+        "time" => Some(entry.time.format("%H:%M:%S").to_string()),
+        _ => None
+    };
+}
+
+// everything we know about a served request
+pub struct AccessLogEntry {
+    pub time: DateTime<Utc>,
+    pub request_id: String,
+    pub client_ip: Option<IpAddr>,
+    // request data is missing when the request could not be parsed
+    pub method: Option<String>,
+    pub path: Option<String>,
+    pub query: Option<String>,
+    pub protocol: Option<String>,
+    pub user_agent: Option<String>,
+    pub referer: Option<String>,
+    pub status_code: u16,
+    pub body_bytes: usize,
+    pub duration: Duration
+}
+
+impl AccessLogEntry {
+    pub fn new(
+        request_id: &str,
+        request: Option<&Request>,
+        client_ip: Option<IpAddr>,
+        summary: ResponseSummary,
+        duration: Duration
+    ) -> Self {
+        return Self {
+            time: Utc::now(),
+            request_id: request_id.to_string(),
+            client_ip,
+            method: request.map(|request| request.method.clone().into()),
+            path: request.map(|request| request.path.clone()),
+            query: request.and_then(|request| request.query.clone()),
+            protocol: request.map(|request| request.version.to_string()),
+            user_agent: request.and_then(|request| request.headers.get(&HeaderName::UserAgent).cloned()),
+            referer: request.and_then(|request| request.headers.get(&HeaderName::Referer).cloned()),
+            status_code: summary.status_code,
+            body_bytes: summary.body_bytes,
+            duration
+        };
+    }
+
+    fn client_ip(self: &Self) -> String {
+        return match self.client_ip {
+            Some(ip) => ip.to_string(),
+            None => "-".to_string()
+        };
+    }
+
+    fn request_line(self: &Self) -> String {
+        let method = match &self.method {
+            Some(method) => method,
+            None => return "-".to_string()
+        };
+        let path = self.path.as_deref().unwrap_or("-");
+        let target = match &self.query {
+            Some(query) => format!("{}?{}", path, query),
+            None => path.to_string()
+        };
+
+        return format!("{} {} {}", method, target, self.protocol.as_deref().unwrap_or("-"));
+    }
+}
+
+// writes access log lines to the output from the config, keeping the log file open
+// between requests and reopening it when the config changes
+pub struct AccessLogger {
+    file: Mutex<Option<(String, File)>>
+}
+
+impl AccessLogger {
+    pub fn new() -> Self {
+        return Self { file: Mutex::new(None) };
+    }
+
+    pub fn log(self: &Self, config: &AccessLogConfig, entry: &AccessLogEntry) {
+        let line = AccessLogFormat::from(config.format.as_str()).format(entry);
+
+        if config.output == "stdout" {
+            // like with the file, a line that cannot be written is lost rather than panicking
+            let _ = writeln!(io::stdout().lock(), "{}", line);
+            return;
+        }
+
+        let mut file = match self.file.lock() {
+            Ok(file) => file,
+            Err(poisoned) => poisoned.into_inner()
+        };
+
+        if file.as_ref().map(|(path, _)| path) != Some(&config.output) {
+            *file = match open(&config.output) {
+                Ok(opened) => Some((config.output.clone(), opened)),
+                Err(err) => {
+                    log(LogLevel::ERROR, format!("Cannot open access log file \"{}\". {}", config.output, err));
+                    return;
+                }
+            };
+        }
+
+        if let Some((path, file)) = file.as_mut() {
+            if let Err(err) = writeln!(file, "{}", line) {
+                log(LogLevel::ERROR, format!("Cannot write to access log file \"{}\". {}", path, err));
+            }
+        }
+    }
+}
+
+fn open(path: &str) -> io::Result<File> {
+    return OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path);
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use super::*;
+
+    fn entry(user_agent: &str) -> AccessLogEntry {
+        return AccessLogEntry {
+            time: Utc.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap(),
+            request_id: "abc".to_string(),
+            client_ip: Some("127.0.0.1".parse().unwrap()),
+            method: Some("GET".to_string()),
+            path: Some("/orders".to_string()),
+            query: Some("page=2".to_string()),
+            protocol: Some("HTTP/1.1".to_string()),
+            user_agent: Some(user_agent.to_string()),
+            referer: None,
+            status_code: 404,
+            body_bytes: 12,
+            duration: Duration::from_millis(7)
+        };
+    }
+
+    #[test]
+    fn formats_custom_templates() {
+        let format = AccessLogFormat::from("%date% %time% %client_ip% %method% %path%?%query% %status% %bytes% %duration%ms %referer% 100%");
+
+        assert_eq!(format.format(&entry("curl")), "2025-01-02 03:04:05 127.0.0.1 GET /orders?page=2 404 12 7ms - 100%");
+    }
+
+    #[test]
+    fn never_replaces_placeholders_in_values() {
+        let format = AccessLogFormat::from("%user_agent% %status%");
+
+        assert_eq!(format.format(&entry("%status% %date%")), "%status% %date% 404");
+    }
+
+    #[test]
+    fn formats_the_combined_format() {
+        let format = AccessLogFormat::from("combined");
+
+        assert_eq!(
+            format.format(&entry("curl")),
+            "127.0.0.1 - - [02/Jan/2025:03:04:05 +0000] \"GET /orders?page=2 HTTP/1.1\" 404 12 \"-\" \"curl\""
+        );
+    }
+}
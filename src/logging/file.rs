@@ -0,0 +1,119 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf
+};
+use chrono::Utc;
+use crate::config::{LogRotationConfig, RotationInterval};
+
+// a log file rotated by size or time, rotated files get a numeric suffix,
+// ".1" being the most recent one
+pub struct LogFile {
+    path: PathBuf,
+    rotation: Option<LogRotationConfig>,
+    file: Option<File>,
+    size: u64,
+    // the hour or day the file was opened in, it's rotated once it changes
+    period: String
+}
+
+impl LogFile {
+    pub fn new(path: PathBuf, rotation: Option<LogRotationConfig>) -> Self {
+        return Self {
+            path,
+            rotation,
+            file: None,
+            size: 0,
+            period: String::new()
+        };
+    }
+
+    pub fn write_line(self: &mut Self, line: &str) -> io::Result<()> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+
+        if self.should_rotate(line.len() as u64 + 1) {
+            self.rotate()?;
+        }
+
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => return Ok(())
+        };
+
+        writeln!(file, "{}", line)?;
+        self.size += line.len() as u64 + 1;
+
+        return Ok(());
+    }
+
+    fn open(self: &mut Self) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+
+        self.size = file.metadata()?.len();
+        self.period = current_period(&self.rotation);
+        self.file = Some(file);
+
+        return Ok(());
+    }
+
+    fn should_rotate(self: &Self, incoming: u64) -> bool {
+        let rotation = match &self.rotation {
+            Some(rotation) => rotation,
+            None => return false
+        };
+        let too_large = match rotation.max_size {
+            Some(max_size) => self.size > 0 && self.size + incoming > max_size,
+            None => false
+        };
+        let period_changed = rotation.interval.is_some() && current_period(&self.rotation) != self.period;
+
+        return too_large || period_changed;
+    }
+
+    fn rotate(self: &mut Self) -> io::Result<()> {
+        let keep = match &self.rotation {
+            Some(rotation) => rotation.keep,
+            None => return Ok(())
+        };
+
+        self.file = None;
+
+        // we shift older files up by one, dropping the ones over the limit
+        let _ = fs::remove_file(self.rotated_path(keep));
+
+        for idx in (1..keep).rev() {
+            let _ = fs::rename(self.rotated_path(idx), self.rotated_path(idx + 1));
+        }
+
+        if keep > 0 {
+            fs::rename(&self.path, self.rotated_path(1))?;
+        } else {
+            fs::remove_file(&self.path)?;
+        }
+
+        return self.open();
+    }
+
+    fn rotated_path(self: &Self, idx: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+
+        path.push(format!(".{}", idx));
+
+        return path.into();
+    }
+}
+
+fn current_period(rotation: &Option<LogRotationConfig>) -> String {
+    let format = match rotation.as_ref().and_then(|rotation| rotation.interval) {
+        Some(RotationInterval::Hourly) => "%Y-%m-%d %H",
+        Some(RotationInterval::Daily) => "%Y-%m-%d",
+        None => return String::new()
+    };
+
+    return Utc::now().format(format).to_string();
+}
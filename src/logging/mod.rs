@@ -1,7 +1,31 @@
+pub mod access;
+pub mod file;
+pub mod trace;
+#[cfg(feature = "tracing")]
+pub mod subscriber;
+
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    panic::Location,
+    process,
+    sync::Mutex
+};
 use chrono::{Utc, DateTime};
+use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
+use crate::{config::{LoggingConfig, LogStyle, LogColor}, logging::file::LogFile};
 
 static FORMATTING: &str = "[%loglevel%] %date% %time% => %message%";
 
+// the logger configured from the config and CLI flags, messages are logged
+// with the default settings until it's configured
+static LOGGER: Mutex<Option<Logger>> = Mutex::new(None);
+// the settings given as CLI flags, they take precedence over the ones from the config
+static OVERRIDES: Mutex<Option<LoggingConfig>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
 pub enum LogLevel {
     INFO,
     DEBUG,
@@ -9,6 +33,36 @@ pub enum LogLevel {
     WARN
 }
 
+impl LogLevel {
+    // how severe the level is, messages below the configured severity are not logged
+    pub fn severity(self: &Self) -> u8 {
+        return match self {
+            Self::DEBUG => 0,
+            Self::INFO => 1,
+            Self::WARN => 2,
+            Self::ERROR => 3
+        };
+    }
+
+    fn color(self: &Self) -> &'static str {
+        return match self {
+            Self::DEBUG => "\x1b[34m",
+            Self::INFO => "\x1b[32m",
+            Self::WARN => "\x1b[33m",
+            Self::ERROR => "\x1b[31m"
+        };
+    }
+
+    fn syslog_severity(self: &Self) -> u8 {
+        return match self {
+            Self::DEBUG => 7,
+            Self::INFO => 6,
+            Self::WARN => 4,
+            Self::ERROR => 3
+        };
+    }
+}
+
 impl Into<String> for LogLevel {
     fn into(self) -> String {
         return match self {
@@ -21,17 +75,193 @@ impl Into<String> for LogLevel {
     }
 }
 
+impl TryFrom<String> for LogLevel {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        return match s.to_lowercase().as_str() {
+            "debug" => Ok(Self::DEBUG),
+            "info" => Ok(Self::INFO),
+            "warn" => Ok(Self::WARN),
+            "error" => Ok(Self::ERROR),
+            _ => Err(format!("Unknown log level \"{}\", expected one of debug, info, warn or error.", s))
+        };
+    }
+}
+
+enum Output {
+    Stdout,
+    Stderr,
+    File(LogFile)
+}
+
+struct Logger {
+    level: LogLevel,
+    // sorted from the most specific module
+    modules: Vec<(String, LogLevel)>,
+    style: LogStyle,
+    format: String,
+    color: bool,
+    output: Output
+}
+
+impl Logger {
+    fn new(config: &LoggingConfig) -> Self {
+        let output = match config.output.as_deref() {
+            None | Some("stdout") => Output::Stdout,
+            Some("stderr") => Output::Stderr,
+            Some(path) => Output::File(LogFile::new(path.into(), config.rotation.clone()))
+        };
+        let color = match (config.color.unwrap_or(LogColor::Auto), &output) {
+            (LogColor::Always, _) => true,
+            (LogColor::Auto, Output::Stdout) => io::stdout().is_terminal(),
+            (LogColor::Auto, Output::Stderr) => io::stderr().is_terminal(),
+            _ => false
+        };
+        let mut modules = config.modules
+            .iter()
+            .map(|(module, level)| (module.clone(), *level))
+            .collect::<Vec<(String, LogLevel)>>();
+
+        modules.sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+
+        return Self {
+            level: config.level.unwrap_or(LogLevel::INFO),
+            modules,
+            style: config.style.unwrap_or(LogStyle::Plain),
+            format: config.format.clone().unwrap_or(FORMATTING.to_string()),
+            color,
+            output
+        };
+    }
+
+    fn enabled(self: &Self, level: LogLevel, module: &str) -> bool {
+        let minimum = self.modules
+            .iter()
+            .find(|(prefix, _)| module == prefix || module.starts_with(&format!("{}::", prefix)))
+            .map(|(_, level)| *level)
+            .unwrap_or(self.level);
+
+        return level.severity() >= minimum.severity();
+    }
+
+    fn write(self: &mut Self, level: LogLevel, module: &str, message: &str) {
+        if !self.enabled(level, module) {
+            return;
+        }
+
+        let line = match self.style {
+            LogStyle::Plain => format_plain(&self.format, level, module, message, self.color),
+            LogStyle::Syslog => format_syslog(level, module, message)
+        };
+
+        let _ = match &mut self.output {
+            Output::Stdout => writeln!(io::stdout().lock(), "{}", line),
+            Output::Stderr => writeln!(io::stderr().lock(), "{}", line),
+            Output::File(file) => file.write_line(&line)
+        };
+    }
+}
+
+// sets up the logger from the logging config, it's called again every time the config is reloaded
+pub fn configure(config: &LoggingConfig) {
+    let overrides = match OVERRIDES.lock() {
+        Ok(overrides) => overrides.clone(),
+        Err(poisoned) => poisoned.into_inner().clone()
+    };
+    let config = match overrides {
+        Some(overrides) => config.overridden_by(&overrides),
+        None => config.clone()
+    };
+    let mut logger = match LOGGER.lock() {
+        Ok(logger) => logger,
+        Err(poisoned) => poisoned.into_inner()
+    };
+
+    *logger = Some(Logger::new(&config));
+}
+
+// sets the logging settings that take precedence over the ones from the config
+pub fn set_overrides(overrides: LoggingConfig) {
+    let mut current = match OVERRIDES.lock() {
+        Ok(current) => current,
+        Err(poisoned) => poisoned.into_inner()
+    };
+
+    *current = Some(overrides.clone());
+    drop(current);
+    configure(&LoggingConfig::default());
+}
+
+// logs the message, with the tracing feature enabled it's emitted as a tracing event instead
+#[track_caller]
 pub fn log(level: LogLevel, message: String) {
+    let module = module_of(Location::caller().file());
+
+    #[cfg(feature = "tracing")]
+    trace::event(level, &module, &message);
+    #[cfg(not(feature = "tracing"))]
+    write(level, &module, &message);
+}
+
+// writes the message with the configured logger
+pub fn write(level: LogLevel, module: &str, message: &str) {
+    let mut logger = match LOGGER.lock() {
+        Ok(logger) => logger,
+        Err(poisoned) => poisoned.into_inner()
+    };
+
+    match logger.as_mut() {
+        Some(logger) => logger.write(level, module, message),
+        // a closed stdout, e.g. piped into `head`, must not take the server down with it
+        None => { let _ = writeln!(io::stdout().lock(), "{}", format_plain(FORMATTING, level, module, message, false)); }
+    };
+}
+
+// turns the path of the source file into the module path, e.g. "src/http/server.rs" into "http::server"
+fn module_of(file: &str) -> String {
+    let file = file.replace('\\', "/");
+    let relative = match file.rfind("src/") {
+        Some(idx) => &file[idx + 4..],
+        None => file.as_str()
+    };
+
+    return relative
+        .trim_end_matches(".rs")
+        .trim_end_matches("/mod")
+        .replace('/', "::");
+}
+
+fn format_plain(format: &str, level: LogLevel, module: &str, message: &str, color: bool) -> String {
     let level_str: String = level.into();
+    let level_str = match color {
+        true => format!("{}{}\x1b[0m", level.color(), level_str),
+        false => level_str
+    };
     let now: DateTime<Utc> = Utc::now();
     let date_str = now.format("%Y-%m-%d").to_string();
     let time_str = now.format("%H:%M:%S").to_string();
 
-    let formatted = FORMATTING
+    return format
         .replace("%loglevel%", level_str.as_str())
-        .replace("%message%", message.as_str())
+        .replace("%module%", module)
+        .replace("%message%", message)
         .replace("%date%", date_str.as_str())
         .replace("%time%", time_str.as_str());
-    
-    println!("{}", formatted);
+}
+
+// formats the line as a RFC 5424 syslog message with the user facility
+fn format_syslog(level: LogLevel, module: &str, message: &str) -> String {
+    let priority = 8 + level.syslog_severity();
+    let hostname = env::var("HOSTNAME").unwrap_or("-".to_string());
+
+    return format!(
+        "<{}>1 {} {} quickserving {} {} - {}",
+        priority,
+        Utc::now().to_rfc3339(),
+        hostname,
+        process::id(),
+        module,
+        message
+    );
 }
@@ -0,0 +1,68 @@
+use std::fmt;
+use tracing::{Event, Level, Subscriber, field::{Field, Visit}};
+use tracing_subscriber::{layer::{Context, Layer, SubscriberExt}, registry::Registry};
+use crate::logging::{LogLevel, write};
+
+// a tracing layer writing events with the console logger, so that the binary
+// logs the same way whether the tracing feature is enabled or not
+pub struct ConsoleLayer;
+
+impl<S: Subscriber> Layer<S> for ConsoleLayer {
+    fn on_event(self: &Self, event: &Event<'_>, _: Context<'_, S>) {
+        let mut visitor = FieldVisitor {
+            message: String::new(),
+            module: None,
+            fields: Vec::new()
+        };
+
+        event.record(&mut visitor);
+
+        let level = match *event.metadata().level() {
+            Level::ERROR => LogLevel::ERROR,
+            Level::WARN => LogLevel::WARN,
+            Level::INFO => LogLevel::INFO,
+            _ => LogLevel::DEBUG
+        };
+        let module = visitor.module.unwrap_or(event.metadata().target().to_string());
+        let mut message = visitor.message;
+
+        for field in visitor.fields {
+            message.push(' ');
+            message.push_str(&field);
+        }
+
+        write(level, &module, &message);
+    }
+}
+
+// sets the console logger as the global tracing subscriber
+pub fn install() -> Result<(), String> {
+    let subscriber = Registry::default().with(ConsoleLayer);
+
+    return tracing::subscriber::set_global_default(subscriber)
+        .map_err(|err| err.to_string());
+}
+
+struct FieldVisitor {
+    message: String,
+    module: Option<String>,
+    fields: Vec<String>
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(self: &mut Self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = value.to_string(),
+            "module" => self.module = Some(value.to_string()),
+            name => self.fields.push(format!("{}={}", name, value))
+        };
+    }
+
+    fn record_debug(self: &mut Self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{:?}", value),
+            "module" => self.module = Some(format!("{:?}", value)),
+            name => self.fields.push(format!("{}={:?}", name, value))
+        };
+    }
+}
@@ -0,0 +1,100 @@
+use std::{net::IpAddr, time::Duration};
+#[cfg(feature = "tracing")]
+use crate::logging::LogLevel;
+use crate::http::{request::Request, response::ResponseSummary};
+
+// the span of a single request, it does nothing unless the "tracing" feature is enabled
+pub struct RequestSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span
+}
+
+impl RequestSpan {
+    #[cfg(feature = "tracing")]
+    pub fn new(client_ip: Option<IpAddr>) -> Self {
+        let client_ip = client_ip.map(|ip| ip.to_string());
+        let span = tracing::info_span!(
+            target: "quickserving",
+            "request",
+            request_id = tracing::field::Empty,
+            client_ip,
+            method = tracing::field::Empty,
+            path = tracing::field::Empty,
+            route = tracing::field::Empty,
+            status = tracing::field::Empty,
+            latency_ms = tracing::field::Empty
+        );
+
+        return Self { span };
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub fn new(_: Option<IpAddr>) -> Self {
+        return Self {};
+    }
+
+    // runs the function inside of the span, so that everything logged in it is a part of the request
+    pub fn in_scope<T>(self: &Self, f: impl FnOnce() -> T) -> T {
+        #[cfg(feature = "tracing")]
+        return self.span.in_scope(f);
+        #[cfg(not(feature = "tracing"))]
+        return f();
+    }
+
+    // the id is only known once the request headers are read
+    #[cfg(feature = "tracing")]
+    pub fn record_id(self: &Self, request_id: &str) {
+        self.span.record("request_id", request_id);
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub fn record_id(self: &Self, _: &str) {}
+
+    #[cfg(feature = "tracing")]
+    pub fn record_request(self: &Self, request: &Request) {
+        let method: String = request.method.clone().into();
+
+        self.record_id(&request.id);
+        self.span.record("method", method.as_str());
+        self.span.record("path", request.path.as_str());
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub fn record_request(self: &Self, _: &Request) {}
+
+    #[cfg(feature = "tracing")]
+    pub fn record_response(self: &Self, summary: &ResponseSummary, latency: Duration) {
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+
+        if let Some(route) = &summary.route {
+            self.span.record("route", route.as_str());
+        }
+
+        self.span.record("status", summary.status_code);
+        self.span.record("latency_ms", latency_ms);
+
+        tracing::debug!(
+            target: "quickserving",
+            parent: &self.span,
+            route = summary.route.as_deref(),
+            status = summary.status_code,
+            bytes = summary.body_bytes,
+            latency_ms,
+            "Served request."
+        );
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub fn record_response(self: &Self, _: &ResponseSummary, _: Duration) {}
+}
+
+// emits the log message as a tracing event, with the module it was logged from as a field
+#[cfg(feature = "tracing")]
+pub fn event(level: LogLevel, module: &str, message: &str) {
+    match level {
+        LogLevel::DEBUG => tracing::debug!(target: "quickserving", module, "{}", message),
+        LogLevel::INFO => tracing::info!(target: "quickserving", module, "{}", message),
+        LogLevel::WARN => tracing::warn!(target: "quickserving", module, "{}", message),
+        LogLevel::ERROR => tracing::error!(target: "quickserving", module, "{}", message)
+    };
+}
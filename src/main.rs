@@ -1,42 +1,176 @@
-use std::{fs::File, io::Read};
+use std::{env, fs, path::PathBuf, process};
 
 use quickserving_core::{
-    logging::{LogLevel, log},
-    http::server::Server,
-    config::ServerConfig
+    logging::{self, LogLevel, log},
+    http::{server::Server, shutdown::Shutdown, record::{Recorder, RecordOptions}},
+    config::{ServerConfig, CONFIG_FILE_NAMES, format::ConfigFormat, import, schema},
+    error::Error,
+    cli::{Args, Command, USAGE}
 };
 
 fn main() {
-    let config_file_result = File::options()
-        .read(true)
-        .create(false)
-        .write(false)
-        .append(false)
-        .truncate(false)
-        .open("./quickserving.json");
-    let mut config_file = match config_file_result {
-        Ok(file) => file,
-        Err(_) => {
-            log(LogLevel::ERROR, "Cannot read config file \"quickserving.json\", file not found.".to_string());
+    // with the tracing feature the library logs through tracing, so the console logger becomes its subscriber
+    #[cfg(feature = "tracing")]
+    if let Err(err) = logging::subscriber::install() {
+        log(LogLevel::WARN, format!("Cannot install the console logger as tracing subscriber. {}", err));
+    }
+
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            log(LogLevel::ERROR, err);
+            println!("{}", USAGE);
+            return;
+        }
+    };
+
+    if args.help {
+        println!("{}", USAGE);
+        return;
+    }
+
+    match args.command {
+        Command::Serve => serve(args),
+        Command::Schema => println!("{}", serde_json::to_string_pretty(&schema::schema()).unwrap()),
+        Command::Record => record(args),
+        Command::Import => import(args)
+    };
+}
+
+fn serve(args: Args) {
+    logging::set_overrides(args.logging.clone());
+
+    // when there is no config file we report the default one as missing
+    let config_path = args.config_path
+        .or_else(ServerConfig::find_file)
+        .unwrap_or(PathBuf::from(CONFIG_FILE_NAMES[0]));
+    let format = args.format.unwrap_or(ConfigFormat::from_path(&config_path));
+    let config = match ServerConfig::read_from_file(&config_path, format) {
+        Ok(config) => config,
+        Err(Error::Validation(diagnostics)) => {
+            log(LogLevel::ERROR, format!("Config file \"{}\" is invalid, found {} problem(s).", config_path.display(), diagnostics.len()));
+
+            for diagnostic in diagnostics {
+                log(LogLevel::ERROR, diagnostic.to_string());
+            }
+
+            return;
+        },
+        Err(err) => {
+            log(LogLevel::ERROR, err.to_string());
+            return;
+        }
+    };
+    logging::configure(&config.logging);
+
+    let server = Server::new(config)
+        .watch_config_file(config_path, format);
+
+    run(server);
+}
+
+fn record(args: Args) {
+    logging::set_overrides(args.logging.clone());
+
+    let upstream = match args.upstream {
+        Some(upstream) => upstream,
+        None => {
+            log(LogLevel::ERROR, "The record command needs the --upstream to forward requests to.".to_string());
+            println!("{}", USAGE);
             return;
         }
     };
-    let mut config_str = String::new();
-    let read_result = config_file.read_to_string(&mut config_str);
+    let port = args.port.unwrap_or(8080);
+    let config = match ServerConfig::try_from(format!("{{\"port\": {}, \"routes\": {{}}}}", port)) {
+        Ok(config) => config,
+        Err(err) => {
+            log(LogLevel::ERROR, err.to_string());
+            return;
+        }
+    };
+    let options = RecordOptions {
+        upstream: upstream.clone(),
+        port,
+        output: args.output.unwrap_or(PathBuf::from("recording.json")),
+        bodies: args.bodies,
+        har: args.har
+    };
+
+    log(LogLevel::INFO, format!("Recording the traffic to \"{}\" into \"{}\".", upstream, options.output.display()));
+    logging::configure(&config.logging);
+    run(Server::new(config).record(Recorder::new(options)));
+}
 
-    match read_result {
-        Ok(_) => (),
-        Err(_) => {
-            log(LogLevel::ERROR, "Cannot read config file \"quickserving.json\", insufficient permissions.".to_string());
+fn import(args: Args) {
+    let input = match args.input {
+        Some(input) => input,
+        None => {
+            log(LogLevel::ERROR, "The import command needs the file to import.".to_string());
+            println!("{}", USAGE);
             return;
         }
     };
+    let format = args.format.unwrap_or(ConfigFormat::from_path(&input));
+    let routes = match import::import_file(&input, format) {
+        Ok(routes) => routes,
+        Err(err) => {
+            log(LogLevel::ERROR, err.to_string());
+            return;
+        }
+    };
+    let config = serde_json::json!({
+        "port": args.port.unwrap_or(8080),
+        "routes": routes
+    });
+    let config = serde_json::to_string_pretty(&config).unwrap();
 
-    let config: ServerConfig = config_str.try_into().unwrap();
-    let server = Server::new(config);
-    let setup_result = server.listen();
+    // the imported config is checked the same way it will be when served
+    if let Err(err) = ServerConfig::try_from(config.clone()) {
+        log(LogLevel::WARN, format!("The imported config needs changes before it can be served. {}", err));
+    }
 
-    if setup_result.is_err() {
-        log(LogLevel::ERROR, setup_result.err().unwrap().to_string());
+    match args.output {
+        Some(output) => match fs::write(&output, config) {
+            Ok(_) => log(LogLevel::INFO, format!("Imported {} route(s) from \"{}\" into \"{}\".", routes.len(), input.display(), output.display())),
+            Err(err) => log(LogLevel::ERROR, format!("Cannot write \"{}\". {}", output.display(), err))
+        },
+        None => println!("{}", config)
+    };
+}
+
+fn run(server: Server) {
+    if let Err(err) = handle_shutdown_signals(server.shutdown()) {
+        log(LogLevel::WARN, format!("Cannot handle shutdown signals, the server will stop immediately. {}", err));
+    }
+
+    if let Err(err) = server.listen() {
+        log(LogLevel::ERROR, format!("Cannot start the server. {}", err));
     }
 }
+
+// on SIGINT or SIGTERM we drain the server before exiting, so that no request is cut off,
+// while SIGHUP is left to the config watcher
+#[cfg(unix)]
+fn handle_shutdown_signals(shutdown: Shutdown) -> Result<(), String> {
+    use std::thread;
+    use signal_hook::{consts::{SIGINT, SIGTERM}, iterator::Signals};
+
+    let mut signals = Signals::new([SIGINT, SIGTERM]).map_err(|err| err.to_string())?;
+
+    thread::spawn(move || {
+        if signals.forever().next().is_some() {
+            shutdown.drain();
+            process::exit(0);
+        }
+    });
+
+    return Ok(());
+}
+
+#[cfg(not(unix))]
+fn handle_shutdown_signals(shutdown: Shutdown) -> Result<(), String> {
+    return ctrlc::set_handler(move || {
+        shutdown.drain();
+        process::exit(0);
+    }).map_err(|err| err.to_string());
+}